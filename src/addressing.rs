@@ -0,0 +1,241 @@
+use crate::memory::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressingMode {
+    IMPLIED,
+    ACCUMULATOR,
+    IMMEDIATE,
+    ZERO_PAGE,
+    ZERO_PAGE_X,
+    ZERO_PAGE_Y,
+    ABSOLUTE,
+    ABSOLUTE_X,
+    ABSOLUTE_Y,
+    INDIRECT,
+    INDIRECT_X,
+    INDIRECT_Y,
+    RELATIVE,
+    //65C02 additions
+    ZERO_PAGE_INDIRECT,
+    ABSOLUTE_INDIRECT_X,
+    ZERO_PAGE_RELATIVE
+}
+
+//Where an instruction's operand lives once its addressing mode has been resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    NONE,
+    ACCUMULATOR,
+    IMMEDIATE(u8),
+    ADDRESS(u16)
+}
+
+//CPU state an addressing mode needs to work out its operand
+#[derive(Debug, Clone, Copy)]
+pub struct ResolveContext {
+    pub index_x: u8,
+    pub index_y: u8,
+    pub next_pc: u16,           //Address of the following instruction, which relative branches are offset from
+    pub always_fixup: bool,     //Spend the indexed fix-up cycle even when no page is crossed
+    pub fixed_indirect: bool    //65C02 JMP indirect, which carries into the pointer's high byte
+}
+
+impl AddressingMode {
+    //Number of operand bytes following the opcode
+    pub const fn num_operand_bytes(&self) -> u16 {
+        match self {
+            AddressingMode::IMPLIED | AddressingMode::ACCUMULATOR => 0,
+            AddressingMode::IMMEDIATE | AddressingMode::ZERO_PAGE | AddressingMode::ZERO_PAGE_X
+            | AddressingMode::ZERO_PAGE_Y | AddressingMode::INDIRECT_X | AddressingMode::INDIRECT_Y
+            | AddressingMode::RELATIVE | AddressingMode::ZERO_PAGE_INDIRECT => 1,
+            AddressingMode::ABSOLUTE | AddressingMode::ABSOLUTE_X | AddressingMode::ABSOLUTE_Y
+            | AddressingMode::INDIRECT | AddressingMode::ABSOLUTE_INDIRECT_X | AddressingMode::ZERO_PAGE_RELATIVE => 2
+        }
+    }
+
+    //Resolve the operand bytes of an instruction to the value or address it refers to, along with
+    //whether indexing or branching moved the address onto a different page (which costs a cycle).
+    //
+    //Every memory access the 6502 makes while working out the address goes through read, in bus
+    //order, including the dummy reads made while an index is added. Indexed writes and
+    //read-modify-write instructions always spend the extra fix-up cycle, so always_fixup forces
+    //that dummy read even when no page is crossed.
+    pub fn resolve(&self, data: &[u8], ctx: &ResolveContext,
+                   read: &mut dyn FnMut(u16) -> Result<u8, Error>) -> Result<(Operand, bool), Error> {
+        let (index_x, index_y, next_pc, always_fixup) = (ctx.index_x, ctx.index_y, ctx.next_pc, ctx.always_fixup);
+        match self {
+            AddressingMode::IMPLIED => Ok((Operand::NONE, false)),
+            AddressingMode::ACCUMULATOR => Ok((Operand::ACCUMULATOR, false)),
+            AddressingMode::IMMEDIATE => Ok((Operand::IMMEDIATE(data[0]), false)),
+            AddressingMode::ZERO_PAGE => Ok((Operand::ADDRESS(data[0] as u16), false)),
+            AddressingMode::ZERO_PAGE_X => {
+                //Zero page indexing wraps around within the zero page
+                read(data[0] as u16)?;
+                Ok((Operand::ADDRESS(data[0].wrapping_add(index_x) as u16), false))
+            },
+            AddressingMode::ZERO_PAGE_Y => {
+                read(data[0] as u16)?;
+                Ok((Operand::ADDRESS(data[0].wrapping_add(index_y) as u16), false))
+            },
+            AddressingMode::ABSOLUTE => Ok((Operand::ADDRESS(absolute(data)), false)),
+            AddressingMode::ABSOLUTE_X => indexed(absolute(data), index_x, always_fixup, read),
+            AddressingMode::ABSOLUTE_Y => indexed(absolute(data), index_y, always_fixup, read),
+            AddressingMode::INDIRECT => {
+                let ptr = absolute(data);
+                if ctx.fixed_indirect {
+                    //The 65C02 spends an extra cycle (re-reading the last operand byte) to carry properly
+                    read(next_pc.wrapping_sub(1))?;
+                    Ok((Operand::ADDRESS(read_word(ptr, read)?), false))
+                }else{
+                    //The 6502 never carries into the high byte of the pointer, so JMP ($xxFF) reads its high byte from $xx00
+                    let lo = read(ptr)?;
+                    let hi = read((ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF))?;
+                    Ok((Operand::ADDRESS((lo as u16) | ((hi as u16) << 8)), false))
+                }
+            },
+            AddressingMode::INDIRECT_X => {
+                //Both the indexed pointer and its high byte stay within the zero page
+                read(data[0] as u16)?;
+                let ptr = data[0].wrapping_add(index_x);
+                Ok((Operand::ADDRESS(read_zero_page_word(ptr, read)?), false))
+            },
+            AddressingMode::INDIRECT_Y => {
+                let base = read_zero_page_word(data[0], read)?;
+                indexed(base, index_y, always_fixup, read)
+            },
+            AddressingMode::RELATIVE => {
+                //Offset is signed and relative to the instruction following the branch
+                let target = next_pc.wrapping_add(data[0] as i8 as u16);
+                Ok((Operand::ADDRESS(target), (target & 0xFF00) != (next_pc & 0xFF00)))
+            },
+            AddressingMode::ZERO_PAGE_INDIRECT => {
+                Ok((Operand::ADDRESS(read_zero_page_word(data[0], read)?), false))
+            },
+            AddressingMode::ABSOLUTE_INDIRECT_X => {
+                //Unlike (zp,X) the indexed pointer is a full 16 bit address
+                read(next_pc.wrapping_sub(1))?;
+                let ptr = absolute(data).wrapping_add(index_x as u16);
+                Ok((Operand::ADDRESS(read_word(ptr, read)?), false))
+            },
+            AddressingMode::ZERO_PAGE_RELATIVE => {
+                //BBR/BBS test the zero page byte here; the instruction works out its branch target itself
+                Ok((Operand::ADDRESS(data[0] as u16), false))
+            }
+        }
+    }
+}
+
+fn indexed(base: u16, index: u8, always_fixup: bool, read: &mut dyn FnMut(u16) -> Result<u8, Error>) -> Result<(Operand, bool), Error> {
+    let addr = base.wrapping_add(index as u16);
+    let page_crossed = (addr & 0xFF00) != (base & 0xFF00);
+    if page_crossed || always_fixup {
+        //The low byte is added first, so the CPU reads from the un-carried address before fixing the high byte
+        read((base & 0xFF00) | (addr & 0x00FF))?;
+    }
+    Ok((Operand::ADDRESS(addr), page_crossed))
+}
+
+fn absolute(data: &[u8]) -> u16 {
+    (data[0] as u16) | ((data[1] as u16) << 8)
+}
+
+fn read_word(addr: u16, read: &mut dyn FnMut(u16) -> Result<u8, Error>) -> Result<u16, Error> {
+    let lo = read(addr)?;
+    let hi = read(addr.wrapping_add(1))?;
+    Ok((lo as u16) | ((hi as u16) << 8))
+}
+
+fn read_zero_page_word(ptr: u8, read: &mut dyn FnMut(u16) -> Result<u8, Error>) -> Result<u16, Error> {
+    let lo = read(ptr as u16)?;
+    let hi = read(ptr.wrapping_add(1) as u16)?;
+    Ok((lo as u16) | ((hi as u16) << 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Resolve mode against memory holding only the given bytes, returning the operand, whether a page
+    //was crossed, and every address read along the way
+    fn resolve(mode: AddressingMode, data: &[u8], index_x: u8, index_y: u8, bytes: &[(u16, u8)]) -> (Operand, bool, Vec<u16>) {
+        let ctx = ResolveContext { index_x, index_y, next_pc: 0x0403, always_fixup: false, fixed_indirect: false };
+        let mut reads = Vec::new();
+        let mut read = |addr: u16| -> Result<u8, Error> {
+            reads.push(addr);
+            Ok(bytes.iter().find(|(at, _)| *at == addr).map_or(0, |(_, byte)| *byte))
+        };
+        let (operand, page_crossed) = mode.resolve(data, &ctx, &mut read).unwrap();
+        (operand, page_crossed, reads)
+    }
+
+    fn address(mode: AddressingMode, data: &[u8], index_x: u8, index_y: u8, bytes: &[(u16, u8)]) -> (Operand, bool) {
+        let (operand, page_crossed, _) = resolve(mode, data, index_x, index_y, bytes);
+        (operand, page_crossed)
+    }
+
+    #[test]
+    fn direct_modes() {
+        assert_eq!(address(AddressingMode::IMPLIED, &[], 0, 0, &[]), (Operand::NONE, false));
+        assert_eq!(address(AddressingMode::ACCUMULATOR, &[], 0, 0, &[]), (Operand::ACCUMULATOR, false));
+        assert_eq!(address(AddressingMode::IMMEDIATE, &[0x42], 0, 0, &[]), (Operand::IMMEDIATE(0x42), false));
+        assert_eq!(address(AddressingMode::ZERO_PAGE, &[0x80], 0, 0, &[]), (Operand::ADDRESS(0x0080), false));
+        assert_eq!(address(AddressingMode::ABSOLUTE, &[0x34, 0x12], 0, 0, &[]), (Operand::ADDRESS(0x1234), false));
+    }
+
+    #[test]
+    fn zero_page_indexed_wraps() {
+        assert_eq!(address(AddressingMode::ZERO_PAGE_X, &[0x80], 0x10, 0, &[]), (Operand::ADDRESS(0x0090), false));
+        assert_eq!(address(AddressingMode::ZERO_PAGE_X, &[0xF0], 0x20, 0, &[]), (Operand::ADDRESS(0x0010), false));
+        assert_eq!(address(AddressingMode::ZERO_PAGE_Y, &[0xFF], 0, 0x01, &[]), (Operand::ADDRESS(0x0000), false));
+        //The base is read while the index is added
+        assert_eq!(resolve(AddressingMode::ZERO_PAGE_X, &[0xF0], 0x20, 0, &[]).2, vec![0x00F0]);
+    }
+
+    #[test]
+    fn absolute_indexed_page_cross() {
+        assert_eq!(address(AddressingMode::ABSOLUTE_X, &[0x10, 0x12], 0x05, 0, &[]), (Operand::ADDRESS(0x1215), false));
+        assert_eq!(address(AddressingMode::ABSOLUTE_Y, &[0x10, 0x12], 0, 0x05, &[]), (Operand::ADDRESS(0x1215), false));
+        assert_eq!(address(AddressingMode::ABSOLUTE_X, &[0xFF, 0x12], 0x01, 0, &[]), (Operand::ADDRESS(0x1300), true));
+        assert_eq!(address(AddressingMode::ABSOLUTE_Y, &[0xF0, 0xFF], 0, 0x20, &[]), (Operand::ADDRESS(0x0010), true));
+        //Crossing a page makes a dummy read of the un-carried address
+        assert_eq!(resolve(AddressingMode::ABSOLUTE_X, &[0xFF, 0x12], 0x01, 0, &[]).2, vec![0x1200]);
+        assert_eq!(resolve(AddressingMode::ABSOLUTE_X, &[0x10, 0x12], 0x01, 0, &[]).2, Vec::<u16>::new());
+    }
+
+    #[test]
+    fn indirect_x_wraps_in_zero_page() {
+        let bytes = [(0x0024, 0x74), (0x0025, 0x20), (0x00FF, 0x00), (0x0000, 0x30)];
+        assert_eq!(address(AddressingMode::INDIRECT_X, &[0x20], 0x04, 0, &bytes), (Operand::ADDRESS(0x2074), false));
+        //The indexed pointer wraps, and so does its high byte when the pointer is $FF
+        assert_eq!(address(AddressingMode::INDIRECT_X, &[0xFB], 0x29, 0, &bytes), (Operand::ADDRESS(0x2074), false));
+        assert_eq!(resolve(AddressingMode::INDIRECT_X, &[0xFE], 0x01, 0, &bytes).2, vec![0x00FE, 0x00FF, 0x0000]);
+        assert_eq!(address(AddressingMode::INDIRECT_X, &[0xFE], 0x01, 0, &bytes), (Operand::ADDRESS(0x3000), false));
+    }
+
+    #[test]
+    fn indirect_y_wraps_in_zero_page() {
+        let bytes = [(0x0086, 0x28), (0x0087, 0x40), (0x00FF, 0xF0), (0x0000, 0x12)];
+        assert_eq!(address(AddressingMode::INDIRECT_Y, &[0x86], 0, 0x10, &bytes), (Operand::ADDRESS(0x4038), false));
+        assert_eq!(address(AddressingMode::INDIRECT_Y, &[0x86], 0, 0xE0, &bytes), (Operand::ADDRESS(0x4108), true));
+        //The pointer's high byte comes from $00, not $0100
+        assert_eq!(resolve(AddressingMode::INDIRECT_Y, &[0xFF], 0, 0x20, &bytes).2, vec![0x00FF, 0x0000, 0x1210]);
+        assert_eq!(address(AddressingMode::INDIRECT_Y, &[0xFF], 0, 0x20, &bytes), (Operand::ADDRESS(0x1310), true));
+    }
+
+    #[test]
+    fn indirect_jump_page_bug() {
+        let bytes = [(0x1234, 0x78), (0x1235, 0x56), (0x10FF, 0x34), (0x1000, 0x12), (0x1100, 0x99)];
+        assert_eq!(address(AddressingMode::INDIRECT, &[0x34, 0x12], 0, 0, &bytes), (Operand::ADDRESS(0x5678), false));
+        //JMP ($10FF) takes its high byte from $1000 rather than $1100
+        assert_eq!(address(AddressingMode::INDIRECT, &[0xFF, 0x10], 0, 0, &bytes), (Operand::ADDRESS(0x1234), false));
+    }
+
+    #[test]
+    fn relative_page_cross() {
+        //Offsets are from next_pc, $0403
+        assert_eq!(address(AddressingMode::RELATIVE, &[0x10], 0, 0, &[]), (Operand::ADDRESS(0x0413), false));
+        assert_eq!(address(AddressingMode::RELATIVE, &[0xFD], 0, 0, &[]), (Operand::ADDRESS(0x0400), false));
+        assert_eq!(address(AddressingMode::RELATIVE, &[0x80], 0, 0, &[]), (Operand::ADDRESS(0x0383), true));
+        assert_eq!(address(AddressingMode::RELATIVE, &[0x7F], 0, 0, &[]), (Operand::ADDRESS(0x0482), false));
+    }
+}
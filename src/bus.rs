@@ -0,0 +1,79 @@
+use crate::memory::Error;
+
+//Which CPU accesses a watchpoint fires on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    READ,           //Includes instruction fetches, but not reads the CPU throws away
+    WRITE,
+    ACCESS          //Either
+}
+
+//A watchpoint firing, as reported by Bus::take_watch_hit. kind is READ or WRITE, whichever the
+//access was.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub pc: u16,    //Instruction that made the access
+    pub addr: u16,
+    pub value: u8,
+    pub kind: WatchKind
+}
+
+//Everything the CPU is attached to. Memory implements this for a flat 64K of RAM; implement it
+//yourself to put the CPU in front of custom address decoders, devices or test fixtures.
+//
+//The CPU makes exactly one read or write per clock cycle, and calls tick after each one.
+pub trait Bus {
+    //A read by the CPU, which may have side effects on devices (e.g. acknowledging an interrupt)
+    fn read(&mut self, addr: u16) -> Result<u8, Error>;
+
+    fn write(&mut self, addr: u16, data: u8) -> Result<u8, Error>;
+
+    //Read without any side effects, for debuggers, disassemblers and trace output
+    fn peek(&self, addr: u16) -> Result<u8, Error>;
+
+    //Peek a run of bytes, wrapping around at the top of the address space
+    fn peek_n_bytes(&self, addr: u16, size: usize) -> Result<Vec<u8>, Error> {
+        (0..size).map(|i| self.peek(addr.wrapping_add(i as u16))).collect()
+    }
+
+    //A read of an opcode or operand byte from the instruction stream. Defaults to a normal read.
+    fn fetch(&mut self, addr: u16) -> Result<u8, Error> {
+        self.read(addr)
+    }
+
+    //A read whose value the CPU throws away, e.g. of the byte after a one byte instruction. Devices
+    //still see these on real hardware, so it defaults to a normal read.
+    fn dummy_read(&mut self, addr: u16) -> Result<u8, Error> {
+        self.read(addr)
+    }
+
+    //Called as the CPU starts each instruction or interrupt sequence, so the bus knows which
+    //instruction makes the accesses that follow. Running a cycle at a time calls it again for every
+    //cycle of the same instruction.
+    fn begin_instruction(&mut self, _pc: u16) {}
+
+    //The first watchpoint hit since this was last called, for buses that support watchpoints
+    fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        None
+    }
+
+    //Called once per CPU clock cycle, after that cycle's bus access, so devices can keep in step
+    fn tick(&mut self) {}
+
+    //Whether something on the bus is holding the IRQ line low. The CPU samples this whenever it
+    //polls for interrupts, in addition to CPU::raise_irq.
+    fn irq(&self) -> bool {
+        false
+    }
+}
+
+//Everything the 65816 is attached to, addressed with 24 bits: the bank in the top 8 bits and the
+//offset within the bank below. ExtendedMemory implements this for a flat 16MB of RAM.
+pub trait Bus24 {
+    fn read(&mut self, addr: u32) -> Result<u8, Error>;
+
+    fn write(&mut self, addr: u32, data: u8) -> Result<u8, Error>;
+
+    //Read without any side effects, for debuggers, disassemblers and trace output
+    fn peek(&self, addr: u32) -> Result<u8, Error>;
+}
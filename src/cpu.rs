@@ -1,66 +1,183 @@
-use crate::memory::Memory;
+use crate::addressing::{AddressingMode, Operand, ResolveContext};
+use crate::log;
+use crate::logging::{Level, Subsystem};
+use crate::bus::Bus;
+use crate::memory::Error as MemoryError;
+use crate::opcodes::{self, InstructionTypes, OpcodeInfo};
+use crate::status::StatusFlags;
+use crate::trace::{TraceLine, TraceWriter};
+use std::cell::RefCell;
+use std::rc::Rc;
 
-#[derive(Debug)]
-enum InstructionTypes {
-    BRK,
-    LDA_IMMEDIATE,
-    STA_ABSOLUTE,
-    ASL_ACCUMULATOR,
-    ADC_IMMEDIATE,
-    ADC_ABSOLUTE,
-    ROL_IMMEDIATE,
-    CLEAR_CARRY,
-    RTS
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_BRK_VECTOR: u16 = 0xFFFE;
+const INTERRUPT_CYCLES: u16 = 7;
+const STACK_BASE: u16 = 0x0100;      //The stack always lives in page one
+
+//What a successful call to step executed. Addresses are 24 bits wide on the 65816, where they
+//include the program bank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepOutcome {
+    INSTRUCTION { pc: u32, opcode: u8, cycles: u16 },
+    INTERRUPT { vector: u16, cycles: u16 },
+    IDLE            //One cycle spent waiting in WAI for an interrupt
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuErrorKind {
+    FETCH_FAILED(MemoryError),          //The opcode or its operand bytes could not be read
+    UNKNOWN_OPCODE,                     //The opcode is not part of the instruction set being decoded
+    READ_FAILED(u32, MemoryError),      //A read made while executing failed at the given address
+    WRITE_FAILED(u32, MemoryError)      //A write made while executing failed at the given address
+}
+
+//Why the CPU could not carry on executing. The instruction or interrupt sequence that faulted has
+//still run to completion, with failed reads returning 0 and failed writes discarded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuError {
+    pub pc: u32,                //Address of the instruction that faulted, including the program bank on the 65816
+    pub opcode: Option<u8>,     //None if the opcode could not be read, or the fault came from an interrupt sequence
+    pub kind: CpuErrorKind
+}
+
+impl CpuError {
+    //The memory error underneath this fault, if there was one
+    pub fn memory_error(&self) -> Option<&MemoryError> {
+        match &self.kind {
+            CpuErrorKind::FETCH_FAILED(error) | CpuErrorKind::READ_FAILED(_, error) | CpuErrorKind::WRITE_FAILED(_, error) => Some(error),
+            CpuErrorKind::UNKNOWN_OPCODE => None
+        }
+    }
+}
+
+impl std::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PC {:#06x}", self.pc)?;
+        if let Some(opcode) = self.opcode {
+            write!(f, " opcode {:#04x}", opcode)?;
+        }
+        match &self.kind {
+            CpuErrorKind::FETCH_FAILED(error) => write!(f, ": failed to fetch instruction ({:?})", error),
+            CpuErrorKind::UNKNOWN_OPCODE => write!(f, ": unknown opcode"),
+            CpuErrorKind::READ_FAILED(addr, error) => write!(f, ": read from {:#06x} failed ({:?})", addr, error),
+            CpuErrorKind::WRITE_FAILED(addr, error) => write!(f, ": write to {:#06x} failed ({:?})", addr, error)
+        }
+    }
 }
 
-enum Error {
-    FETCH_ERROR,
-    FETCH_ERROR_UNKNOWN_INST
+//Which member of the 6502 family is being emulated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    NMOS_6502,
+    WDC_65C02,
+    RICOH_2A03      //NES CPU: an NMOS 6502 with the decimal mode circuitry disconnected
 }
 
+#[derive(Clone)]
 pub struct CPU {
     pub reg_pc: u16,            //Program counter
     pub reg_sp: u8,             //Stack pointer
     pub reg_accum: u8,          //Accumulator
     pub reg_index_x: u8,        //Index Register X
     pub reg_index_y: u8,        //Index Register Y
-    pub reg_ps_cf: u8,          //Processor Status carry flag
-    pub reg_ps_zf: u8,          //Processor Status zero flag
-    pub reg_ps_id: u8,          //Processor Status interrupt disable
-    pub reg_ps_dm: u8,          //Processor Status decimal mode
-    pub reg_ps_bc: u8,          //Processor Status break command
-    pub reg_ps_of: u8,          //Processor Status overflow flag
-    pub reg_ps_nf: u8,          //Processor Status negative flag
-    pub reg_ps_un: u8,          //Processor Status unused flag
+    pub reg_ps: StatusFlags,    //Processor Status
+    variant: Variant,           //Instruction set and quirks being emulated
     do_halt: bool,              //To halt or not
-    total_cycles: u64           //Total number of cycles ran
+    waiting: bool,              //Stopped by WAI until an interrupt line is asserted
+    break_hit: bool,            //Last instruction executed was a BRK
+    undocumented_opcodes: bool, //Decode the undocumented NMOS opcodes instead of rejecting them
+    nmi_pending: bool,          //NMI edge seen but not yet serviced
+    irq_line: bool,             //IRQ line is being held low by a device
+    bus_irq: bool,              //IRQ line as last sampled from the bus with Bus::irq
+    total_cycles: u64,          //Total number of cycles ran
+    interrupt_latched: Option<u16>,     //Interrupt vector polled before the last cycle of the previous instruction
+    tick_interrupt: Option<u16>,        //Interrupt sequence being run one cycle at a time, if any
+    tick_snapshot: Option<Box<CPU>>,    //State at the start of the instruction being run one cycle at a time
+    cycle_replay: CycleReplay,
+    bus_fault: Option<CpuErrorKind>,    //First failed bus access made by the current instruction
+    tracer: Option<Rc<RefCell<TraceWriter>>>    //Shared with tick snapshots, which are only temporary copies
+}
+
+//Why the CPU is reading, so the bus can treat the instruction stream and thrown away reads differently
+#[derive(Clone, Copy, PartialEq)]
+enum ReadKind {
+    DATA,
+    FETCH,      //Opcode and operand bytes
+    DUMMY       //Reads made only because the bus is busy every cycle
+}
+
+//Bus accesses already made by the instruction being run one cycle at a time. Each tick re-runs the
+//instruction from its starting state: accesses in the log are answered from it without touching
+//memory again, the next one is performed for real, and any after that are left for later ticks.
+//
+//Re-running the prefix makes an instruction of n cycles cost O(n^2) executions of its logic. This is
+//deliberate: execute stays straight-line code shared with step, rather than every instruction being
+//rewritten as a resumable state machine, and n is at most 8 so the extra work is bounded.
+#[derive(Clone, Default)]
+struct CycleReplay {
+    active: bool,
+    log: Vec<Result<u8, MemoryError>>,
+    replay_len: usize,      //Accesses made on earlier ticks, which this run must not repeat
+    position: usize
+}
+
+//Registers as they were before an instruction ran, for the trace writer
+#[derive(Clone, Copy)]
+struct RegisterState {
+    accum: u8,
+    index_x: u8,
+    index_y: u8,
+    status: u8,
+    sp: u8,
+    cycles: u64
 }
 
 #[derive(Debug)]
 struct Instruction {
+    opcode: u8,
     inst: InstructionTypes,
+    mode: AddressingMode,
     data: Vec<u8>,
-    num_cycles: u16
+    num_bytes: u16,
+    num_cycles: u16,
+    page_cross_penalty: bool
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new()
+    }
 }
 
 impl CPU {
     pub fn new() -> CPU {
+        CPU::with_variant(Variant::NMOS_6502)
+    }
+
+    pub fn with_variant(variant: Variant) -> CPU {
         CPU {
             reg_pc: 0,
             reg_sp: 0xFF,
             reg_accum: 0,
             reg_index_x: 0,
             reg_index_y: 0,
-            reg_ps_cf: 0,
-            reg_ps_zf: 1,
-            reg_ps_id: 1,
-            reg_ps_dm: 0,
-            reg_ps_bc: 0,
-            reg_ps_of: 0,
-            reg_ps_nf: 0,
-            reg_ps_un: 1,
+            reg_ps: StatusFlags::new(),
+            variant,
             do_halt: false,
-            total_cycles: 0
+            waiting: false,
+            break_hit: false,
+            undocumented_opcodes: false,
+            nmi_pending: false,
+            irq_line: false,
+            bus_irq: false,
+            total_cycles: 0,
+            interrupt_latched: None,
+            tick_interrupt: None,
+            tick_snapshot: None,
+            cycle_replay: CycleReplay::default(),
+            bus_fault: None,
+            tracer: None
         }
     }
 
@@ -72,193 +189,1911 @@ impl CPU {
         self.do_halt = true;
     }
 
-    pub fn get_status_reg_byte(&self) -> u8 {
-        ((self.reg_ps_nf & 0x1) << 7) | ((self.reg_ps_of & 0x1) << 6) | ((self.reg_ps_un & 0x1) << 5) | ((self.reg_ps_bc & 0x1) << 4)
-        | ((self.reg_ps_dm & 0x1) << 3) | ((self.reg_ps_id & 0x1) << 2) | ((self.reg_ps_zf & 0x1) << 1) 
-        | ((self.reg_ps_cf & 0x1)) 
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    //Allow programs to use the undocumented NMOS opcodes (LAX, SAX, DCP, the multi-byte NOPs, JAM etc.)
+    pub fn set_undocumented_opcodes(&mut self, enabled: bool) {
+        self.undocumented_opcodes = enabled;
+    }
+
+    //Look up an opcode in the instruction set this CPU is configured to decode
+    pub fn decode(&self, opcode: u8) -> Option<&'static OpcodeInfo> {
+        if self.variant == Variant::WDC_65C02 {
+            opcodes::lookup_65c02(opcode)
+        }else if let Some(info) = opcodes::lookup(opcode) {
+            Some(info)
+        }else if self.undocumented_opcodes {
+            opcodes::lookup_undocumented(opcode)
+        }else{
+            None
+        }
+    }
+
+    //Write a nestest.log style line for every instruction executed from now on, or stop tracing with None
+    pub fn set_trace_writer(&mut self, writer: Option<TraceWriter>) {
+        self.tracer = writer.map(|writer| Rc::new(RefCell::new(writer)));
+    }
+
+    //Whether the most recently executed instruction was a BRK, which the debugger treats as a breakpoint
+    pub fn check_break(&self) -> bool {
+        self.break_hit
+    }
+
+    //Run the reset sequence: the 6502 performs three suppressed stack pushes, disables interrupts
+    //and loads the program counter from the reset vector
+    pub fn reset<B: Bus>(&mut self, mem: &mut B) {
+        self.tick_snapshot = None;
+        self.cycle_replay = CycleReplay::default();
+        self.interrupt_latched = None;
+        self.dummy_read(mem, self.reg_pc);
+        self.dummy_read(mem, self.reg_pc);
+        for _ in 0..3 {
+            self.dummy_read(mem, self.stack_addr());
+            self.reg_sp = self.reg_sp.wrapping_sub(1);
+        }
+        self.reg_ps.interrupt_disable = true;
+        self.break_hit = false;
+        self.nmi_pending = false;
+        self.do_halt = false;
+        self.waiting = false;
+        self.reg_pc = self.read_vector(mem, RESET_VECTOR);
+        self.total_cycles += INTERRUPT_CYCLES as u64;
+    }
+
+    //NMI is edge triggered, so each call is serviced exactly once regardless of the interrupt disable flag
+    pub fn raise_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    //IRQ is level triggered and stays asserted until the device releases it with clear_irq
+    pub fn raise_irq(&mut self) {
+        self.irq_line = true;
+    }
+
+    pub fn clear_irq(&mut self) {
+        self.irq_line = false;
+    }
+
+    fn read_vector<B: Bus>(&mut self, mem: &mut B, vector: u16) -> u16 {
+        let lo = self.bus_read(mem, vector, ReadKind::DATA).unwrap_or(0);
+        let hi = self.bus_read(mem, vector.wrapping_add(1), ReadKind::DATA).unwrap_or(0);
+        (lo as u16) | ((hi as u16) << 8)
     }
 
-    fn update_status_regs(&mut self, res: u8, res_wider_carry_check: u16) 
-    {
-        //Reset BRK flag if it was 1 - reg_ps_bc
-        if self.reg_ps_bc == 1 {
-            self.reg_ps_bc = 0;
+    //Push the return address and status, then jump through the given vector. The break flag is
+    //only set in the pushed status when the interrupt came from a BRK instruction.
+    fn interrupt<B: Bus>(&mut self, mem: &mut B, return_addr: u16, vector: u16, from_brk: bool) {
+        self.break_hit = from_brk;
+        let status = self.reg_ps.to_byte(from_brk);
+        self.push_word(mem, return_addr);
+        self.push_byte(mem, status);
+        self.reg_ps.interrupt_disable = true;
+        if self.variant == Variant::WDC_65C02 {
+            //The 65C02 also clears decimal mode so handlers start in binary arithmetic
+            self.reg_ps.decimal = false;
         }
+        self.reg_pc = self.read_vector(mem, vector);
+    }
 
-        //Set negative flag - reg_ps_nf
-        //If high bit set, then negative flag is set
-        if (res & 0x80) == 0x80 {
-            self.reg_ps_nf = 1;
+    //WAI leaves the CPU idle, one cycle at a time, until an interrupt line is asserted. An IRQ
+    //wakes it even while interrupts are disabled, in which case execution simply carries on.
+    //Returns true if this cycle was spent idle, which still clocks the bus so devices can raise an interrupt.
+    fn idle_cycle<B: Bus>(&mut self, mem: &mut B) -> bool {
+        if !self.waiting {
+            false
+        }else if self.nmi_pending || self.irq_asserted() {
+            self.waiting = false;
+            self.interrupt_latched = self.pending_interrupt();
+            false
         }else{
-            self.reg_ps_nf = 0;
+            self.total_cycles += 1;
+            mem.tick();
+            true
         }
+    }
+
+    //IRQ is asserted either by raise_irq or by a device on the bus
+    fn irq_asserted(&self) -> bool {
+        self.irq_line || self.bus_irq
+    }
 
-        //Set zero flag - reg_ps_zf
-        //If res is 0, then flag is 1, otherwise it is 0
-        if res == 0 {
-            self.reg_ps_zf = 1;
+    //The interrupt that would be taken if the CPU polled right now: a pending NMI, or an asserted IRQ if interrupts are enabled
+    fn pending_interrupt(&self) -> Option<u16> {
+        if self.nmi_pending {
+            Some(NMI_VECTOR)
+        }else if self.irq_asserted() && !self.reg_ps.interrupt_disable {
+            Some(IRQ_BRK_VECTOR)
         }else{
-            self.reg_ps_zf = 0;
+            None
         }
+    }
+
+    //Begin servicing an interrupt, consuming the NMI edge if that is what is being taken
+    fn take_interrupt(&mut self, vector: u16) {
+        if vector == NMI_VECTOR {
+            self.nmi_pending = false;
+        }
+    }
+
+    fn service_interrupt<B: Bus>(&mut self, mem: &mut B, vector: u16) {
+        //The opcode fetch is replaced by two dummy reads of the interrupted instruction
+        self.dummy_read(mem, self.reg_pc);
+        self.dummy_read(mem, self.reg_pc);
+        self.interrupt(mem, self.reg_pc, vector, false);
+    }
+
+    fn log_interrupt(&self, vector: u16) {
+        log!(Subsystem::CPU, Level::DEBUG, "Interrupt: {} - Cycles {} - Total Cycles {}", if vector == NMI_VECTOR { "NMI" } else { "IRQ" }, INTERRUPT_CYCLES, self.total_cycles);
+    }
 
-        //Set carry flag if anything is in any bits wider that 0-7
-        if ((res_wider_carry_check & 0xFF00) >> 8) as u8 > 0 {
-            self.reg_ps_cf = 1;
+    //Status as it would be pushed by a hardware interrupt, so the break bit is always clear
+    pub fn get_status_reg_byte(&self) -> u8 {
+        self.reg_ps.to_byte(false)
+    }
+
+    //Answer a bus access from the replay log if it was already performed on an earlier tick, or
+    //suppress it if it belongs to a later cycle. None means the access must go to memory now.
+    fn replayed_access(&mut self) -> Option<Result<u8, MemoryError>> {
+        if !self.cycle_replay.active {
+            return None;
+        }
+        let index = self.cycle_replay.position;
+        self.cycle_replay.position += 1;
+        if index < self.cycle_replay.replay_len {
+            Some(self.cycle_replay.log[index].clone())
+        }else if index == self.cycle_replay.replay_len {
+            None
         }else{
-            self.reg_ps_cf = 0;
+            Some(Ok(0))
         }
     }
 
-    fn fetch(&self, mem: &Memory) -> Result<Instruction, Error> {
-        if let Ok(inst) = mem.read_byte(self.reg_pc, true) {
-            match inst {
-                0x0 => {
-                    //1 bytes total
-                    Ok(Instruction { inst: InstructionTypes::BRK, data: vec![inst], num_cycles: 7 })
-                }
-                0xA9 => {
-                    // 2 bytes total
-                    if let Ok(data_bytes) = mem.read_n_bytes(self.reg_pc + 1, 1, true) {
-                        Ok(Instruction { inst: InstructionTypes::LDA_IMMEDIATE, data: data_bytes, num_cycles: 2 })
-                    }else{
-                        Err(Error::FETCH_ERROR)
-                    }
-                },
-                0x8D => {
-                    // 3 bytes total
-                    if let Ok(data_bytes) = mem.read_n_bytes(self.reg_pc + 1, 2, true) {
-                        Ok(Instruction { inst: InstructionTypes::STA_ABSOLUTE, data: data_bytes, num_cycles: 4 })
-                    }else{
-                        Err(Error::FETCH_ERROR)
-                    }
-                },
-                0x0A => {
-                    // 1 bytes total
-                    Ok(Instruction { inst: InstructionTypes::ASL_ACCUMULATOR, data: vec![inst], num_cycles: 2 })
-                },
-                0x69 => {
-                    // 2 bytes total
-                    if let Ok(data_bytes) = mem.read_n_bytes(self.reg_pc + 1, 1, true) {
-                        Ok(Instruction { inst: InstructionTypes::ADC_IMMEDIATE, data: data_bytes, num_cycles: 2 })
-                    }else{
-                        Err(Error::FETCH_ERROR)
-                    }
-                },
-                0x2A => {
-                    // 1 bytes total
-                    Ok(Instruction { inst: InstructionTypes::ROL_IMMEDIATE, data: vec![inst], num_cycles: 2 })
-                },
-                0x18 => {
-                    // 1 bytes total
-                    Ok(Instruction { inst: InstructionTypes::CLEAR_CARRY, data: vec![inst], num_cycles: 2 })
-                }
-                0x6D => {
-                    // 3 bytes total
-                    if let Ok(data_bytes) = mem.read_n_bytes(self.reg_pc + 1, 2, true) {
-                        Ok(Instruction { inst: InstructionTypes::ADC_ABSOLUTE, data: data_bytes, num_cycles: 4 })
+    fn record_access(&mut self, res: &Result<u8, MemoryError>) {
+        if self.cycle_replay.active {
+            self.cycle_replay.log.push(res.clone());
+        }
+    }
+
+    //Remember the first failed access so it can be reported once the instruction has finished
+    fn record_fault(&mut self, res: &Result<u8, MemoryError>, fault: impl FnOnce(MemoryError) -> CpuErrorKind) {
+        if let Err(error) = res {
+            if self.bus_fault.is_none() {
+                self.bus_fault = Some(fault(error.clone()));
+            }
+        }
+    }
+
+    fn take_fault(&mut self, pc: u16, opcode: Option<u8>) -> Result<(), CpuError> {
+        if let Some(kind) = self.bus_fault.take() {
+            Err(CpuError { pc: pc as u32, opcode, kind })
+        }else{
+            Ok(())
+        }
+    }
+
+    //Every memory access the CPU makes goes through bus_read, bus_write, bus_push or bus_pull so
+    //that each one is exactly one clock cycle
+    fn bus_read<B: Bus>(&mut self, mem: &mut B, addr: u16, kind: ReadKind) -> Result<u8, MemoryError> {
+        let res = if let Some(res) = self.replayed_access() {
+            res
+        }else{
+            let res = match kind {
+                ReadKind::DATA => mem.read(addr),
+                ReadKind::FETCH => mem.fetch(addr),
+                ReadKind::DUMMY => mem.dummy_read(addr)
+            };
+            self.record_access(&res);
+            mem.tick();
+            res
+        };
+        self.record_fault(&res, |error| CpuErrorKind::READ_FAILED(addr as u32, error));
+        res
+    }
+
+    fn bus_write<B: Bus>(&mut self, mem: &mut B, addr: u16, data: u8) -> Result<u8, MemoryError> {
+        let res = if let Some(res) = self.replayed_access() {
+            res
+        }else{
+            let res = mem.write(addr, data);
+            self.record_access(&res);
+            mem.tick();
+            res
+        };
+        self.record_fault(&res, |error| CpuErrorKind::WRITE_FAILED(addr as u32, error));
+        res
+    }
+
+    //Address of the stack slot SP points at. SP is only 8 bits wide, so pushing past $0100 or pulling
+    //past $01FF wraps around within page one just like the real hardware.
+    fn stack_addr(&self) -> u16 {
+        STACK_BASE | self.reg_sp as u16
+    }
+
+    //Pushes write to the free slot SP points at and then decrement it
+    fn bus_push<B: Bus>(&mut self, mem: &mut B, data: u8) -> Result<u8, MemoryError> {
+        let res = self.bus_write(mem, self.stack_addr(), data);
+        self.reg_sp = self.reg_sp.wrapping_sub(1);
+        res
+    }
+
+    //Pulls increment SP first, then read the most recently pushed byte
+    fn bus_pull<B: Bus>(&mut self, mem: &mut B) -> Result<u8, MemoryError> {
+        self.reg_sp = self.reg_sp.wrapping_add(1);
+        self.bus_read(mem, self.stack_addr(), ReadKind::DATA)
+    }
+
+    //A read the 6502 makes only because the bus is busy every cycle; the value is thrown away
+    fn dummy_read<B: Bus>(&mut self, mem: &mut B, addr: u16) {
+        let _ = self.bus_read(mem, addr, ReadKind::DUMMY);
+    }
+
+    fn fetch<B: Bus>(&mut self, mem: &mut B) -> Result<Instruction, CpuError> {
+        let inst_pc = self.reg_pc;
+        let fetch_failed = |opcode, error| CpuError { pc: inst_pc as u32, opcode, kind: CpuErrorKind::FETCH_FAILED(error) };
+        match self.bus_read(mem, self.reg_pc, ReadKind::FETCH) {
+            Ok(inst) => {
+                if let Some(info) = self.decode(inst) {
+                    let mut data_bytes: Vec<u8> = Vec::new();
+                    if info.num_bytes == 1 {
+                        //Single byte instructions still read the byte after the opcode, apart from
+                        //the 65C02's one cycle NOPs
+                        if info.base_cycles > 1 {
+                            self.dummy_read(mem, self.reg_pc.wrapping_add(1));
+                        }
                     }else{
-                        Err(Error::FETCH_ERROR)
+                        //JSR reads the high byte of its target only after pushing the return address
+                        let num_operand_bytes = if info.inst == InstructionTypes::JSR { 1 } else { info.num_bytes - 1 };
+                        for i in 0..num_operand_bytes {
+                            match self.bus_read(mem, self.reg_pc.wrapping_add(1 + i), ReadKind::FETCH) {
+                                Ok(byte) => data_bytes.push(byte),
+                                Err(error) => return Err(fetch_failed(Some(inst), error))
+                            }
+                        }
                     }
-                },
-                0x60 => {
-                    // 1 bytes total
-                    Ok(Instruction { inst: InstructionTypes::RTS, data: vec![inst], num_cycles: 6 })
-                }
-                _ => {
-                    println!("CPU> Unknown instruction. Opcode: {:#04x}", inst);
-                    Err(Error::FETCH_ERROR_UNKNOWN_INST)
+                    Ok(Instruction { opcode: inst, inst: info.inst, mode: info.mode, data: data_bytes, num_bytes: info.num_bytes, num_cycles: info.base_cycles, page_cross_penalty: info.page_cross_penalty })
+                }else{
+                    Err(CpuError { pc: inst_pc as u32, opcode: Some(inst), kind: CpuErrorKind::UNKNOWN_OPCODE })
                 }
+            },
+            Err(error) => Err(fetch_failed(None, error))
+        }
+    }
+
+    fn resolve_operand<B: Bus>(&mut self, inst: &Instruction, mem: &mut B) -> (Operand, bool) {
+        //PC has already been advanced past the instruction by the time this is called. Writes always
+        //spend the fix-up cycle, except the 65C02 shifts which only do so when crossing a page.
+        let ctx = ResolveContext {
+            index_x: self.reg_index_x,
+            index_y: self.reg_index_y,
+            next_pc: self.reg_pc,
+            always_fixup: inst.inst.writes_operand() && !inst.page_cross_penalty,
+            fixed_indirect: self.variant == Variant::WDC_65C02
+        };
+        //A failed pointer read has already been recorded as the instruction's fault
+        let mut read = |addr: u16| self.bus_read(mem, addr, ReadKind::DATA);
+        inst.mode.resolve(&inst.data, &ctx, &mut read).unwrap_or((Operand::NONE, false))
+    }
+
+    fn read_operand<B: Bus>(&mut self, operand: Operand, mem: &mut B) -> u8 {
+        match operand {
+            Operand::ACCUMULATOR => self.reg_accum,
+            Operand::IMMEDIATE(value) => value,
+            Operand::ADDRESS(addr) => self.bus_read(mem, addr, ReadKind::DATA).unwrap_or(0),
+            Operand::NONE => 0
+        }
+    }
+
+    fn write_operand<B: Bus>(&mut self, operand: Operand, mem: &mut B, data: u8) {
+        match operand {
+            Operand::ACCUMULATOR => {
+                self.reg_accum = data;
+            },
+            Operand::ADDRESS(addr) => {
+                let _ = self.bus_write(mem, addr, data);
+            },
+            _ => {
+                log!(Subsystem::CPU, Level::WARN, "Operand {:?} is not writable", operand);
+            }
+        }
+    }
+
+    //Read-modify-write instructions write the unmodified value back before writing the result. The
+    //65C02 reads it a second time instead.
+    fn read_modify_write<B: Bus>(&mut self, operand: Operand, mem: &mut B, modify: fn(&mut CPU, u8) -> u8) {
+        let value = self.read_operand(operand, mem);
+        if let Operand::ADDRESS(addr) = operand {
+            if self.variant == Variant::WDC_65C02 {
+                self.dummy_read(mem, addr);
+            }else{
+                self.write_operand(operand, mem, value);
             }
+        }
+        let res = modify(self, value);
+        self.write_operand(operand, mem, res);
+    }
+
+    fn operand_address(&self, operand: Operand) -> u16 {
+        if let Operand::ADDRESS(addr) = operand {
+            addr
         }else{
-            Err(Error::FETCH_ERROR)
+            0
         }
     }
 
-    fn execute(&mut self, inst: &Instruction, mem: &mut Memory) {
-        self.total_cycles += inst.num_cycles as u64;
+    //A failed push or pull has already been recorded as the instruction's fault, so the result can be dropped
+    fn push_byte<B: Bus>(&mut self, mem: &mut B, data: u8) {
+        let _ = self.bus_push(mem, data);
+    }
+
+    //Words go on high byte first, so they end up little endian in memory
+    fn push_word<B: Bus>(&mut self, mem: &mut B, data: u16) {
+        self.push_byte(mem, (data >> 8) as u8);
+        self.push_byte(mem, data as u8);
+    }
+
+    fn pull_byte<B: Bus>(&mut self, mem: &mut B) -> u8 {
+        self.bus_pull(mem).unwrap_or(0)
+    }
+
+    fn pull_word<B: Bus>(&mut self, mem: &mut B) -> u16 {
+        let lo = self.pull_byte(mem);
+        let hi = self.pull_byte(mem);
+        (lo as u16) | ((hi as u16) << 8)
+    }
+
+    fn binary_add(&mut self, value: u8) -> u8 {
+        let carry_flag_check: u16 = self.reg_accum as u16 + value as u16 + self.reg_ps.carry as u16;
+        let res = carry_flag_check as u8;
+        self.reg_ps.update_zero_negative(res);
+        self.reg_ps.update_carry(carry_flag_check);
+        self.reg_ps.update_overflow(self.reg_accum, value, res);
+        res
+    }
+
+    //Whether arithmetic should be done in BCD. The 2A03 still stores the D flag but ignores it.
+    fn decimal_mode(&self) -> bool {
+        self.reg_ps.decimal && self.variant != Variant::RICOH_2A03
+    }
+
+    fn add_with_carry(&mut self, value: u8) {
+        let accum = self.reg_accum;
+        let carry_in = self.reg_ps.carry;
+        let res = self.binary_add(value);
+
+        if self.decimal_mode() {
+            //NMOS decimal mode: Z comes from the binary sum above, while N and V are taken from the
+            //intermediate result after the low nibble is adjusted but before the high nibble is
+            let mut low = (accum & 0x0F) as u16 + (value & 0x0F) as u16 + carry_in as u16;
+            if low >= 0x0A {
+                low = ((low + 0x06) & 0x0F) + 0x10;
+            }
+            let mut sum = (accum & 0xF0) as u16 + (value & 0xF0) as u16 + low;
+            let intermediate = sum as u8;
+            self.reg_ps.negative = (intermediate & 0x80) != 0;
+            self.reg_ps.update_overflow(accum, value, intermediate);
+            if sum >= 0xA0 {
+                sum += 0x60;
+            }
+            self.reg_ps.carry = sum >= 0x100;
+            self.reg_accum = sum as u8;
+            if self.variant == Variant::WDC_65C02 {
+                //The 65C02 fixes N and Z up to match the decimal result
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            }
+        }else{
+            self.reg_accum = res;
+        }
+    }
+
+    fn subtract_with_borrow(&mut self, value: u8) {
+        let accum = self.reg_accum;
+        let borrow = 1 - self.reg_ps.carry as i16;
+
+        //Subtraction is addition of the one's complement, with carry acting as "not borrow".
+        //On NMOS parts every flag comes from this binary result, even in decimal mode.
+        let res = self.binary_add(!value);
+
+        if self.decimal_mode() && self.variant == Variant::WDC_65C02 {
+            //The 65C02 adjusts the whole binary difference rather than each nibble, and its N and Z
+            //reflect the decimal result
+            let low = (accum & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+            let mut diff = accum as i16 - value as i16 - borrow;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            if low < 0 {
+                diff -= 0x06;
+            }
+            self.reg_accum = diff as u8;
+            self.reg_ps.update_zero_negative(self.reg_accum);
+        }else if self.decimal_mode() {
+            let mut low = (accum & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+            if low < 0 {
+                low = ((low - 0x06) & 0x0F) - 0x10;
+            }
+            let mut diff = (accum & 0xF0) as i16 - (value & 0xF0) as i16 + low;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            self.reg_accum = diff as u8;
+        }else{
+            self.reg_accum = res;
+        }
+    }
+
+    //The 65C02 spends an extra cycle on ADC and SBC in decimal mode to correct the flags
+    fn decimal_fixup_cycle<B: Bus>(&mut self, mem: &mut B) -> u16 {
+        if self.reg_ps.decimal && self.variant == Variant::WDC_65C02 {
+            self.dummy_read(mem, self.reg_pc.wrapping_sub(1));
+            1
+        }else{
+            0
+        }
+    }
+
+    fn compare(&mut self, reg: u8, value: u8) {
+        self.reg_ps.update_zero_negative(reg.wrapping_sub(value));
+        self.reg_ps.carry = reg >= value;
+    }
+
+    //Returns the extra cycles used: one for a taken branch, and another if it lands on a different page
+    fn branch<B: Bus>(&mut self, mem: &mut B, operand: Operand, page_crossed: bool, condition: bool) -> u16 {
+        if condition {
+            let target = self.operand_address(operand);
+            self.dummy_read(mem, self.reg_pc);
+            if page_crossed {
+                self.dummy_read(mem, (self.reg_pc & 0xFF00) | (target & 0x00FF));
+            }
+            self.reg_pc = target;
+            1 + page_crossed as u16
+        }else{
+            0
+        }
+    }
+
+    fn shift_left(&mut self, value: u8, carry_in: u8) -> u8 {
+        let carry_flag_check: u16 = ((value as u16) << 1) | carry_in as u16;
+        let res = carry_flag_check as u8;
+        self.reg_ps.update_zero_negative(res);
+        self.reg_ps.update_carry(carry_flag_check);
+        res
+    }
+
+    fn shift_right(&mut self, value: u8, carry_in: u8) -> u8 {
+        let res = (value >> 1) | (carry_in << 7);
+        self.reg_ps.carry = (value & 0x1) != 0;
+        self.reg_ps.update_zero_negative(res);
+        res
+    }
+
+    //ARR is AND followed by ROR, but takes C and V from the rotated result, and in decimal mode
+    //applies a BCD fix-up to each nibble like ADC does
+    fn and_rotate_right(&mut self, value: u8) {
+        let anded = self.reg_accum & value;
+        let mut res = (anded >> 1) | ((self.reg_ps.carry as u8) << 7);
+        if self.decimal_mode() {
+            self.reg_ps.negative = self.reg_ps.carry;
+            self.reg_ps.zero = res == 0;
+            self.reg_ps.overflow = ((anded ^ res) & 0x40) != 0;
+            if (anded & 0x0F) + (anded & 0x01) > 0x05 {
+                res = (res & 0xF0) | (res.wrapping_add(0x06) & 0x0F);
+            }
+            self.reg_ps.carry = (anded & 0xF0) as u16 + (anded & 0x10) as u16 > 0x50;
+            if self.reg_ps.carry {
+                res = res.wrapping_add(0x60);
+            }
+        }else{
+            self.reg_ps.update_zero_negative(res);
+            self.reg_ps.carry = (res & 0x40) != 0;
+            self.reg_ps.overflow = (((res >> 6) ^ (res >> 5)) & 0x1) != 0;
+        }
+        self.reg_accum = res;
+    }
+
+    //Execute a fetched instruction, returning the number of cycles it took
+    fn execute<B: Bus>(&mut self, inst: &mut Instruction, mem: &mut B) -> u16 {
+        //Only a BRK leaves this set for the debugger to see
+        self.break_hit = false;
+
+        //Program counter points at the next instruction while this one executes
+        let inst_pc = self.reg_pc;
+        self.reg_pc = self.reg_pc.wrapping_add(inst.num_bytes);
+        let (operand, page_crossed) = if inst.inst == InstructionTypes::JSR {
+            //JSR has only fetched the low byte of its target at this point and finishes decoding it itself
+            (Operand::NONE, false)
+        }else{
+            self.resolve_operand(inst, mem)
+        };
+
+        //Indexed reads need an extra cycle to fix up the high byte when they cross a page
+        let mut num_cycles = inst.num_cycles;
+        if page_crossed && inst.page_cross_penalty && inst.mode != AddressingMode::RELATIVE {
+            num_cycles += 1;
+        }
+
         match inst.inst {
             InstructionTypes::BRK => {
-                println!("CPU> Instruction: BRK - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                //Push PC + 2 and status register (with break flag set to 1) to stack
-                let pc_plus_two = self.reg_pc + 2;
-                self.reg_ps_bc = 1;
-                let bytes_to_push_on_stack = vec![((pc_plus_two & 0xFF00) >> 8) as u8, (pc_plus_two & 0x00FF) as u8, self.get_status_reg_byte()];
-                self.reg_ps_bc = 0;
-                if let Ok(_) = mem.push_onto_stack(self.reg_sp, &bytes_to_push_on_stack) {
-                    self.reg_sp -= 3;
-                    self.reg_ps_bc = 1;
-                    self.reg_pc += 1;
-                }else{
-                    //TODO: Push onto stack fails
-                    println!("CPU> BRK push onto stack failed");
-                }
-                //TODO: Update status regs?
-            },
-            InstructionTypes::LDA_IMMEDIATE => {
-                println!("CPU> Instruction: LDA Immediate - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                let carry_flag_check: u16 = inst.data[0] as u16;
-                self.reg_accum = inst.data[0] as u8;
-                self.update_status_regs(self.reg_accum, carry_flag_check);
-                self.reg_pc += 2;
-            },
-            InstructionTypes::STA_ABSOLUTE => {
-                println!("CPU> Instruction: STA Absolute - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                let carry_flag_check: u16 = self.reg_accum as u16; //TODO: Is this right?
-                if let Ok(_) = mem.write_byte((inst.data[0] as u16) | ((inst.data[1] as u16) << 8), self.reg_accum, false) {
-                    self.update_status_regs(self.reg_accum, carry_flag_check); //TODO: Is this right?
-                    self.reg_pc += 3;
-                }else{
-                    //TODO: Write fails?
-                    println!("CPU> STA absolute write failed");
+                //BRK skips the padding byte after it, so the return address is PC + 2
+                self.interrupt(mem, inst_pc.wrapping_add(2), IRQ_BRK_VECTOR, true);
+            },
+            InstructionTypes::LDA => {
+                self.reg_accum = self.read_operand(operand, mem);
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::LDX => {
+                self.reg_index_x = self.read_operand(operand, mem);
+                self.reg_ps.update_zero_negative(self.reg_index_x);
+            },
+            InstructionTypes::LDY => {
+                self.reg_index_y = self.read_operand(operand, mem);
+                self.reg_ps.update_zero_negative(self.reg_index_y);
+            },
+            InstructionTypes::STA => {
+                self.write_operand(operand, mem, self.reg_accum);
+            },
+            InstructionTypes::STX => {
+                self.write_operand(operand, mem, self.reg_index_x);
+            },
+            InstructionTypes::STY => {
+                self.write_operand(operand, mem, self.reg_index_y);
+            },
+            InstructionTypes::TAX => {
+                self.reg_index_x = self.reg_accum;
+                self.reg_ps.update_zero_negative(self.reg_index_x);
+            },
+            InstructionTypes::TAY => {
+                self.reg_index_y = self.reg_accum;
+                self.reg_ps.update_zero_negative(self.reg_index_y);
+            },
+            InstructionTypes::TXA => {
+                self.reg_accum = self.reg_index_x;
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::TYA => {
+                self.reg_accum = self.reg_index_y;
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::TSX => {
+                self.reg_index_x = self.reg_sp;
+                self.reg_ps.update_zero_negative(self.reg_index_x);
+            },
+            InstructionTypes::TXS => {
+                self.reg_sp = self.reg_index_x;
+            },
+            InstructionTypes::PHA => {
+                self.push_byte(mem, self.reg_accum);
+            },
+            InstructionTypes::PHP => {
+                //PHP pushes the status with the break bit set, just like BRK
+                let status = self.reg_ps.to_byte(true);
+                self.push_byte(mem, status);
+            },
+            InstructionTypes::PLA => {
+                //Pulls spend a cycle reading the current stack slot before incrementing SP
+                self.dummy_read(mem, self.stack_addr());
+                self.reg_accum = self.pull_byte(mem);
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::PLP => {
+                self.dummy_read(mem, self.stack_addr());
+                let status = self.pull_byte(mem);
+                self.reg_ps.set_from_byte(status);
+            },
+            InstructionTypes::AND => {
+                self.reg_accum &= self.read_operand(operand, mem);
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::EOR => {
+                self.reg_accum ^= self.read_operand(operand, mem);
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::ORA => {
+                self.reg_accum |= self.read_operand(operand, mem);
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::BIT => {
+                let value = self.read_operand(operand, mem);
+                self.reg_ps.zero = (self.reg_accum & value) == 0;
+                //The 65C02's BIT #imm has no memory flags to copy, so it only affects Z
+                if inst.mode != AddressingMode::IMMEDIATE {
+                    self.reg_ps.negative = (value & 0x80) != 0;
+                    self.reg_ps.overflow = (value & 0x40) != 0;
                 }
             },
-            InstructionTypes::ASL_ACCUMULATOR => {
-                println!("CPU> Instruction: ASL Accumulator - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                let carry_flag_check: u16 = (self.reg_accum as u16) << self.reg_accum as u16;
-                self.reg_accum <<= self.reg_accum; //TODO: Is this right?
-                self.update_status_regs(self.reg_accum, carry_flag_check);
-                self.reg_pc += 1;
-            },
-            InstructionTypes::ADC_IMMEDIATE => {
-                println!("CPU> Instruction: ADC Immediate - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                let carry_flag_check: u16 = self.reg_accum as u16 + inst.data[0] as u16;
-                self.reg_accum += inst.data[0] as u8;
-                self.update_status_regs(self.reg_accum, carry_flag_check);
-                self.reg_pc += 2;
-            },
-            InstructionTypes::ROL_IMMEDIATE => {
-                println!("CPU> Instruction: ROL Immediate - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                let carry_flag_check: u16 = (self.reg_accum as u16) << 1;
-                self.reg_accum <<= 1; //TODO: Is this right?
-                self.update_status_regs(self.reg_accum, carry_flag_check);
-                self.reg_pc += 1;
-            },
-            InstructionTypes::CLEAR_CARRY => {
-                println!("CPU> Instruction: Clear Carry - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                self.reg_ps_cf = 0;
-                self.reg_pc += 1;
-                //TODO: Update status regs?
-            },
-            InstructionTypes::ADC_ABSOLUTE => {
-                println!("CPU> Instruction: ADC Absolute - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                //TODO:
-                self.reg_pc += 3;
+            InstructionTypes::ADC => {
+                let value = self.read_operand(operand, mem);
+                num_cycles += self.decimal_fixup_cycle(mem);
+                self.add_with_carry(value);
+            },
+            InstructionTypes::SBC => {
+                let value = self.read_operand(operand, mem);
+                num_cycles += self.decimal_fixup_cycle(mem);
+                self.subtract_with_borrow(value);
+            },
+            InstructionTypes::CMP => {
+                let value = self.read_operand(operand, mem);
+                self.compare(self.reg_accum, value);
+            },
+            InstructionTypes::CPX => {
+                let value = self.read_operand(operand, mem);
+                self.compare(self.reg_index_x, value);
+            },
+            InstructionTypes::CPY => {
+                let value = self.read_operand(operand, mem);
+                self.compare(self.reg_index_y, value);
+            },
+            InstructionTypes::INC => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = value.wrapping_add(1);
+                    cpu.reg_ps.update_zero_negative(res);
+                    res
+                });
+            },
+            InstructionTypes::DEC => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = value.wrapping_sub(1);
+                    cpu.reg_ps.update_zero_negative(res);
+                    res
+                });
+            },
+            InstructionTypes::INX => {
+                self.reg_index_x = self.reg_index_x.wrapping_add(1);
+                self.reg_ps.update_zero_negative(self.reg_index_x);
+            },
+            InstructionTypes::INY => {
+                self.reg_index_y = self.reg_index_y.wrapping_add(1);
+                self.reg_ps.update_zero_negative(self.reg_index_y);
+            },
+            InstructionTypes::DEX => {
+                self.reg_index_x = self.reg_index_x.wrapping_sub(1);
+                self.reg_ps.update_zero_negative(self.reg_index_x);
+            },
+            InstructionTypes::DEY => {
+                self.reg_index_y = self.reg_index_y.wrapping_sub(1);
+                self.reg_ps.update_zero_negative(self.reg_index_y);
+            },
+            InstructionTypes::ASL => {
+                self.read_modify_write(operand, mem, |cpu, value| cpu.shift_left(value, 0));
+            },
+            InstructionTypes::ROL => {
+                self.read_modify_write(operand, mem, |cpu, value| cpu.shift_left(value, cpu.reg_ps.carry as u8));
+            },
+            InstructionTypes::LSR => {
+                self.read_modify_write(operand, mem, |cpu, value| cpu.shift_right(value, 0));
+            },
+            InstructionTypes::ROR => {
+                self.read_modify_write(operand, mem, |cpu, value| cpu.shift_right(value, cpu.reg_ps.carry as u8));
+            },
+            InstructionTypes::JMP => {
+                self.reg_pc = self.operand_address(operand);
+            },
+            InstructionTypes::JSR => {
+                //Return address pushed is the last byte of the JSR instruction, which is read only
+                //after the push
+                let return_addr = inst_pc.wrapping_add(2);
+                self.dummy_read(mem, self.stack_addr());
+                self.push_word(mem, return_addr);
+                let target_hi = self.bus_read(mem, return_addr, ReadKind::FETCH).unwrap_or(0);
+                inst.data.push(target_hi);
+                self.reg_pc = (inst.data[0] as u16) | ((target_hi as u16) << 8);
             },
             InstructionTypes::RTS => {
-                println!("CPU> Instruction: RTS (Return from Subroutine) - Cycles {} - Total Cycles {}", inst.num_cycles, self.total_cycles);
-                //TODO:
-                self.reg_pc += 1;
+                self.dummy_read(mem, self.stack_addr());
+                let return_addr = self.pull_word(mem);
+                self.dummy_read(mem, return_addr);
+                self.reg_pc = return_addr.wrapping_add(1);
+            },
+            InstructionTypes::RTI => {
+                self.dummy_read(mem, self.stack_addr());
+                let status = self.pull_byte(mem);
+                self.reg_ps.set_from_byte(status);
+                self.reg_pc = self.pull_word(mem);
+            },
+            InstructionTypes::BCC => {
+                num_cycles += self.branch(mem, operand, page_crossed, !self.reg_ps.carry);
+            },
+            InstructionTypes::BCS => {
+                num_cycles += self.branch(mem, operand, page_crossed, self.reg_ps.carry);
+            },
+            InstructionTypes::BEQ => {
+                num_cycles += self.branch(mem, operand, page_crossed, self.reg_ps.zero);
+            },
+            InstructionTypes::BNE => {
+                num_cycles += self.branch(mem, operand, page_crossed, !self.reg_ps.zero);
+            },
+            InstructionTypes::BMI => {
+                num_cycles += self.branch(mem, operand, page_crossed, self.reg_ps.negative);
+            },
+            InstructionTypes::BPL => {
+                num_cycles += self.branch(mem, operand, page_crossed, !self.reg_ps.negative);
+            },
+            InstructionTypes::BVS => {
+                num_cycles += self.branch(mem, operand, page_crossed, self.reg_ps.overflow);
+            },
+            InstructionTypes::BVC => {
+                num_cycles += self.branch(mem, operand, page_crossed, !self.reg_ps.overflow);
+            },
+            InstructionTypes::CLC => {
+                self.reg_ps.carry = false;
+            },
+            InstructionTypes::SEC => {
+                self.reg_ps.carry = true;
+            },
+            InstructionTypes::CLI => {
+                self.reg_ps.interrupt_disable = false;
+            },
+            InstructionTypes::SEI => {
+                self.reg_ps.interrupt_disable = true;
+            },
+            InstructionTypes::CLD => {
+                self.reg_ps.decimal = false;
+            },
+            InstructionTypes::SED => {
+                self.reg_ps.decimal = true;
+            },
+            InstructionTypes::CLV => {
+                self.reg_ps.overflow = false;
+            },
+            InstructionTypes::NOP => {
+                //The undocumented multi-byte NOPs still read their operand
+                if let Operand::ADDRESS(addr) = operand {
+                    self.read_operand(operand, mem);
+                    //The 65C02's eight cycle NOP goes on to read from the top page for four more cycles
+                    for _ in 4..inst.num_cycles {
+                        self.dummy_read(mem, 0xFF00 | (addr & 0x00FF));
+                    }
+                }
+            },
+            InstructionTypes::LAX => {
+                self.reg_accum = self.read_operand(operand, mem);
+                self.reg_index_x = self.reg_accum;
+                self.reg_ps.update_zero_negative(self.reg_accum);
+            },
+            InstructionTypes::SAX => {
+                self.write_operand(operand, mem, self.reg_accum & self.reg_index_x);
+            },
+            InstructionTypes::DCP => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = value.wrapping_sub(1);
+                    cpu.compare(cpu.reg_accum, res);
+                    res
+                });
+            },
+            InstructionTypes::ISC => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = value.wrapping_add(1);
+                    cpu.subtract_with_borrow(res);
+                    res
+                });
+            },
+            InstructionTypes::SLO => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = cpu.shift_left(value, 0);
+                    cpu.reg_accum |= res;
+                    cpu.reg_ps.update_zero_negative(cpu.reg_accum);
+                    res
+                });
+            },
+            InstructionTypes::RLA => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = cpu.shift_left(value, cpu.reg_ps.carry as u8);
+                    cpu.reg_accum &= res;
+                    cpu.reg_ps.update_zero_negative(cpu.reg_accum);
+                    res
+                });
+            },
+            InstructionTypes::SRE => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = cpu.shift_right(value, 0);
+                    cpu.reg_accum ^= res;
+                    cpu.reg_ps.update_zero_negative(cpu.reg_accum);
+                    res
+                });
+            },
+            InstructionTypes::RRA => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    let res = cpu.shift_right(value, cpu.reg_ps.carry as u8);
+                    cpu.add_with_carry(res);
+                    res
+                });
+            },
+            InstructionTypes::ANC => {
+                //AND, then copy the result's sign into carry as if it had been shifted left
+                self.reg_accum &= self.read_operand(operand, mem);
+                self.reg_ps.update_zero_negative(self.reg_accum);
+                self.reg_ps.carry = self.reg_ps.negative;
+            },
+            InstructionTypes::ALR => {
+                let value = self.read_operand(operand, mem);
+                self.reg_accum = self.shift_right(self.reg_accum & value, 0);
+            },
+            InstructionTypes::ARR => {
+                let value = self.read_operand(operand, mem);
+                self.and_rotate_right(value);
+            },
+            InstructionTypes::SBX => {
+                //X = (A & X) - value, setting flags like CMP and ignoring decimal mode
+                let value = self.read_operand(operand, mem);
+                let anded = self.reg_accum & self.reg_index_x;
+                self.compare(anded, value);
+                self.reg_index_x = anded.wrapping_sub(value);
+            },
+            InstructionTypes::JAM => {
+                //The processor locks up with the opcode on the bus until it is reset
+                self.reg_pc = inst_pc;
+                self.set_halt();
+            },
+            InstructionTypes::BRA => {
+                num_cycles += self.branch(mem, operand, page_crossed, true);
+            },
+            InstructionTypes::PHX => {
+                self.push_byte(mem, self.reg_index_x);
+            },
+            InstructionTypes::PHY => {
+                self.push_byte(mem, self.reg_index_y);
+            },
+            InstructionTypes::PLX => {
+                self.dummy_read(mem, self.stack_addr());
+                self.reg_index_x = self.pull_byte(mem);
+                self.reg_ps.update_zero_negative(self.reg_index_x);
+            },
+            InstructionTypes::PLY => {
+                self.dummy_read(mem, self.stack_addr());
+                self.reg_index_y = self.pull_byte(mem);
+                self.reg_ps.update_zero_negative(self.reg_index_y);
+            },
+            InstructionTypes::STZ => {
+                self.write_operand(operand, mem, 0);
+            },
+            InstructionTypes::TSB => {
+                //Z reflects the bits the accumulator has in common with memory before they are set
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    cpu.reg_ps.zero = (cpu.reg_accum & value) == 0;
+                    value | cpu.reg_accum
+                });
+            },
+            InstructionTypes::TRB => {
+                self.read_modify_write(operand, mem, |cpu, value| {
+                    cpu.reg_ps.zero = (cpu.reg_accum & value) == 0;
+                    value & !cpu.reg_accum
+                });
+            },
+            InstructionTypes::RMB(bit) => {
+                let value = self.read_operand(operand, mem);
+                self.dummy_read(mem, self.operand_address(operand));
+                self.write_operand(operand, mem, value & !(1 << bit));
+            },
+            InstructionTypes::SMB(bit) => {
+                let value = self.read_operand(operand, mem);
+                self.dummy_read(mem, self.operand_address(operand));
+                self.write_operand(operand, mem, value | (1 << bit));
+            },
+            InstructionTypes::BBR(bit) | InstructionTypes::BBS(bit) => {
+                //Test a zero page bit, then branch relative to the following instruction
+                let value = self.read_operand(operand, mem);
+                self.dummy_read(mem, self.operand_address(operand));
+                let target = self.reg_pc.wrapping_add(inst.data[1] as i8 as u16);
+                let page_crossed = (target & 0xFF00) != (self.reg_pc & 0xFF00);
+                let bit_set = (value & (1 << bit)) != 0;
+                let condition = if let InstructionTypes::BBS(_) = inst.inst { bit_set } else { !bit_set };
+                num_cycles += self.branch(mem, Operand::ADDRESS(target), page_crossed, condition);
+            },
+            InstructionTypes::WAI => {
+                self.dummy_read(mem, self.reg_pc);
+                self.waiting = true;
+            },
+            InstructionTypes::STP => {
+                //Only a reset restarts the processor
+                self.dummy_read(mem, self.reg_pc);
+                self.set_halt();
+            }
+        }
+
+        num_cycles
+    }
+
+    fn log_instruction(&self, inst: &Instruction, inst_pc: u16, num_cycles: u16) {
+        if let Some(info) = self.decode(inst.opcode) {
+            log!(Subsystem::CPU, Level::TRACE, "Instruction: {} - Cycles {} - Total Cycles {}", info.disassemble(&inst.data, inst_pc), num_cycles, self.total_cycles);
+        }
+    }
+
+    fn register_state(&self) -> RegisterState {
+        RegisterState {
+            accum: self.reg_accum,
+            index_x: self.reg_index_x,
+            index_y: self.reg_index_y,
+            status: self.get_status_reg_byte(),
+            sp: self.reg_sp,
+            cycles: self.total_cycles
+        }
+    }
+
+    fn trace_instruction(&mut self, inst: &Instruction, inst_pc: u16, before: RegisterState) {
+        let tracer = if let Some(tracer) = &self.tracer { tracer.clone() } else { return };
+        if let Some(info) = self.decode(inst.opcode) {
+            let mut bytes = vec![inst.opcode];
+            bytes.extend(&inst.data);
+            let line = TraceLine {
+                pc: inst_pc,
+                bytes,
+                disassembly: info.disassemble(&inst.data, inst_pc),
+                undocumented: self.variant != Variant::WDC_65C02 && opcodes::lookup(inst.opcode).is_none(),
+                accum: before.accum,
+                index_x: before.index_x,
+                index_y: before.index_y,
+                status: before.status,
+                sp: before.sp,
+                cycles: before.cycles
+            };
+            let res = tracer.borrow_mut().write_line(&line);
+            if let Err(error) = res {
+                log!(Subsystem::CPU, Level::ERROR, "Failed to write trace, tracing stopped: {}", error);
+                self.tracer = None;
+            }
+        }
+    }
+
+    //Fetch and execute one instruction, returning it with its address and cycle count. Faults
+    //during execution are left in bus_fault for the caller to report once the cycles are accounted for.
+    fn fetch_and_execute<B: Bus>(&mut self, mem: &mut B) -> Result<(Instruction, u16, u16), CpuError> {
+        let inst_pc = self.reg_pc;
+        self.bus_fault = None;
+        mem.begin_instruction(inst_pc);
+        let mut inst = self.fetch(mem)?;
+        let num_cycles = self.execute(&mut inst, mem);
+        Ok((inst, inst_pc, num_cycles))
+    }
+
+    //Run the interrupt sequence for the given vector, leaving any fault in bus_fault
+    fn run_interrupt<B: Bus>(&mut self, mem: &mut B, vector: u16) {
+        self.bus_fault = None;
+        mem.begin_instruction(self.reg_pc);
+        self.service_interrupt(mem, vector);
+    }
+
+    //Total number of clock cycles executed since the CPU was created
+    pub fn cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    //Run whole instructions until at least num_cycles clock cycles have elapsed, returning how many
+    //actually ran. The last instruction may overshoot, so callers keeping peripherals in sync
+    //should carry the difference into the next slice.
+    pub fn run_for_cycles<B: Bus>(&mut self, mem_ref: &mut B, num_cycles: u64) -> Result<u64, CpuError> {
+        let start_cycles = self.total_cycles;
+        while !self.do_halt && self.total_cycles - start_cycles < num_cycles {
+            self.step(mem_ref)?;
+        }
+        Ok(self.total_cycles - start_cycles)
+    }
+
+    //Execute one whole instruction (or interrupt sequence) at once
+    pub fn step<B: Bus>(&mut self, mem_ref: &mut B) -> Result<StepOutcome, CpuError> {
+        //Finish off an instruction that was started one cycle at a time
+        if self.tick_snapshot.is_some() {
+            loop {
+                if let Some(outcome) = self.tick_cycle(mem_ref)? {
+                    return Ok(outcome);
+                }
             }
         }
+
+        self.bus_irq = mem_ref.irq();
+        if self.idle_cycle(mem_ref) {
+            return Ok(StepOutcome::IDLE);
+        }
+
+        //An interrupt polled during the last instruction takes the place of the next one
+        if let Some(vector) = self.interrupt_latched.take() {
+            let interrupted_pc = self.reg_pc;
+            self.take_interrupt(vector);
+            self.run_interrupt(mem_ref, vector);
+            self.total_cycles += INTERRUPT_CYCLES as u64;
+            self.log_interrupt(vector);
+            self.take_fault(interrupted_pc, None)?;
+            return Ok(StepOutcome::INTERRUPT { vector, cycles: INTERRUPT_CYCLES });
+        }
+
+        //Fetch the next instruction and then number of cycles it takes. Interrupts are polled before
+        //its last cycle, so a change to I by CLI, SEI or PLP only takes effect after the next instruction.
+        let before = self.register_state();
+        let interrupt_polled = self.pending_interrupt();
+        let executed = self.fetch_and_execute(mem_ref);
+        self.interrupt_latched = interrupt_polled;
+        let (inst, inst_pc, num_cycles) = executed?;
+        self.total_cycles += num_cycles as u64;
+        self.log_instruction(&inst, inst_pc, num_cycles);
+        self.trace_instruction(&inst, inst_pc, before);
+        self.take_fault(inst_pc, Some(inst.opcode))?;
+        Ok(StepOutcome::INSTRUCTION { pc: inst_pc as u32, opcode: inst.opcode, cycles: num_cycles })
+    }
+
+    //Advance the CPU by exactly one clock cycle, performing a single bus read or write. Returns true
+    //when that cycle completed an instruction or interrupt sequence. Can be freely mixed with step.
+    pub fn tick<B: Bus>(&mut self, mem_ref: &mut B) -> Result<bool, CpuError> {
+        Ok(self.tick_cycle(mem_ref)?.is_some())
     }
 
-    pub fn step(&mut self, mem_ref: &mut Memory) {
-        //Fetch the next instruction and then number of cycles it takes
-        if let Ok(inst) = self.fetch(mem_ref) {
-            //Excute the instuction
-            self.execute(&inst, mem_ref);
+    //One cycle of tick, returning what was executed once the instruction or interrupt sequence completes
+    fn tick_cycle<B: Bus>(&mut self, mem_ref: &mut B) -> Result<Option<StepOutcome>, CpuError> {
+        //Sampled every cycle, so the poll before an instruction's last cycle sees the line as it is then
+        self.bus_irq = mem_ref.irq();
+        if self.tick_snapshot.is_none() && self.idle_cycle(mem_ref) {
+            return Ok(Some(StepOutcome::IDLE));
+        }
+        let snapshot = if let Some(snapshot) = self.tick_snapshot.take() {
+            snapshot
         }else{
-            println!("CPU> Failed to fetch next instruction!");
+            //Starting a new instruction, unless an interrupt was polled at the end of the last one
+            self.tick_interrupt = self.interrupt_latched.take();
+            if let Some(vector) = self.tick_interrupt {
+                self.take_interrupt(vector);
+            }
+            self.cycle_replay = CycleReplay::default();
+            Box::new(self.clone())
+        };
+
+        //Interrupts are polled before the last cycle of an instruction, using the state it started with
+        let interrupt_polled = self.pending_interrupt();
+
+        //Requests from outside the CPU that must survive rolling back a partly run instruction
+        let (nmi_pending, irq_line, do_halt) = (self.nmi_pending, self.irq_line, self.do_halt);
+
+        self.cycle_replay.active = true;
+        self.cycle_replay.replay_len = self.cycle_replay.log.len();
+        self.cycle_replay.position = 0;
+        let executed = if let Some(vector) = self.tick_interrupt {
+            self.run_interrupt(mem_ref, vector);
+            None
+        }else{
+            Some(self.fetch_and_execute(mem_ref))
+        };
+        self.cycle_replay.active = false;
+
+        let cycles_so_far = self.cycle_replay.log.len() as u64;
+        if self.cycle_replay.position == self.cycle_replay.replay_len + 1 {
+            //The access made this cycle was the last one the instruction needed
+            self.total_cycles = snapshot.total_cycles + cycles_so_far;
+            self.cycle_replay = CycleReplay::default();
+            if let Some(vector) = self.tick_interrupt {
+                //The first handler instruction always runs before interrupts are polled again
+                self.interrupt_latched = None;
+                self.log_interrupt(vector);
+                self.take_fault(snapshot.reg_pc, None)?;
+                Ok(Some(StepOutcome::INTERRUPT { vector, cycles: cycles_so_far as u16 }))
+            }else{
+                self.interrupt_latched = interrupt_polled;
+                if let Some(executed) = executed {
+                    let (inst, inst_pc, _) = executed?;
+                    self.log_instruction(&inst, inst_pc, cycles_so_far as u16);
+                    self.trace_instruction(&inst, inst_pc, snapshot.register_state());
+                    self.take_fault(inst_pc, Some(inst.opcode))?;
+                    Ok(Some(StepOutcome::INSTRUCTION { pc: inst_pc as u32, opcode: inst.opcode, cycles: cycles_so_far as u16 }))
+                }else{
+                    Ok(None)
+                }
+            }
+        }else{
+            //Roll back to the start of the instruction, keeping the accesses made so far and any
+            //interrupt or halt requests that arrived before it was run again
+            let cycle_replay = std::mem::take(&mut self.cycle_replay);
+            *self = (*snapshot).clone();
+            self.nmi_pending = nmi_pending;
+            self.irq_line = irq_line;
+            self.do_halt = do_halt;
+            self.cycle_replay = cycle_replay;
+            self.total_cycles = snapshot.total_cycles + cycles_so_far;
+            self.tick_snapshot = Some(snapshot);
+            Ok(None)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::Device;
+    use crate::memory::Memory;
+    use crate::status::{FLAG_BREAK, FLAG_INTERRUPT_DISABLE, FLAG_UNUSED, FLAG_ZERO};
+
+    const PROGRAM_START: u16 = 0x0400;
+
+    //A CPU of the given variant about to run prog from PROGRAM_START
+    fn load(variant: Variant, prog: &[u8]) -> (CPU, Memory) {
+        let mut mem = Memory::new();
+        for (offset, byte) in prog.iter().enumerate() {
+            mem.write_byte(PROGRAM_START + offset as u16, *byte).unwrap();
+        }
+        let mut cpu = CPU::with_variant(variant);
+        cpu.reg_pc = PROGRAM_START;
+        (cpu, mem)
+    }
+
+    //Run prog from PROGRAM_START on an NMOS CPU until it steps past the end of it
+    fn run(prog: &[u8]) -> (CPU, Memory) {
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, prog);
+        let end = PROGRAM_START + prog.len() as u16;
+        for _ in 0..100 {
+            if cpu.reg_pc == end {
+                return (cpu, mem);
+            }
+            cpu.step(&mut mem).unwrap();
+        }
+        panic!("program never reached {:#06x}", end);
+    }
+
+    //N, V, Z and C
+    fn flags(cpu: &CPU) -> [bool; 4] {
+        [cpu.reg_ps.negative, cpu.reg_ps.overflow, cpu.reg_ps.zero, cpu.reg_ps.carry]
+    }
+
+    #[test]
+    fn loads_and_stores() {
+        //LDA #$80; STA $10; LDX $10; STX $11; LDY $11; STY $12; LDA #$00
+        let (cpu, mem) = run(&[0xA9, 0x80, 0x85, 0x10, 0xA6, 0x10, 0x86, 0x11, 0xA4, 0x11, 0x84, 0x12, 0xA9, 0x00]);
+        assert_eq!(mem.peek_n_bytes(0x10, 3).unwrap(), vec![0x80, 0x80, 0x80]);
+        assert_eq!((cpu.reg_accum, cpu.reg_index_x, cpu.reg_index_y), (0x00, 0x80, 0x80));
+        assert_eq!(flags(&cpu), [false, false, true, false]);
+    }
+
+    #[test]
+    fn transfers() {
+        //LDX #$05; TXA; TAY; INY; TYA
+        let (cpu, _) = run(&[0xA2, 0x05, 0x8A, 0xA8, 0xC8, 0x98]);
+        assert_eq!((cpu.reg_accum, cpu.reg_index_x, cpu.reg_index_y), (0x06, 0x05, 0x06));
+        //LDX #$80; TXS; LDX #$00; TSX
+        let (cpu, _) = run(&[0xA2, 0x80, 0x9A, 0xA2, 0x00, 0xBA]);
+        assert_eq!((cpu.reg_sp, cpu.reg_index_x), (0x80, 0x80));
+        assert_eq!(flags(&cpu), [true, false, false, false]);
+    }
+
+    #[test]
+    fn arithmetic() {
+        //CLC; LDA #$10; ADC #$20
+        let (cpu, _) = run(&[0x18, 0xA9, 0x10, 0x69, 0x20]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x30, [false, false, false, false]));
+        //SEC; LDA #$30; SBC #$31, which borrows
+        let (cpu, _) = run(&[0x38, 0xA9, 0x30, 0xE9, 0x31]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0xFF, [true, false, false, false]));
+        //CLC; LDA #$FF; ADC #$01 carries out
+        let (cpu, _) = run(&[0x18, 0xA9, 0xFF, 0x69, 0x01]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x00, [false, false, true, true]));
+    }
+
+    #[test]
+    fn logic() {
+        //LDA #$F0; AND #$3C; ORA #$03
+        let (cpu, _) = run(&[0xA9, 0xF0, 0x29, 0x3C, 0x09, 0x03]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x33, [false, false, false, false]));
+        //LDA #$33; EOR #$CC
+        let (cpu, _) = run(&[0xA9, 0x33, 0x49, 0xCC]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0xFF, [true, false, false, false]));
+        //LDA #$3F; BIT $10, with $10 holding $C0. A is left alone.
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, &[0xA9, 0x3F, 0x24, 0x10]);
+        mem.write_byte(0x10, 0xC0).unwrap();
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+        assert_eq!((cpu.reg_accum, cpu.reg_ps.zero), (0x3F, true));
+    }
+
+    #[test]
+    fn shifts_and_rotates() {
+        //LDA #$81; ASL A
+        let (cpu, _) = run(&[0xA9, 0x81, 0x0A]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x02, [false, false, false, true]));
+        //...; ROR A takes the carry into bit 7
+        let (cpu, _) = run(&[0xA9, 0x81, 0x0A, 0x6A]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x81, [true, false, false, false]));
+        //...; LSR A
+        let (cpu, _) = run(&[0xA9, 0x81, 0x0A, 0x6A, 0x4A]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x40, [false, false, false, true]));
+        //...; ROL A takes the carry into bit 0
+        let (cpu, _) = run(&[0xA9, 0x81, 0x0A, 0x6A, 0x4A, 0x2A]);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x81, [true, false, false, false]));
+        //LDA #$80; STA $10; ASL $10 works on memory, leaving A alone
+        let (cpu, mem) = run(&[0xA9, 0x80, 0x85, 0x10, 0x06, 0x10]);
+        assert_eq!((mem.peek(0x10).unwrap(), cpu.reg_accum), (0x00, 0x80));
+        assert_eq!(flags(&cpu), [false, false, true, true]);
+    }
+
+    #[test]
+    fn increments_and_decrements() {
+        //LDX #$FF; INX; LDY #$00; DEY; INC $10; DEC $11
+        let (cpu, mem) = run(&[0xA2, 0xFF, 0xE8, 0xA0, 0x00, 0x88, 0xE6, 0x10, 0xC6, 0x11]);
+        assert_eq!((cpu.reg_index_x, cpu.reg_index_y), (0x00, 0xFF));
+        assert_eq!(mem.peek_n_bytes(0x10, 2).unwrap(), vec![0x01, 0xFF]);
+        assert_eq!(flags(&cpu), [true, false, false, false]);
+        //LDX #$FF; INX wraps to zero
+        let (cpu, _) = run(&[0xA2, 0xFF, 0xE8]);
+        assert_eq!(flags(&cpu), [false, false, true, false]);
+    }
+
+    #[test]
+    fn compares() {
+        //LDA #$40; CMP #$40
+        let (cpu, _) = run(&[0xA9, 0x40, 0xC9, 0x40]);
+        assert_eq!(flags(&cpu), [false, false, true, true]);
+        //LDX #$10; CPX #$20
+        let (cpu, _) = run(&[0xA2, 0x10, 0xE0, 0x20]);
+        assert_eq!(flags(&cpu), [true, false, false, false]);
+        //LDY #$30; CPY #$20
+        let (cpu, _) = run(&[0xA0, 0x30, 0xC0, 0x20]);
+        assert_eq!((cpu.reg_index_y, flags(&cpu)), (0x30, [false, false, false, true]));
+    }
+
+    #[test]
+    fn branches() {
+        //LDX #$03; DEX; BNE back to the DEX
+        let (cpu, _) = run(&[0xA2, 0x03, 0xCA, 0xD0, 0xFD]);
+        assert_eq!(cpu.reg_index_x, 0);
+        //LDA #$80; BMI over LDA #$01
+        let (cpu, _) = run(&[0xA9, 0x80, 0x30, 0x02, 0xA9, 0x01]);
+        assert_eq!(cpu.reg_accum, 0x80);
+        //CLV; BVC over LDX #$01; SEC; BCS over LDY #$01; BEQ not taken into LDA #$01
+        let (cpu, _) = run(&[0xB8, 0x50, 0x02, 0xA2, 0x01, 0x38, 0xB0, 0x02, 0xA0, 0x01, 0xF0, 0x00, 0xA9, 0x01]);
+        assert_eq!((cpu.reg_accum, cpu.reg_index_x, cpu.reg_index_y), (0x01, 0x00, 0x00));
+    }
+
+    #[test]
+    fn jumps_and_subroutines() {
+        //0400: JSR $0408; INX; JMP $040C
+        //0408: LDY #$05; RTS
+        let (cpu, _) = run(&[0x20, 0x08, 0x04, 0xE8, 0x4C, 0x0C, 0x04, 0x00, 0xA0, 0x05, 0x60, 0x00]);
+        assert_eq!((cpu.reg_index_x, cpu.reg_index_y, cpu.reg_sp), (0x01, 0x05, 0xFF));
+    }
+
+    #[test]
+    fn stack_operations() {
+        //LDA #$42; PHA; LDA #$00; PLA sets N and Z from the pulled value
+        let (cpu, _) = run(&[0xA9, 0x42, 0x48, 0xA9, 0x00, 0x68]);
+        assert_eq!((cpu.reg_accum, cpu.reg_sp, flags(&cpu)), (0x42, 0xFF, [false, false, false, false]));
+        //SEC; PHP; CLC; PLP
+        let (cpu, _) = run(&[0x38, 0x08, 0x18, 0x28]);
+        assert_eq!((cpu.reg_ps.carry, cpu.reg_sp), (true, 0xFF));
+    }
+
+    #[test]
+    fn flag_instructions() {
+        //SEC; SED; SEI
+        let (cpu, _) = run(&[0x38, 0xF8, 0x78]);
+        assert!(cpu.reg_ps.carry && cpu.reg_ps.decimal && cpu.reg_ps.interrupt_disable);
+        //...; CLC; CLD; CLI
+        let (cpu, _) = run(&[0x38, 0xF8, 0x78, 0x18, 0xD8, 0x58]);
+        assert!(!cpu.reg_ps.carry && !cpu.reg_ps.decimal && !cpu.reg_ps.interrupt_disable);
+        //LDA #$7F; ADC #$01 overflows; CLV
+        let (cpu, _) = run(&[0xA9, 0x7F, 0x69, 0x01, 0xB8]);
+        assert!(!cpu.reg_ps.overflow);
+    }
+
+    #[test]
+    fn stores_and_txs_leave_flags() {
+        //LDX #$80; LDA #$00 leaves Z set; then STA/STX/STY and TXS of $80
+        let (cpu, _) = run(&[0xA2, 0x80, 0xA9, 0x00, 0x85, 0x10, 0x86, 0x11, 0x84, 0x12, 0x9A]);
+        assert_eq!((cpu.reg_sp, flags(&cpu)), (0x80, [false, false, true, false]));
+        //TXS of zero doesn't set Z either
+        let (cpu, _) = run(&[0xA2, 0x00, 0xA9, 0x80, 0x9A]);
+        assert_eq!((cpu.reg_sp, flags(&cpu)), (0x00, [true, false, false, false]));
+    }
+
+    #[test]
+    fn bit_takes_n_and_v_from_memory() {
+        //LDA #$FF; BIT $10: Z comes from A AND memory, N and V straight from memory
+        for (value, expected) in [(0xC0, [true, true, false, false]), (0x40, [false, true, false, false]), (0x00, [false, false, true, false])] {
+            let (mut cpu, mut mem) = load(Variant::NMOS_6502, &[0xA9, 0xFF, 0x24, 0x10]);
+            mem.write_byte(0x10, value).unwrap();
+            cpu.step(&mut mem).unwrap();
+            cpu.step(&mut mem).unwrap();
+            assert_eq!(flags(&cpu), expected, "{:#04x}", value);
+        }
+    }
+
+    #[test]
+    fn compare_sets_carry() {
+        //LDA #$40 then CMP below, equal to and above A. CMP never touches V.
+        assert_eq!(flags(&run(&[0xA9, 0x40, 0xC9, 0x30]).0), [false, false, false, true]);
+        assert_eq!(flags(&run(&[0xA9, 0x40, 0xC9, 0x40]).0), [false, false, true, true]);
+        assert_eq!(flags(&run(&[0xA9, 0x40, 0xC9, 0x50]).0), [true, false, false, false]);
+        //LDA #$7F; ADC #$01 sets V; CMP #$00 leaves it
+        assert_eq!(flags(&run(&[0xA9, 0x7F, 0x69, 0x01, 0xC9, 0x00]).0), [true, true, false, true]);
+    }
+
+    #[test]
+    fn add_and_subtract_overflow() {
+        //CLC; LDA; ADC: positive + positive going negative, and negative + negative going positive
+        assert_eq!(flags(&run(&[0x18, 0xA9, 0x50, 0x69, 0x50]).0), [true, true, false, false]);
+        assert_eq!(flags(&run(&[0x18, 0xA9, 0x90, 0x69, 0x90]).0), [false, true, false, true]);
+        assert_eq!(flags(&run(&[0x18, 0xA9, 0x50, 0x69, 0x10]).0), [false, false, false, false]);
+        //SEC; LDA; SBC: positive - negative going negative, and negative - positive going positive
+        assert_eq!(flags(&run(&[0x38, 0xA9, 0x50, 0xE9, 0xB0]).0), [true, true, false, false]);
+        assert_eq!(flags(&run(&[0x38, 0xA9, 0xD0, 0xE9, 0x70]).0), [false, true, false, true]);
+        assert_eq!(flags(&run(&[0x38, 0xA9, 0x50, 0xE9, 0x30]).0), [false, false, false, true]);
+    }
+
+    #[test]
+    fn unused_opcode_is_illegal() {
+        //$02 is a JAM and $A7 is LAX, but neither decodes unless undocumented opcodes are turned on
+        for opcode in [0x02, 0xA7] {
+            let (mut cpu, mut mem) = load(Variant::NMOS_6502, &[opcode, 0x00]);
+            assert!(cpu.decode(opcode).is_none());
+            let error = cpu.step(&mut mem).unwrap_err();
+            assert_eq!((error.pc, error.opcode, error.kind), (PROGRAM_START as u32, Some(opcode), CpuErrorKind::UNKNOWN_OPCODE));
+        }
+    }
+
+    //Run prog with undocumented opcodes turned on and $10 holding value, returning the CPU and the new $10
+    fn undocumented(prog: &[u8], value: u8) -> (CPU, u8) {
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, prog);
+        cpu.set_undocumented_opcodes(true);
+        mem.write_byte(0x10, value).unwrap();
+        for _ in 0..prog.len() {
+            if cpu.reg_pc == PROGRAM_START + prog.len() as u16 {
+                break;
+            }
+            cpu.step(&mut mem).unwrap();
+        }
+        let value = mem.peek(0x10).unwrap();
+        (cpu, value)
+    }
+
+    #[test]
+    fn undocumented_option_toggles_decoding() {
+        let mut cpu = CPU::new();
+        assert!(cpu.decode(0xA7).is_none());
+        cpu.set_undocumented_opcodes(true);
+        assert_eq!(cpu.decode(0xA7).unwrap().inst, InstructionTypes::LAX);
+        assert_eq!(cpu.decode(0x02).unwrap().inst, InstructionTypes::JAM);
+        //Documented opcodes decode the same either way
+        assert_eq!(cpu.decode(0xA9).unwrap().inst, InstructionTypes::LDA);
+        cpu.set_undocumented_opcodes(false);
+        assert!(cpu.decode(0xA7).is_none());
+    }
+
+    #[test]
+    fn undocumented_loads_and_stores() {
+        //LAX $10
+        let (cpu, _) = undocumented(&[0xA7, 0x10], 0x80);
+        assert_eq!((cpu.reg_accum, cpu.reg_index_x, flags(&cpu)), (0x80, 0x80, [true, false, false, false]));
+        //LDA #$F0; LDX #$3C; SAX $10 stores A AND X, leaving the flags from LDX
+        let (cpu, value) = undocumented(&[0xA9, 0xF0, 0xA2, 0x3C, 0x87, 0x10], 0x00);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x30, 0xF0, [false, false, false, false]));
+    }
+
+    #[test]
+    fn undocumented_read_modify_write() {
+        //LDA #$40; DCP $10 decrements then compares
+        let (cpu, value) = undocumented(&[0xA9, 0x40, 0xC7, 0x10], 0x41);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x40, 0x40, [false, false, true, true]));
+        //SEC; LDA #$20; ISC $10 increments then subtracts
+        let (cpu, value) = undocumented(&[0x38, 0xA9, 0x20, 0xE7, 0x10], 0x0F);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x10, 0x10, [false, false, false, true]));
+        //LDA #$01; SLO $10 shifts left then ORs
+        let (cpu, value) = undocumented(&[0xA9, 0x01, 0x07, 0x10], 0x81);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x02, 0x03, [false, false, false, true]));
+        //SEC; LDA #$FF; RLA $10 rotates left then ANDs
+        let (cpu, value) = undocumented(&[0x38, 0xA9, 0xFF, 0x27, 0x10], 0x81);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x03, 0x03, [false, false, false, true]));
+        //LDA #$FF; SRE $10 shifts right then EORs
+        let (cpu, value) = undocumented(&[0xA9, 0xFF, 0x47, 0x10], 0x81);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x40, 0xBF, [true, false, false, true]));
+        //SEC; LDA #$10; RRA $10 rotates right then adds, with the rotated out bit as the carry in
+        let (cpu, value) = undocumented(&[0x38, 0xA9, 0x10, 0x67, 0x10], 0x02);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x81, 0x91, [true, false, false, false]));
+    }
+
+    #[test]
+    fn undocumented_immediates() {
+        //LDA #$FF; ANC #$80 copies N into C
+        let (cpu, _) = undocumented(&[0xA9, 0xFF, 0x0B, 0x80], 0);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x80, [true, false, false, true]));
+        //LDA #$FF; ALR #$03 ANDs then shifts right
+        let (cpu, _) = undocumented(&[0xA9, 0xFF, 0x4B, 0x03], 0);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x01, [false, false, false, true]));
+        //SEC; LDA #$C0; ARR #$FF ANDs then rotates right, with C from bit 6 and V from bit 6 XOR bit 5
+        let (cpu, _) = undocumented(&[0x38, 0xA9, 0xC0, 0x6B, 0xFF], 0);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0xE0, [true, false, false, true]));
+        //CLC; LDA #$40; ARR #$FF
+        let (cpu, _) = undocumented(&[0x18, 0xA9, 0x40, 0x6B, 0xFF], 0);
+        assert_eq!((cpu.reg_accum, flags(&cpu)), (0x20, [false, true, false, false]));
+    }
+
+    #[test]
+    fn jam_halts() {
+        //INX; JAM; INX
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, &[0xE8, 0x02, 0xE8]);
+        cpu.set_undocumented_opcodes(true);
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+        assert!(cpu.check_halt());
+        assert_eq!((cpu.reg_pc, cpu.reg_index_x), (PROGRAM_START + 1, 1));
+        //Running on stays stuck on the JAM
+        cpu.run_for_cycles(&mut mem, 10).unwrap();
+        assert_eq!((cpu.reg_pc, cpu.reg_index_x), (PROGRAM_START + 1, 1));
+    }
+
+    //Run count instructions of prog on a 65C02 with $10 holding value, returning the CPU and the new $10
+    fn wdc_65c02(prog: &[u8], value: u8, count: usize) -> (CPU, u8) {
+        let (mut cpu, mut mem) = load(Variant::WDC_65C02, prog);
+        mem.write_byte(0x10, value).unwrap();
+        for _ in 0..count {
+            cpu.step(&mut mem).unwrap();
+        }
+        let value = mem.peek(0x10).unwrap();
+        (cpu, value)
+    }
+
+    #[test]
+    fn wdc_65c02_bra_and_stz() {
+        //BRA over LDA #$01; LDX #$02
+        let (cpu, _) = wdc_65c02(&[0x80, 0x02, 0xA9, 0x01, 0xA2, 0x02], 0, 2);
+        assert_eq!((cpu.reg_accum, cpu.reg_index_x), (0x00, 0x02));
+        //LDA #$FF; STZ $10 stores zero without touching A or the flags
+        let (cpu, value) = wdc_65c02(&[0xA9, 0xFF, 0x64, 0x10], 0x55, 2);
+        assert_eq!((value, cpu.reg_accum, flags(&cpu)), (0x00, 0xFF, [true, false, false, false]));
+    }
+
+    #[test]
+    fn wdc_65c02_test_and_set_reset_bits() {
+        //LDA #$0F; TSB $10: Z is from A AND memory before the bits are set
+        let (cpu, value) = wdc_65c02(&[0xA9, 0x0F, 0x04, 0x10], 0xF0, 2);
+        assert_eq!((value, cpu.reg_ps.zero), (0xFF, true));
+        //LDA #$0F; TRB $10
+        let (cpu, value) = wdc_65c02(&[0xA9, 0x0F, 0x14, 0x10], 0x3C, 2);
+        assert_eq!((value, cpu.reg_ps.zero, cpu.reg_accum), (0x30, false, 0x0F));
+    }
+
+    #[test]
+    fn wdc_65c02_bit_instructions() {
+        //RMB0 $10; SMB7 $10
+        let (_, value) = wdc_65c02(&[0x07, 0x10, 0xF7, 0x10], 0x01, 2);
+        assert_eq!(value, 0x80);
+        //BBR0 $10 branches over LDA #$01 when bit 0 is clear, and falls through when it is set
+        let (cpu, _) = wdc_65c02(&[0x0F, 0x10, 0x02, 0xA9, 0x01, 0xEA], 0xFE, 2);
+        assert_eq!((cpu.reg_pc, cpu.reg_accum), (PROGRAM_START + 6, 0x00));
+        let (cpu, _) = wdc_65c02(&[0x0F, 0x10, 0x02, 0xA9, 0x01, 0xEA], 0x01, 2);
+        assert_eq!((cpu.reg_pc, cpu.reg_accum), (PROGRAM_START + 5, 0x01));
+        //BBS7 $10
+        let (cpu, _) = wdc_65c02(&[0xFF, 0x10, 0x02, 0xA9, 0x01, 0xEA], 0x80, 2);
+        assert_eq!((cpu.reg_pc, cpu.reg_accum), (PROGRAM_START + 6, 0x00));
+    }
+
+    #[test]
+    fn wdc_65c02_wai_and_stp() {
+        //SEI; WAI; INX. With I set, IRQ wakes WAI without being taken.
+        let (mut cpu, mut mem) = load(Variant::WDC_65C02, &[0x78, 0xCB, 0xE8]);
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::IDLE);
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::IDLE);
+        cpu.raise_irq();
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: PROGRAM_START as u32 + 2, opcode: 0xE8, cycles: 2 });
+        //STP; INX
+        let (cpu, _) = wdc_65c02(&[0xDB, 0xE8], 0, 1);
+        assert!(cpu.check_halt());
+        assert_eq!(cpu.reg_index_x, 0);
+    }
+
+    #[test]
+    fn wdc_65c02_indirect_jump_carries() {
+        //JMP ($10FF) reads its high byte from $1100, where the NMOS part would use $1000
+        for (variant, target, cycles) in [(Variant::WDC_65C02, 0x1234, 6), (Variant::NMOS_6502, 0x5634, 5)] {
+            let (mut cpu, mut mem) = load(variant, &[0x6C, 0xFF, 0x10]);
+            for (addr, byte) in [(0x10FF, 0x34), (0x1100, 0x12), (0x1000, 0x56)] {
+                mem.write_byte(addr, byte).unwrap();
+            }
+            cpu.step(&mut mem).unwrap();
+            assert_eq!((cpu.reg_pc, cpu.cycles()), (target, cycles));
+        }
+    }
+
+    //Run SED then a decimal ADC/SBC immediate, returning A, the N V Z C flags and the cycles the ADC/SBC took
+    fn decimal_op(variant: Variant, opcode: u8, accum: u8, operand: u8, carry: bool) -> (u8, [bool; 4], u64) {
+        let (mut cpu, mut mem) = load(variant, &[0xF8, opcode, operand]);
+        cpu.reg_accum = accum;
+        cpu.reg_ps.carry = carry;
+        cpu.step(&mut mem).unwrap();
+        let before = cpu.cycles();
+        cpu.step(&mut mem).unwrap();
+        let flags = [cpu.reg_ps.negative, cpu.reg_ps.overflow, cpu.reg_ps.zero, cpu.reg_ps.carry];
+        (cpu.reg_accum, flags, cpu.cycles() - before)
+    }
+
+    const ADC: u8 = 0x69;
+    const SBC: u8 = 0xE9;
+
+    #[test]
+    fn decimal_nmos() {
+        //N, V and Z come from the binary sum, so 99+01 shows N set and Z clear despite the 00 result
+        assert_eq!(decimal_op(Variant::NMOS_6502, ADC, 0x99, 0x01, false), (0x00, [true, false, false, true], 2));
+        assert_eq!(decimal_op(Variant::NMOS_6502, ADC, 0x50, 0x50, false), (0x00, [true, true, false, true], 2));
+        assert_eq!(decimal_op(Variant::NMOS_6502, ADC, 0x12, 0x34, false), (0x46, [false, false, false, false], 2));
+        assert_eq!(decimal_op(Variant::NMOS_6502, ADC, 0x58, 0x46, true), (0x05, [true, true, false, true], 2));
+        assert_eq!(decimal_op(Variant::NMOS_6502, SBC, 0x00, 0x01, true), (0x99, [true, false, false, false], 2));
+        assert_eq!(decimal_op(Variant::NMOS_6502, SBC, 0x46, 0x12, true), (0x34, [false, false, false, true], 2));
+        assert_eq!(decimal_op(Variant::NMOS_6502, SBC, 0x32, 0x02, false), (0x29, [false, false, false, true], 2));
+    }
+
+    #[test]
+    fn decimal_65c02() {
+        //N and Z reflect the decimal result, at the cost of an extra cycle
+        assert_eq!(decimal_op(Variant::WDC_65C02, ADC, 0x99, 0x01, false), (0x00, [false, false, true, true], 3));
+        assert_eq!(decimal_op(Variant::WDC_65C02, ADC, 0x50, 0x50, false), (0x00, [false, true, true, true], 3));
+        assert_eq!(decimal_op(Variant::WDC_65C02, SBC, 0x00, 0x01, true), (0x99, [true, false, false, false], 3));
+    }
+
+    #[test]
+    fn decimal_2a03() {
+        //The decimal flag is ignored, so these are plain binary
+        assert_eq!(decimal_op(Variant::RICOH_2A03, ADC, 0x99, 0x01, false), (0x9A, [true, false, false, false], 2));
+        assert_eq!(decimal_op(Variant::RICOH_2A03, ADC, 0x50, 0x50, false), (0xA0, [true, true, false, false], 2));
+        assert_eq!(decimal_op(Variant::RICOH_2A03, SBC, 0x00, 0x01, true), (0xFF, [true, false, false, false], 2));
+    }
+
+    //Vectors: reset to PROGRAM_START, IRQ/BRK to $0500 and NMI to $0600. Both handlers are INX; RTI.
+    fn load_with_vectors(prog: &[u8]) -> (CPU, Memory) {
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, prog);
+        for (addr, byte) in [(0xFFFA, 0x00), (0xFFFB, 0x06), (0xFFFC, 0x00), (0xFFFD, 0x04), (0xFFFE, 0x00), (0xFFFF, 0x05)] {
+            mem.write_byte(addr, byte).unwrap();
+        }
+        for handler in [0x0500, 0x0600] {
+            mem.write_byte(handler, 0xE8).unwrap();
+            mem.write_byte(handler + 1, 0x40).unwrap();
+        }
+        cpu.reg_pc = 0;
+        (cpu, mem)
+    }
+
+    #[test]
+    fn reset_fetches_vector() {
+        let (mut cpu, mut mem) = load_with_vectors(&[0xEA]);
+        cpu.reg_ps.interrupt_disable = false;
+        let sp = cpu.reg_sp;
+        cpu.reset(&mut mem);
+        assert_eq!(cpu.reg_pc, PROGRAM_START);
+        assert!(cpu.reg_ps.interrupt_disable);
+        //Reset goes through the motions of pushing without writing, so SP drops by three
+        assert_eq!(cpu.reg_sp, sp.wrapping_sub(3));
+        assert_eq!(cpu.cycles(), INTERRUPT_CYCLES as u64);
+    }
+
+    #[test]
+    fn irq_held_off_while_masked() {
+        //SEI; NOP; CLI; NOP
+        let (mut cpu, mut mem) = load_with_vectors(&[0x78, 0xEA, 0x58, 0xEA, 0xEA]);
+        cpu.reset(&mut mem);
+        cpu.raise_irq();
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: 0x0400, opcode: 0x78, cycles: 2 });
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: 0x0401, opcode: 0xEA, cycles: 2 });
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: 0x0402, opcode: 0x58, cycles: 2 });
+        //The instruction after CLI runs before the IRQ is taken
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: 0x0403, opcode: 0xEA, cycles: 2 });
+        let sp = cpu.reg_sp;
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INTERRUPT { vector: IRQ_BRK_VECTOR, cycles: 7 });
+        assert_eq!(cpu.reg_pc, 0x0500);
+        assert!(cpu.reg_ps.interrupt_disable);
+        //Return address then P, with B clear and bit 5 set
+        let pushed = |addr: u8| mem.read_byte(STACK_BASE | addr as u16);
+        assert_eq!((pushed(sp), pushed(sp.wrapping_sub(1))), (0x04, 0x04));
+        assert_eq!(pushed(sp.wrapping_sub(2)) & (FLAG_BREAK | FLAG_UNUSED), FLAG_UNUSED);
+        assert_eq!(cpu.reg_sp, sp.wrapping_sub(3));
+        //The handler runs with I set, so the still asserted line doesn't interrupt it
+        cpu.step(&mut mem).unwrap();
+        cpu.clear_irq();
+        cpu.step(&mut mem).unwrap();
+        assert_eq!((cpu.reg_pc, cpu.reg_index_x, cpu.reg_ps.interrupt_disable), (0x0404, 1, false));
+    }
+
+    #[test]
+    fn irq_taken_after_sei() {
+        //CLI; SEI; NOP. The IRQ was polled before SEI set I, so it is still taken straight after it.
+        let (mut cpu, mut mem) = load_with_vectors(&[0x58, 0x78, 0xEA]);
+        cpu.reset(&mut mem);
+        cpu.step(&mut mem).unwrap();
+        cpu.raise_irq();
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: 0x0401, opcode: 0x78, cycles: 2 });
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INTERRUPT { vector: IRQ_BRK_VECTOR, cycles: 7 });
+        //SEI has finished by then, so the pushed P has I set
+        assert_eq!(mem.read_byte(STACK_BASE | cpu.reg_sp.wrapping_add(1) as u16) & FLAG_INTERRUPT_DISABLE, FLAG_INTERRUPT_DISABLE);
+    }
+
+    #[test]
+    fn nmi_ignores_mask() {
+        let (mut cpu, mut mem) = load_with_vectors(&[0xEA, 0xEA]);
+        cpu.reset(&mut mem);
+        cpu.raise_nmi();
+        //Raised between instructions, it is polled during the next one and taken after it
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: 0x0400, opcode: 0xEA, cycles: 2 });
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INTERRUPT { vector: NMI_VECTOR, cycles: 7 });
+        assert_eq!(cpu.reg_pc, 0x0600);
+        assert_eq!(mem.read_byte(STACK_BASE | cpu.reg_sp.wrapping_add(1) as u16) & (FLAG_BREAK | FLAG_UNUSED), FLAG_UNUSED);
+        //Edge triggered, so it is serviced once
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::INSTRUCTION { pc: 0x0401, opcode: 0xEA, cycles: 2 });
+    }
+
+    #[test]
+    fn brk_pushes_break_flag() {
+        let (mut cpu, mut mem) = load_with_vectors(&[0x00, 0xEA]);
+        cpu.reset(&mut mem);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.reg_pc, 0x0500);
+        assert_eq!(mem.read_byte(STACK_BASE | cpu.reg_sp.wrapping_add(1) as u16) & (FLAG_BREAK | FLAG_UNUSED), FLAG_BREAK | FLAG_UNUSED);
+        //BRK skips a padding byte, so RTI returns two bytes on
+        assert_eq!(mem.read_byte(STACK_BASE | cpu.reg_sp.wrapping_add(2) as u16), 0x02);
+    }
+
+    #[test]
+    fn stack_wraps_in_page_one() {
+        //LDX #$00; TXS; LDA #$42; PHA writes $0100 and wraps SP round to $FF
+        let (cpu, mem) = run(&[0xA2, 0x00, 0x9A, 0xA9, 0x42, 0x48]);
+        assert_eq!((mem.peek(0x0100).unwrap(), cpu.reg_sp), (0x42, 0xFF));
+        //...; LDA #$00; PLA wraps SP back to $00 and reads $0100, not $0200
+        let (cpu, _) = run(&[0xA2, 0x00, 0x9A, 0xA9, 0x42, 0x48, 0xA9, 0x00, 0x68]);
+        assert_eq!((cpu.reg_accum, cpu.reg_sp), (0x42, 0x00));
+        //LDX #$00; TXS; JSR $0408 splits the return address across $0100 and $01FF
+        let (cpu, mem) = run(&[0xA2, 0x00, 0x9A, 0x20, 0x08, 0x04, 0x00, 0x00]);
+        assert_eq!((mem.peek(0x0100).unwrap(), mem.peek(0x01FF).unwrap(), cpu.reg_sp), (0x04, 0x05, 0xFE));
+    }
+
+    #[test]
+    fn php_pushes_break_flag() {
+        //CLC; PHP
+        let (cpu, mem) = run(&[0x18, 0x08]);
+        assert_eq!(mem.peek(0x01FF).unwrap(), FLAG_ZERO | FLAG_INTERRUPT_DISABLE | FLAG_BREAK | FLAG_UNUSED);
+        assert_eq!(cpu.get_status_reg_byte(), FLAG_ZERO | FLAG_INTERRUPT_DISABLE | FLAG_UNUSED);
+    }
+
+    #[test]
+    fn pulled_status_ignores_break_and_unused() {
+        //LDA #$30; PHA; LDA #$FF; PLP pulls just B and bit 5, which don't exist in P
+        let (cpu, _) = run(&[0xA9, 0x30, 0x48, 0xA9, 0xFF, 0x28]);
+        assert_eq!(cpu.reg_ps, StatusFlags::from_byte(0));
+        assert_eq!(cpu.get_status_reg_byte(), FLAG_UNUSED);
+        //RTI to $0410 with the same pulled P: LDA #$04; PHA; LDA #$10; PHA; LDA #$30; PHA; RTI
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, &[0xA9, 0x04, 0x48, 0xA9, 0x10, 0x48, 0xA9, 0x30, 0x48, 0x40]);
+        for _ in 0..7 {
+            cpu.step(&mut mem).unwrap();
+        }
+        assert_eq!((cpu.reg_pc, cpu.reg_ps), (0x0410, StatusFlags::from_byte(0)));
+    }
+
+    //Raises IRQ once a number of cycles have passed, and holds it until it is acknowledged by a read
+    struct Timer {
+        countdown: u32,
+        asserted: bool
+    }
+
+    impl Device for Timer {
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.asserted = false;
+            0
+        }
+
+        fn write(&mut self, _offset: u16, _data: u8) {}
+
+        fn peek(&self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn tick(&mut self) {
+            if self.countdown > 0 {
+                self.countdown -= 1;
+                self.asserted = self.countdown == 0;
+            }
+        }
+
+        fn irq(&self) -> bool {
+            self.asserted
+        }
+    }
+
+    //Number of IRQs taken while running a loop with a timer mapped at $D000, stepping or ticking
+    fn timer_interrupts(ticking: bool) -> (usize, u8) {
+        //CLI, then INX in a loop. The handler acknowledges the timer and counts the interrupt in Y.
+        let (mut cpu, mut mem) = load_with_vectors(&[0x58, 0xE8, 0x4C, 0x01, 0x04]);
+        for (offset, byte) in [0xAD, 0x00, 0xD0, 0xC8, 0x40].iter().enumerate() {
+            mem.write_byte(0x0500 + offset as u16, *byte).unwrap();
+        }
+        mem.map_device(0xD000, 0xD000, Box::new(Timer { countdown: 20, asserted: false })).unwrap();
+        cpu.reset(&mut mem);
+        let mut interrupts = 0;
+        for _ in 0..100 {
+            let outcome = if ticking {
+                let mut outcome = None;
+                while outcome.is_none() {
+                    outcome = cpu.tick_cycle(&mut mem).unwrap();
+                }
+                outcome.unwrap()
+            }else{
+                cpu.step(&mut mem).unwrap()
+            };
+            if outcome == (StepOutcome::INTERRUPT { vector: IRQ_BRK_VECTOR, cycles: INTERRUPT_CYCLES }) {
+                interrupts += 1;
+            }
+        }
+        (interrupts, cpu.reg_index_y)
+    }
+
+    #[test]
+    fn device_irq() {
+        assert_eq!(timer_interrupts(false), (1, 1));
+        assert_eq!(timer_interrupts(true), (1, 1));
+    }
+
+    //Cycles taken by each of the first count instructions of prog, loaded at start
+    fn instruction_cycles(start: u16, prog: &[u8], index_x: u8, count: usize) -> Vec<u64> {
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, &[]);
+        for (offset, byte) in prog.iter().enumerate() {
+            mem.write_byte(start + offset as u16, *byte).unwrap();
+        }
+        cpu.reg_pc = start;
+        cpu.reg_index_x = index_x;
+        (0..count).map(|_| {
+            let before = cpu.cycles();
+            cpu.step(&mut mem).unwrap();
+            cpu.cycles() - before
+        }).collect()
+    }
+
+    #[test]
+    fn page_cross_penalty() {
+        //LDA $0410,X stays in page 4, LDA $04FF,X crosses into page 5
+        assert_eq!(instruction_cycles(PROGRAM_START, &[0xBD, 0x10, 0x04], 0x01, 1), vec![4]);
+        assert_eq!(instruction_cycles(PROGRAM_START, &[0xBD, 0xFF, 0x04], 0x01, 1), vec![5]);
+        //Stores always take the extra cycle
+        assert_eq!(instruction_cycles(PROGRAM_START, &[0x9D, 0x10, 0x04], 0x01, 1), vec![5]);
+    }
+
+    #[test]
+    fn branch_cycles() {
+        //LDX #0 then BEQ: not taken, taken within the page, and taken across a page
+        assert_eq!(instruction_cycles(PROGRAM_START, &[0xA2, 0x01, 0xF0, 0x10], 0, 2), vec![2, 2]);
+        assert_eq!(instruction_cycles(PROGRAM_START, &[0xA2, 0x00, 0xF0, 0x10], 0, 2), vec![2, 3]);
+        //The branch at $04FB lands at $050D
+        assert_eq!(instruction_cycles(0x04F9, &[0xA2, 0x00, 0xF0, 0x10], 0, 2), vec![2, 4]);
+        //Backwards across a page
+        assert_eq!(instruction_cycles(0x0500, &[0xA2, 0x00, 0xF0, 0xF0], 0, 2), vec![2, 4]);
+
+        let (mut cpu, mut mem) = load(Variant::NMOS_6502, &[]);
+        for (offset, byte) in [0xA2, 0x00, 0xF0, 0x10].iter().enumerate() {
+            mem.write_byte(0x04F9 + offset as u16, *byte).unwrap();
+        }
+        cpu.reg_pc = 0x04F9;
+        assert_eq!(cpu.run_for_cycles(&mut mem, 6).unwrap(), 6);
+        assert_eq!((cpu.reg_pc, cpu.cycles()), (0x050D, 6));
+    }
+
+    //2K of RAM mirrored across the whole address space, like the NES's internal RAM
+    struct MirroredBus {
+        ram: [u8; 0x0800]
+    }
+
+    impl Bus for MirroredBus {
+        fn read(&mut self, addr: u16) -> Result<u8, MemoryError> {
+            self.peek(addr)
+        }
+
+        fn write(&mut self, addr: u16, data: u8) -> Result<u8, MemoryError> {
+            self.ram[addr as usize & 0x07FF] = data;
+            Ok(data)
+        }
+
+        fn peek(&self, addr: u16) -> Result<u8, MemoryError> {
+            Ok(self.ram[addr as usize & 0x07FF])
+        }
+    }
+
+    #[test]
+    fn custom_bus() {
+        //LDA #$42; STA $0810; LDX $1010; JMP $0400, where $0810 and $1010 are both mirrors of $0010
+        let mut bus = MirroredBus { ram: [0; 0x0800] };
+        bus.ram[0x0400..0x040B].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x10, 0x08, 0xAE, 0x10, 0x10, 0x4C, 0x00, 0x04]);
+        let mut cpu = CPU::new();
+        cpu.reg_pc = 0x4400;
+        for _ in 0..4 {
+            cpu.step(&mut bus).unwrap();
+        }
+        assert_eq!(bus.ram[0x0010], 0x42);
+        assert_eq!(cpu.reg_index_x, 0x42);
+        assert_eq!(cpu.reg_pc, 0x0400);
+        assert_eq!(cpu.cycles(), 2 + 4 + 4 + 3);
+    }
+
+    //Memory that logs every access the CPU makes, as (write, address, data)
+    struct RecordingBus {
+        mem: Memory,
+        accesses: Vec<(bool, u16, u8)>
+    }
+
+    impl Bus for RecordingBus {
+        fn read(&mut self, addr: u16) -> Result<u8, MemoryError> {
+            let data = self.mem.read(addr)?;
+            self.accesses.push((false, addr, data));
+            Ok(data)
+        }
+
+        fn write(&mut self, addr: u16, data: u8) -> Result<u8, MemoryError> {
+            self.accesses.push((true, addr, data));
+            self.mem.write(addr, data)
+        }
+
+        fn peek(&self, addr: u16) -> Result<u8, MemoryError> {
+            self.mem.peek(addr)
+        }
+    }
+
+    //A loop covering zero page, indexed, indirect, stack and subroutine accesses, with an NMI handler
+    fn equivalence_program() -> (CPU, RecordingBus) {
+        //0400: LDX #3; LDY #1
+        //0404: INC $10,X; LDA ($20),Y; STA $0300,X; JSR $0420; DEX; BNE $0404; BRK
+        //0420: PHA; ASL A; PLA; RTS
+        //0430: PHA; LDA #$AA; STA $40; PLA; RTI
+        let (_, mut mem) = load_with_vectors(&[
+            0xA2, 0x03, 0xA0, 0x01,
+            0xF6, 0x10, 0xB1, 0x20, 0x9D, 0x00, 0x03, 0x20, 0x20, 0x04, 0xCA, 0xD0, 0xF3, 0x00
+        ]);
+        for (offset, byte) in [0x48, 0x0A, 0x68, 0x60].iter().enumerate() {
+            mem.write_byte(0x0420 + offset as u16, *byte).unwrap();
+        }
+        for (offset, byte) in [0x48, 0xA9, 0xAA, 0x85, 0x40, 0x68, 0x40].iter().enumerate() {
+            mem.write_byte(0x0430 + offset as u16, *byte).unwrap();
+        }
+        mem.write_byte(0xFFFA, 0x30).unwrap();
+        mem.write_byte(0xFFFB, 0x04).unwrap();
+        mem.write_byte(0x20, 0xFF).unwrap();
+        mem.write_byte(0x21, 0x01).unwrap();
+        let mut cpu = CPU::new();
+        cpu.reg_pc = PROGRAM_START;
+        (cpu, RecordingBus { mem, accesses: Vec::new() })
+    }
+
+    fn assert_same_state(stepped: &(CPU, RecordingBus), ticked: &(CPU, RecordingBus)) {
+        let registers = |cpu: &CPU| (cpu.reg_pc, cpu.reg_sp, cpu.reg_accum, cpu.reg_index_x, cpu.reg_index_y, cpu.get_status_reg_byte(), cpu.cycles());
+        assert_eq!(registers(&stepped.0), registers(&ticked.0));
+        assert_eq!(stepped.1.accesses, ticked.1.accesses);
+        assert_eq!(stepped.1.mem.peek_n_bytes(0, 0x0800).unwrap(), ticked.1.mem.peek_n_bytes(0, 0x0800).unwrap());
+    }
+
+    #[test]
+    fn tick_matches_step() {
+        //Raise an NMI two cycles into the second INC $10,X, the 13th instruction
+        const NMI_DURING: usize = 12;
+
+        let mut stepped = equivalence_program();
+        let mut ticked = equivalence_program();
+        for instruction in 0..24 {
+            //Stepping can only raise it before the instruction the ticks are part way through
+            if instruction == NMI_DURING {
+                stepped.0.raise_nmi();
+            }
+            let outcome = stepped.0.step(&mut stepped.1).unwrap();
+
+            let mut ticks = 0;
+            loop {
+                if instruction == NMI_DURING && ticks == 2 {
+                    ticked.0.raise_nmi();
+                }
+                ticks += 1;
+                if ticked.0.tick(&mut ticked.1).unwrap() {
+                    break;
+                }
+            }
+
+            let cycles = match outcome {
+                StepOutcome::INSTRUCTION { cycles, .. } | StepOutcome::INTERRUPT { cycles, .. } => cycles,
+                StepOutcome::IDLE => 1
+            };
+            assert_eq!(ticks, cycles, "instruction {}", instruction);
+            assert_same_state(&stepped, &ticked);
+            if instruction == NMI_DURING {
+                assert_eq!(outcome, StepOutcome::INSTRUCTION { pc: 0x0404, opcode: 0xF6, cycles: 6 });
+            }else if instruction == NMI_DURING + 1 {
+                assert_eq!(outcome, StepOutcome::INTERRUPT { vector: NMI_VECTOR, cycles: 7 });
+            }
+        }
+        assert_eq!(stepped.1.mem.read_byte(0x40), 0xAA);
+    }
+}
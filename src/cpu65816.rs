@@ -0,0 +1,1526 @@
+use crate::log;
+use crate::logging::{Level, Subsystem};
+use crate::bus::Bus24;
+use crate::cpu::{CpuError, CpuErrorKind, StepOutcome};
+use crate::addressing::AddressingMode;
+use crate::opcodes::{self, InstructionTypes, OpcodeInfo};
+use crate::status::StatusFlags;
+
+//Emulation mode uses the 6502's vectors (plus COP), native mode has its own set just below them
+const EMULATION_COP_VECTOR: u16 = 0xFFF4;
+const EMULATION_NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const EMULATION_IRQ_BRK_VECTOR: u16 = 0xFFFE;
+const NATIVE_COP_VECTOR: u16 = 0xFFE4;
+const NATIVE_BRK_VECTOR: u16 = 0xFFE6;
+const NATIVE_NMI_VECTOR: u16 = 0xFFEA;
+const NATIVE_IRQ_VECTOR: u16 = 0xFFEE;
+const INTERRUPT_CYCLES: u16 = 7;
+
+//In native mode the status bits the 6502 leaves unused hold the register width flags
+const FLAG_MEMORY_WIDTH: u8 = 0x20;
+const FLAG_INDEX_WIDTH: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressingMode65816 {
+    IMPLIED,
+    ACCUMULATOR,
+    IMMEDIATE_M,                //Immediate sized by the M flag
+    IMMEDIATE_X,                //Immediate sized by the X flag
+    IMMEDIATE_8,                //Always one byte (REP, SEP and the BRK/COP/WDM signature bytes)
+    DIRECT,
+    DIRECT_X,
+    DIRECT_Y,
+    DIRECT_INDIRECT,
+    DIRECT_INDIRECT_X,
+    DIRECT_INDIRECT_Y,
+    DIRECT_INDIRECT_LONG,
+    DIRECT_INDIRECT_LONG_Y,
+    ABSOLUTE,
+    ABSOLUTE_X,
+    ABSOLUTE_Y,
+    ABSOLUTE_LONG,
+    ABSOLUTE_LONG_X,
+    ABSOLUTE_INDIRECT,
+    ABSOLUTE_INDIRECT_X,
+    ABSOLUTE_INDIRECT_LONG,
+    STACK_RELATIVE,
+    STACK_RELATIVE_INDIRECT_Y,
+    RELATIVE,
+    RELATIVE_LONG,
+    BLOCK_MOVE
+}
+
+impl AddressingMode65816 {
+    //Number of operand bytes following the opcode, which for immediates depends on the register widths
+    pub fn num_operand_bytes(&self, flag_m: bool, flag_x: bool) -> u16 {
+        match self {
+            AddressingMode65816::IMPLIED | AddressingMode65816::ACCUMULATOR => 0,
+            AddressingMode65816::IMMEDIATE_M => if flag_m { 1 } else { 2 },
+            AddressingMode65816::IMMEDIATE_X => if flag_x { 1 } else { 2 },
+            AddressingMode65816::IMMEDIATE_8 | AddressingMode65816::DIRECT | AddressingMode65816::DIRECT_X
+            | AddressingMode65816::DIRECT_Y | AddressingMode65816::DIRECT_INDIRECT | AddressingMode65816::DIRECT_INDIRECT_X
+            | AddressingMode65816::DIRECT_INDIRECT_Y | AddressingMode65816::DIRECT_INDIRECT_LONG
+            | AddressingMode65816::DIRECT_INDIRECT_LONG_Y | AddressingMode65816::STACK_RELATIVE
+            | AddressingMode65816::STACK_RELATIVE_INDIRECT_Y | AddressingMode65816::RELATIVE => 1,
+            AddressingMode65816::ABSOLUTE | AddressingMode65816::ABSOLUTE_X | AddressingMode65816::ABSOLUTE_Y
+            | AddressingMode65816::ABSOLUTE_INDIRECT | AddressingMode65816::ABSOLUTE_INDIRECT_X
+            | AddressingMode65816::ABSOLUTE_INDIRECT_LONG | AddressingMode65816::RELATIVE_LONG
+            | AddressingMode65816::BLOCK_MOVE => 2,
+            AddressingMode65816::ABSOLUTE_LONG | AddressingMode65816::ABSOLUTE_LONG_X => 3
+        }
+    }
+
+    fn uses_direct_page(&self) -> bool {
+        matches!(self, AddressingMode65816::DIRECT | AddressingMode65816::DIRECT_X | AddressingMode65816::DIRECT_Y
+            | AddressingMode65816::DIRECT_INDIRECT | AddressingMode65816::DIRECT_INDIRECT_X
+            | AddressingMode65816::DIRECT_INDIRECT_Y | AddressingMode65816::DIRECT_INDIRECT_LONG
+            | AddressingMode65816::DIRECT_INDIRECT_LONG_Y)
+    }
+}
+
+//An instruction the 65816 decodes: one it shares with the 6502 family, or one only it has. Keeping
+//its own instructions out of InstructionTypes means the 6502 core never has to handle them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstructionTypes65816 {
+    SHARED(InstructionTypes),
+    BRL,
+    COP,
+    JML,
+    JSL,
+    MVN,
+    MVP,
+    PEA,
+    PEI,
+    PER,
+    PHB,
+    PHD,
+    PHK,
+    PLB,
+    PLD,
+    REP,
+    RTL,
+    SEP,
+    TCD,
+    TCS,
+    TDC,
+    TSC,
+    TXY,
+    TYX,
+    WDM,
+    XBA,
+    XCE
+}
+
+impl InstructionTypes65816 {
+    pub fn mnemonic(&self) -> String {
+        match self {
+            InstructionTypes65816::SHARED(inst) => inst.mnemonic(),
+            _ => format!("{:?}", self)
+        }
+    }
+
+    //Stores and read-modify-write instructions, none of which are the 65816's own
+    pub fn writes_operand(&self) -> bool {
+        matches!(self, InstructionTypes65816::SHARED(inst) if inst.writes_operand())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OpcodeInfo65816 {
+    pub inst: InstructionTypes65816,
+    pub mode: AddressingMode65816,
+    pub base_cycles: u16        //Cycles with 8 bit registers and the direct page aligned to a page
+}
+
+const fn op65816(inst: InstructionTypes65816, mode: AddressingMode65816, base_cycles: u16) -> OpcodeInfo65816 {
+    OpcodeInfo65816 { inst, mode, base_cycles }
+}
+
+const fn shared(inst: InstructionTypes, mode: AddressingMode65816, base_cycles: u16) -> OpcodeInfo65816 {
+    op65816(InstructionTypes65816::SHARED(inst), mode, base_cycles)
+}
+
+//The 65816 runs every 6502 and 65C02 instruction with the same encoding, so its table is built from
+//theirs. These are the opcodes it adds in the 65C02's NOP and bit instruction slots, plus the few
+//whose addressing or timing it changes.
+const ADDITIONS_65816: [(u8, OpcodeInfo65816); 82] = [
+    //The signature byte after BRK is part of the instruction
+    (0x00, shared(InstructionTypes::BRK, AddressingMode65816::IMMEDIATE_8, 7)),
+    (0x02, op65816(InstructionTypes65816::COP, AddressingMode65816::IMMEDIATE_8, 7)),
+    (0x03, shared(InstructionTypes::ORA, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0x07, shared(InstructionTypes::ORA, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0x0B, op65816(InstructionTypes65816::PHD, AddressingMode65816::IMPLIED, 4)),
+    (0x0F, shared(InstructionTypes::ORA, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0x13, shared(InstructionTypes::ORA, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0x17, shared(InstructionTypes::ORA, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0x1B, op65816(InstructionTypes65816::TCS, AddressingMode65816::IMPLIED, 2)),
+    //Indexed read-modify-writes always spend the fix-up cycle, as on the NMOS 6502
+    (0x1E, shared(InstructionTypes::ASL, AddressingMode65816::ABSOLUTE_X, 7)),
+    (0x1F, shared(InstructionTypes::ORA, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+    (0x22, op65816(InstructionTypes65816::JSL, AddressingMode65816::ABSOLUTE_LONG, 8)),
+    (0x23, shared(InstructionTypes::AND, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0x27, shared(InstructionTypes::AND, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0x2B, op65816(InstructionTypes65816::PLD, AddressingMode65816::IMPLIED, 5)),
+    (0x2F, shared(InstructionTypes::AND, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0x33, shared(InstructionTypes::AND, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0x37, shared(InstructionTypes::AND, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0x3B, op65816(InstructionTypes65816::TSC, AddressingMode65816::IMPLIED, 2)),
+    (0x3E, shared(InstructionTypes::ROL, AddressingMode65816::ABSOLUTE_X, 7)),
+    (0x3F, shared(InstructionTypes::AND, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+    (0x42, op65816(InstructionTypes65816::WDM, AddressingMode65816::IMMEDIATE_8, 2)),
+    (0x43, shared(InstructionTypes::EOR, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0x44, op65816(InstructionTypes65816::MVP, AddressingMode65816::BLOCK_MOVE, 7)),
+    (0x47, shared(InstructionTypes::EOR, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0x4B, op65816(InstructionTypes65816::PHK, AddressingMode65816::IMPLIED, 3)),
+    (0x4F, shared(InstructionTypes::EOR, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0x53, shared(InstructionTypes::EOR, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0x54, op65816(InstructionTypes65816::MVN, AddressingMode65816::BLOCK_MOVE, 7)),
+    (0x57, shared(InstructionTypes::EOR, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0x5B, op65816(InstructionTypes65816::TCD, AddressingMode65816::IMPLIED, 2)),
+    (0x5C, op65816(InstructionTypes65816::JML, AddressingMode65816::ABSOLUTE_LONG, 4)),
+    (0x5E, shared(InstructionTypes::LSR, AddressingMode65816::ABSOLUTE_X, 7)),
+    (0x5F, shared(InstructionTypes::EOR, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+    (0x62, op65816(InstructionTypes65816::PER, AddressingMode65816::RELATIVE_LONG, 6)),
+    (0x63, shared(InstructionTypes::ADC, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0x67, shared(InstructionTypes::ADC, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0x6B, op65816(InstructionTypes65816::RTL, AddressingMode65816::IMPLIED, 6)),
+    //JMP (abs) drops the 65C02's extra cycle
+    (0x6C, shared(InstructionTypes::JMP, AddressingMode65816::ABSOLUTE_INDIRECT, 5)),
+    (0x6F, shared(InstructionTypes::ADC, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0x73, shared(InstructionTypes::ADC, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0x77, shared(InstructionTypes::ADC, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0x7B, op65816(InstructionTypes65816::TDC, AddressingMode65816::IMPLIED, 2)),
+    (0x7E, shared(InstructionTypes::ROR, AddressingMode65816::ABSOLUTE_X, 7)),
+    (0x7F, shared(InstructionTypes::ADC, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+    (0x82, op65816(InstructionTypes65816::BRL, AddressingMode65816::RELATIVE_LONG, 4)),
+    (0x83, shared(InstructionTypes::STA, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0x87, shared(InstructionTypes::STA, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0x8B, op65816(InstructionTypes65816::PHB, AddressingMode65816::IMPLIED, 3)),
+    (0x8F, shared(InstructionTypes::STA, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0x93, shared(InstructionTypes::STA, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0x97, shared(InstructionTypes::STA, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0x9B, op65816(InstructionTypes65816::TXY, AddressingMode65816::IMPLIED, 2)),
+    (0x9F, shared(InstructionTypes::STA, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+    (0xA3, shared(InstructionTypes::LDA, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0xA7, shared(InstructionTypes::LDA, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0xAB, op65816(InstructionTypes65816::PLB, AddressingMode65816::IMPLIED, 4)),
+    (0xAF, shared(InstructionTypes::LDA, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0xB3, shared(InstructionTypes::LDA, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0xB7, shared(InstructionTypes::LDA, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0xBB, op65816(InstructionTypes65816::TYX, AddressingMode65816::IMPLIED, 2)),
+    (0xBF, shared(InstructionTypes::LDA, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+    (0xC2, op65816(InstructionTypes65816::REP, AddressingMode65816::IMMEDIATE_8, 3)),
+    (0xC3, shared(InstructionTypes::CMP, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0xC7, shared(InstructionTypes::CMP, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0xCF, shared(InstructionTypes::CMP, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0xD3, shared(InstructionTypes::CMP, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0xD4, op65816(InstructionTypes65816::PEI, AddressingMode65816::DIRECT_INDIRECT, 6)),
+    (0xD7, shared(InstructionTypes::CMP, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0xDC, op65816(InstructionTypes65816::JML, AddressingMode65816::ABSOLUTE_INDIRECT_LONG, 6)),
+    (0xDF, shared(InstructionTypes::CMP, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+    (0xE2, op65816(InstructionTypes65816::SEP, AddressingMode65816::IMMEDIATE_8, 3)),
+    (0xE3, shared(InstructionTypes::SBC, AddressingMode65816::STACK_RELATIVE, 4)),
+    (0xE7, shared(InstructionTypes::SBC, AddressingMode65816::DIRECT_INDIRECT_LONG, 6)),
+    (0xEB, op65816(InstructionTypes65816::XBA, AddressingMode65816::IMPLIED, 3)),
+    (0xEF, shared(InstructionTypes::SBC, AddressingMode65816::ABSOLUTE_LONG, 5)),
+    (0xF3, shared(InstructionTypes::SBC, AddressingMode65816::STACK_RELATIVE_INDIRECT_Y, 7)),
+    (0xF4, op65816(InstructionTypes65816::PEA, AddressingMode65816::ABSOLUTE, 5)),
+    (0xF7, shared(InstructionTypes::SBC, AddressingMode65816::DIRECT_INDIRECT_LONG_Y, 6)),
+    (0xFB, op65816(InstructionTypes65816::XCE, AddressingMode65816::IMPLIED, 2)),
+    (0xFC, shared(InstructionTypes::JSR, AddressingMode65816::ABSOLUTE_INDIRECT_X, 8)),
+    (0xFF, shared(InstructionTypes::SBC, AddressingMode65816::ABSOLUTE_LONG_X, 5)),
+];
+
+//The 6502 addressing mode an instruction was decoded with, widened to the 65816's. Immediates are
+//sized by the register the instruction loads or compares.
+const fn mode_65816(inst: InstructionTypes, mode: AddressingMode) -> AddressingMode65816 {
+    match mode {
+        AddressingMode::IMPLIED => AddressingMode65816::IMPLIED,
+        AddressingMode::ACCUMULATOR => AddressingMode65816::ACCUMULATOR,
+        AddressingMode::IMMEDIATE => {
+            if matches!(inst, InstructionTypes::LDX | InstructionTypes::LDY | InstructionTypes::CPX | InstructionTypes::CPY) {
+                AddressingMode65816::IMMEDIATE_X
+            }else{
+                AddressingMode65816::IMMEDIATE_M
+            }
+        },
+        AddressingMode::ZERO_PAGE => AddressingMode65816::DIRECT,
+        AddressingMode::ZERO_PAGE_X => AddressingMode65816::DIRECT_X,
+        AddressingMode::ZERO_PAGE_Y => AddressingMode65816::DIRECT_Y,
+        AddressingMode::ABSOLUTE => AddressingMode65816::ABSOLUTE,
+        AddressingMode::ABSOLUTE_X => AddressingMode65816::ABSOLUTE_X,
+        AddressingMode::ABSOLUTE_Y => AddressingMode65816::ABSOLUTE_Y,
+        AddressingMode::INDIRECT => AddressingMode65816::ABSOLUTE_INDIRECT,
+        AddressingMode::INDIRECT_X => AddressingMode65816::DIRECT_INDIRECT_X,
+        AddressingMode::INDIRECT_Y => AddressingMode65816::DIRECT_INDIRECT_Y,
+        AddressingMode::RELATIVE => AddressingMode65816::RELATIVE,
+        AddressingMode::ZERO_PAGE_INDIRECT => AddressingMode65816::DIRECT_INDIRECT,
+        AddressingMode::ABSOLUTE_INDIRECT_X => AddressingMode65816::ABSOLUTE_INDIRECT_X,
+        //Only used by BBR and BBS, whose slots the 65816 reuses
+        AddressingMode::ZERO_PAGE_RELATIVE => AddressingMode65816::RELATIVE
+    }
+}
+
+const fn from_6502(info: &OpcodeInfo) -> OpcodeInfo65816 {
+    shared(info.inst, mode_65816(info.inst, info.mode), info.base_cycles)
+}
+
+//Layer the 65C02's instructions over the documented 6502 ones, then the 65816's over those
+const fn build_opcode_table() -> [OpcodeInfo65816; 256] {
+    let mut table = [shared(InstructionTypes::NOP, AddressingMode65816::IMPLIED, 2); 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        if let Some(info) = &opcodes::OPCODE_TABLE[opcode] {
+            table[opcode] = from_6502(info);
+        }
+        if let Some(info) = &opcodes::WDC_65C02_OPCODE_TABLE[opcode] {
+            let replaced = matches!(info.inst, InstructionTypes::NOP | InstructionTypes::RMB(_) | InstructionTypes::SMB(_)
+                | InstructionTypes::BBR(_) | InstructionTypes::BBS(_));
+            if !replaced {
+                table[opcode] = from_6502(info);
+            }
+        }
+        opcode += 1;
+    }
+    let mut index = 0;
+    while index < ADDITIONS_65816.len() {
+        let (opcode, info) = ADDITIONS_65816[index];
+        table[opcode as usize] = info;
+        index += 1;
+    }
+    table
+}
+
+//Indexed by opcode. Unlike the 6502 every one of the 65816's opcodes is defined.
+pub static OPCODE_TABLE_65816: [OpcodeInfo65816; 256] = build_opcode_table();
+
+impl OpcodeInfo65816 {
+    //Render the instruction in standard assembler syntax. pc is the full 24 bit address of the opcode, so
+    //branch targets are shown in the program bank they land in.
+    pub fn disassemble(&self, data: &[u8], pc: u32) -> String {
+        let bank = pc & 0xFF0000;
+        let offset = pc as u16;
+        let byte = || data[0];
+        let word = || (data[0] as u16) | ((data[1] as u16) << 8);
+        let long = || (data[0] as u32) | ((data[1] as u32) << 8) | ((data[2] as u32) << 16);
+        let operand = match self.mode {
+            AddressingMode65816::IMPLIED => String::new(),
+            AddressingMode65816::ACCUMULATOR => String::from(" A"),
+            AddressingMode65816::IMMEDIATE_M | AddressingMode65816::IMMEDIATE_X | AddressingMode65816::IMMEDIATE_8 => {
+                if data.len() == 2 { format!(" #${:04X}", word()) } else { format!(" #${:02X}", byte()) }
+            },
+            AddressingMode65816::DIRECT => format!(" ${:02X}", byte()),
+            AddressingMode65816::DIRECT_X => format!(" ${:02X},X", byte()),
+            AddressingMode65816::DIRECT_Y => format!(" ${:02X},Y", byte()),
+            AddressingMode65816::DIRECT_INDIRECT => format!(" (${:02X})", byte()),
+            AddressingMode65816::DIRECT_INDIRECT_X => format!(" (${:02X},X)", byte()),
+            AddressingMode65816::DIRECT_INDIRECT_Y => format!(" (${:02X}),Y", byte()),
+            AddressingMode65816::DIRECT_INDIRECT_LONG => format!(" [${:02X}]", byte()),
+            AddressingMode65816::DIRECT_INDIRECT_LONG_Y => format!(" [${:02X}],Y", byte()),
+            AddressingMode65816::ABSOLUTE => format!(" ${:04X}", word()),
+            AddressingMode65816::ABSOLUTE_X => format!(" ${:04X},X", word()),
+            AddressingMode65816::ABSOLUTE_Y => format!(" ${:04X},Y", word()),
+            AddressingMode65816::ABSOLUTE_LONG => format!(" ${:06X}", long()),
+            AddressingMode65816::ABSOLUTE_LONG_X => format!(" ${:06X},X", long()),
+            AddressingMode65816::ABSOLUTE_INDIRECT => format!(" (${:04X})", word()),
+            AddressingMode65816::ABSOLUTE_INDIRECT_X => format!(" (${:04X},X)", word()),
+            AddressingMode65816::ABSOLUTE_INDIRECT_LONG => format!(" [${:04X}]", word()),
+            AddressingMode65816::STACK_RELATIVE => format!(" ${:02X},S", byte()),
+            AddressingMode65816::STACK_RELATIVE_INDIRECT_Y => format!(" (${:02X},S),Y", byte()),
+            //Branches wrap within the program bank
+            AddressingMode65816::RELATIVE => format!(" ${:06X}", bank | offset.wrapping_add(2).wrapping_add(byte() as i8 as u16) as u32),
+            AddressingMode65816::RELATIVE_LONG => format!(" ${:06X}", bank | offset.wrapping_add(3).wrapping_add(word()) as u32),
+            //Assembler syntax lists the source bank first, the reverse of the encoding
+            AddressingMode65816::BLOCK_MOVE => format!(" ${:02X},${:02X}", data[1], data[0])
+        };
+        format!("{}{}", self.inst.mnemonic(), operand)
+    }
+}
+
+//Where an instruction's operand lives once its addressing mode has been resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    NONE,
+    ACCUMULATOR,
+    IMMEDIATE(u16),
+    ADDRESS(u32)
+}
+
+struct Instruction {
+    opcode: u8,
+    inst: InstructionTypes65816,
+    mode: AddressingMode65816,
+    info: &'static OpcodeInfo65816,
+    data: Vec<u8>,
+    num_bytes: u16
+}
+
+impl Instruction {
+    fn word(&self) -> u16 {
+        (self.data[0] as u16) | ((self.data[1] as u16) << 8)
+    }
+}
+
+//Load, store and read-modify-write instructions sized by the M flag
+fn uses_accumulator_width(inst: InstructionTypes65816) -> bool {
+    matches!(inst, InstructionTypes65816::SHARED(InstructionTypes::ORA | InstructionTypes::AND
+        | InstructionTypes::EOR | InstructionTypes::ADC | InstructionTypes::SBC | InstructionTypes::CMP
+        | InstructionTypes::BIT | InstructionTypes::LDA | InstructionTypes::STA | InstructionTypes::STZ
+        | InstructionTypes::ASL | InstructionTypes::LSR | InstructionTypes::ROL | InstructionTypes::ROR
+        | InstructionTypes::INC | InstructionTypes::DEC | InstructionTypes::TSB | InstructionTypes::TRB))
+}
+
+//Load, store and compare instructions sized by the X flag
+fn uses_index_width(inst: InstructionTypes65816) -> bool {
+    matches!(inst, InstructionTypes65816::SHARED(InstructionTypes::LDX | InstructionTypes::LDY
+        | InstructionTypes::STX | InstructionTypes::STY | InstructionTypes::CPX | InstructionTypes::CPY))
+}
+
+fn is_read_modify_write(inst: InstructionTypes65816) -> bool {
+    matches!(inst, InstructionTypes65816::SHARED(InstructionTypes::ASL | InstructionTypes::LSR
+        | InstructionTypes::ROL | InstructionTypes::ROR | InstructionTypes::INC | InstructionTypes::DEC
+        | InstructionTypes::TSB | InstructionTypes::TRB))
+}
+
+//The 65816 starts in emulation mode, where it behaves like a 65C02 with the stack in page one and
+//8 bit registers. XCE switches to native mode, where the M and X flags select 8 or 16 bit
+//accumulator and index registers, and addresses are extended to 24 bits by the bank registers.
+pub struct CPU65816 {
+    pub reg_pc: u16,            //Program counter
+    pub reg_pbr: u8,            //Program bank register, the top 8 bits of instruction addresses
+    pub reg_dbr: u8,            //Data bank register, the top 8 bits of absolute data addresses
+    pub reg_dp: u16,            //Direct page register, the base of direct page addressing
+    pub reg_sp: u16,            //Stack pointer, held in page one while in emulation mode
+    pub reg_accum: u16,         //Accumulator, with the hidden B accumulator in the high byte while M is set
+    pub reg_index_x: u16,       //Index Register X, whose high byte is zero while X is set
+    pub reg_index_y: u16,       //Index Register Y, whose high byte is zero while X is set
+    pub reg_ps: StatusFlags,    //Processor Status
+    pub flag_m: bool,           //Accumulator and memory are 8 bits wide
+    pub flag_x: bool,           //Index registers are 8 bits wide
+    pub emulation: bool,        //6502 emulation mode
+    do_halt: bool,              //To halt or not
+    waiting: bool,              //Stopped by WAI until an interrupt line is asserted
+    break_hit: bool,            //Last instruction executed was a BRK
+    bus_fault: Option<CpuErrorKind>,    //First failed bus access made by the current instruction
+    nmi_pending: bool,          //NMI edge seen but not yet serviced
+    irq_line: bool,             //IRQ line is being held low by a device
+    total_cycles: u64           //Total number of cycles ran
+}
+
+impl Default for CPU65816 {
+    fn default() -> Self {
+        CPU65816::new()
+    }
+}
+
+impl CPU65816 {
+    pub fn new() -> CPU65816 {
+        CPU65816 {
+            reg_pc: 0,
+            reg_pbr: 0,
+            reg_dbr: 0,
+            reg_dp: 0,
+            reg_sp: 0x01FF,
+            reg_accum: 0,
+            reg_index_x: 0,
+            reg_index_y: 0,
+            reg_ps: StatusFlags::new(),
+            flag_m: true,
+            flag_x: true,
+            emulation: true,
+            do_halt: false,
+            waiting: false,
+            break_hit: false,
+            bus_fault: None,
+            nmi_pending: false,
+            irq_line: false,
+            total_cycles: 0
+        }
+    }
+
+    pub fn check_halt(&self) -> bool {
+        self.do_halt
+    }
+
+    pub fn set_halt(&mut self) {
+        self.do_halt = true;
+    }
+
+    //Whether the most recently executed instruction was a BRK, which the debugger treats as a breakpoint
+    pub fn check_break(&self) -> bool {
+        self.break_hit
+    }
+
+    //Total number of clock cycles executed since the CPU was created
+    pub fn cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    //Reset always returns to emulation mode with 8 bit registers, the direct page and both banks
+    //at zero, and starts executing from the reset vector in bank zero
+    pub fn reset<B: Bus24>(&mut self, mem: &mut B) {
+        self.emulation = true;
+        self.flag_m = true;
+        self.flag_x = true;
+        self.reg_index_x &= 0x00FF;
+        self.reg_index_y &= 0x00FF;
+        self.reg_dp = 0;
+        self.reg_pbr = 0;
+        self.reg_dbr = 0;
+        self.reg_sp = 0x0100 | (self.reg_sp.wrapping_sub(3) & 0x00FF);
+        self.reg_ps.interrupt_disable = true;
+        self.reg_ps.decimal = false;
+        self.break_hit = false;
+        self.nmi_pending = false;
+        self.do_halt = false;
+        self.waiting = false;
+        self.reg_pc = self.read_bank_word(mem, 0, RESET_VECTOR);
+        self.total_cycles += INTERRUPT_CYCLES as u64;
+    }
+
+    //NMI is edge triggered, so each call is serviced exactly once regardless of the interrupt disable flag
+    pub fn raise_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    //IRQ is level triggered and stays asserted until the device releases it with clear_irq
+    pub fn raise_irq(&mut self) {
+        self.irq_line = true;
+    }
+
+    pub fn clear_irq(&mut self) {
+        self.irq_line = false;
+    }
+
+    //Full 24 bit address of the next instruction
+    pub fn program_address(&self) -> u32 {
+        ((self.reg_pbr as u32) << 16) | self.reg_pc as u32
+    }
+
+    //Status as it would be pushed by a hardware interrupt. In native mode bits 5 and 4 are M and X.
+    pub fn get_status_reg_byte(&self) -> u8 {
+        self.status_byte(false)
+    }
+
+    fn status_byte(&self, break_flag: bool) -> u8 {
+        if self.emulation {
+            self.reg_ps.to_byte(break_flag)
+        }else{
+            let mut byte = self.reg_ps.to_byte(false) & !(FLAG_MEMORY_WIDTH | FLAG_INDEX_WIDTH);
+            if self.flag_m { byte |= FLAG_MEMORY_WIDTH; }
+            if self.flag_x { byte |= FLAG_INDEX_WIDTH; }
+            byte
+        }
+    }
+
+    //Load the status from PLP, RTI, REP or SEP. Emulation mode keeps both registers 8 bits wide.
+    fn set_status_byte(&mut self, byte: u8) {
+        self.reg_ps.set_from_byte(byte);
+        if self.emulation {
+            self.flag_m = true;
+            self.flag_x = true;
+        }else{
+            self.flag_m = (byte & FLAG_MEMORY_WIDTH) != 0;
+            self.flag_x = (byte & FLAG_INDEX_WIDTH) != 0;
+        }
+        self.apply_index_width();
+    }
+
+    //Switching the index registers to 8 bits clears their high bytes
+    fn apply_index_width(&mut self) {
+        if self.flag_x {
+            self.reg_index_x &= 0x00FF;
+            self.reg_index_y &= 0x00FF;
+        }
+    }
+
+    //Swap the carry and emulation flags. Entering emulation mode forces 8 bit registers and moves
+    //the stack back into page one.
+    fn exchange_carry_emulation(&mut self) {
+        std::mem::swap(&mut self.reg_ps.carry, &mut self.emulation);
+        if self.emulation {
+            self.flag_m = true;
+            self.flag_x = true;
+            self.apply_index_width();
+            self.reg_sp = 0x0100 | (self.reg_sp & 0x00FF);
+        }
+    }
+
+    fn update_zero_negative_flags(&mut self, value: u16, byte: bool) {
+        if byte {
+            self.reg_ps.update_zero_negative(value as u8);
+        }else{
+            self.reg_ps.zero = value == 0;
+            self.reg_ps.negative = (value & 0x8000) != 0;
+        }
+    }
+
+    //Mask and sign bit for an 8 or 16 bit value
+    fn width(byte: bool) -> (u16, u16) {
+        if byte { (0x00FF, 0x0080) } else { (0xFFFF, 0x8000) }
+    }
+
+    //Writes to the accumulator leave the hidden B accumulator alone while it is 8 bits wide
+    fn set_accum(&mut self, value: u16) {
+        if self.flag_m {
+            self.reg_accum = (self.reg_accum & 0xFF00) | (value & 0x00FF);
+        }else{
+            self.reg_accum = value;
+        }
+    }
+
+    fn accum(&self) -> u16 {
+        if self.flag_m { self.reg_accum & 0x00FF } else { self.reg_accum }
+    }
+
+    fn index_value(&self, value: u16) -> u16 {
+        if self.flag_x { value & 0x00FF } else { value }
+    }
+
+    //Remember the first failed access so it can be reported once the instruction has finished
+    fn record_fault(&mut self, fault: CpuErrorKind) {
+        if self.bus_fault.is_none() {
+            self.bus_fault = Some(fault);
+        }
+    }
+
+    fn take_fault(&mut self, pc: u32, opcode: Option<u8>) -> Result<(), CpuError> {
+        if let Some(kind) = self.bus_fault.take() {
+            Err(CpuError { pc, opcode, kind })
+        }else{
+            Ok(())
+        }
+    }
+
+    //Failed reads return 0 and failed writes are discarded, leaving the fault in bus_fault
+    fn read_byte<B: Bus24>(&mut self, mem: &mut B, addr: u32) -> u8 {
+        let addr = addr & 0xFFFFFF;
+        mem.read(addr).unwrap_or_else(|error| {
+            self.record_fault(CpuErrorKind::READ_FAILED(addr, error));
+            0
+        })
+    }
+
+    fn write_byte<B: Bus24>(&mut self, mem: &mut B, addr: u32, data: u8) {
+        let addr = addr & 0xFFFFFF;
+        if let Err(error) = mem.write(addr, data) {
+            self.record_fault(CpuErrorKind::WRITE_FAILED(addr, error));
+        }
+    }
+
+    //Data spanning two bytes carries into the next bank
+    fn read_word<B: Bus24>(&mut self, mem: &mut B, addr: u32) -> u16 {
+        (self.read_byte(mem, addr) as u16) | ((self.read_byte(mem, addr.wrapping_add(1)) as u16) << 8)
+    }
+
+    //Pointers and vectors stay within their bank
+    fn read_bank_word<B: Bus24>(&mut self, mem: &mut B, bank: u8, addr: u16) -> u16 {
+        let bank = (bank as u32) << 16;
+        (self.read_byte(mem, bank | addr as u32) as u16) | ((self.read_byte(mem, bank | addr.wrapping_add(1) as u32) as u16) << 8)
+    }
+
+    fn read_bank_long<B: Bus24>(&mut self, mem: &mut B, bank: u8, addr: u16) -> u32 {
+        let bank_byte = self.read_byte(mem, ((bank as u32) << 16) | addr.wrapping_add(2) as u32);
+        (self.read_bank_word(mem, bank, addr) as u32) | ((bank_byte as u32) << 16)
+    }
+
+    //Direct page addresses live in bank zero. In emulation mode with a page aligned direct page
+    //register, indexing wraps within the page like the 6502's zero page.
+    fn direct_addr(&self, offset: u16) -> u32 {
+        if self.emulation && (self.reg_dp & 0x00FF) == 0 {
+            ((self.reg_dp & 0xFF00) | (offset & 0x00FF)) as u32
+        }else{
+            self.reg_dp.wrapping_add(offset) as u32
+        }
+    }
+
+    fn data_addr(&self, addr: u16) -> u32 {
+        ((self.reg_dbr as u32) << 16) | addr as u32
+    }
+
+    fn push_byte<B: Bus24>(&mut self, mem: &mut B, data: u8) {
+        self.write_byte(mem, self.reg_sp as u32, data);
+        self.reg_sp = self.reg_sp.wrapping_sub(1);
+        if self.emulation {
+            self.reg_sp = 0x0100 | (self.reg_sp & 0x00FF);
+        }
+    }
+
+    fn pull_byte<B: Bus24>(&mut self, mem: &mut B) -> u8 {
+        self.reg_sp = self.reg_sp.wrapping_add(1);
+        if self.emulation {
+            self.reg_sp = 0x0100 | (self.reg_sp & 0x00FF);
+        }
+        self.read_byte(mem, self.reg_sp as u32)
+    }
+
+    fn push_word<B: Bus24>(&mut self, mem: &mut B, data: u16) {
+        self.push_byte(mem, (data >> 8) as u8);
+        self.push_byte(mem, data as u8);
+    }
+
+    fn pull_word<B: Bus24>(&mut self, mem: &mut B) -> u16 {
+        let lo = self.pull_byte(mem) as u16;
+        let hi = self.pull_byte(mem) as u16;
+        lo | (hi << 8)
+    }
+
+    //Push a register that is 8 or 16 bits wide
+    fn push_value<B: Bus24>(&mut self, mem: &mut B, value: u16, byte: bool) {
+        if byte {
+            self.push_byte(mem, value as u8);
+        }else{
+            self.push_word(mem, value);
+        }
+    }
+
+    fn pull_value<B: Bus24>(&mut self, mem: &mut B, byte: bool) -> u16 {
+        if byte {
+            self.pull_byte(mem) as u16
+        }else{
+            self.pull_word(mem)
+        }
+    }
+
+    //Push the program bank (native mode only), return address and status, then jump through the
+    //vector in bank zero. Returns the number of cycles taken.
+    fn interrupt<B: Bus24>(&mut self, mem: &mut B, emulation_vector: u16, native_vector: u16, from_brk: bool) -> u16 {
+        self.break_hit = from_brk;
+        let native = !self.emulation;
+        if native {
+            self.push_byte(mem, self.reg_pbr);
+        }
+        self.push_word(mem, self.reg_pc);
+        let status = self.status_byte(from_brk);
+        self.push_byte(mem, status);
+        self.reg_ps.interrupt_disable = true;
+        self.reg_ps.decimal = false;
+        self.reg_pbr = 0;
+        self.reg_pc = self.read_bank_word(mem, 0, if native { native_vector } else { emulation_vector });
+        INTERRUPT_CYCLES + native as u16
+    }
+
+    //The interrupt that would be taken if the CPU polled right now, as its emulation and native vectors
+    fn pending_interrupt(&self) -> Option<(u16, u16)> {
+        if self.nmi_pending {
+            Some((EMULATION_NMI_VECTOR, NATIVE_NMI_VECTOR))
+        }else if self.irq_line && !self.reg_ps.interrupt_disable {
+            Some((EMULATION_IRQ_BRK_VECTOR, NATIVE_IRQ_VECTOR))
+        }else{
+            None
+        }
+    }
+
+    fn fetch<B: Bus24>(&mut self, mem: &mut B) -> Result<Instruction, CpuError> {
+        let inst_addr = self.program_address();
+        let fetch_failed = |opcode, error| CpuError { pc: inst_addr, opcode, kind: CpuErrorKind::FETCH_FAILED(error) };
+        let opcode = mem.read(inst_addr).map_err(|error| fetch_failed(None, error))?;
+        let info = &OPCODE_TABLE_65816[opcode as usize];
+        let num_operand_bytes = info.mode.num_operand_bytes(self.flag_m, self.flag_x);
+        let mut data: Vec<u8> = Vec::new();
+        for i in 0..num_operand_bytes {
+            //The program counter wraps within the program bank
+            let addr = ((self.reg_pbr as u32) << 16) | self.reg_pc.wrapping_add(1 + i) as u32;
+            data.push(mem.read(addr).map_err(|error| fetch_failed(Some(opcode), error))?);
+        }
+        Ok(Instruction { opcode, inst: info.inst, mode: info.mode, info, data, num_bytes: num_operand_bytes + 1 })
+    }
+
+    //Work out the operand, along with whether indexing moved the address onto a different page
+    fn resolve_operand<B: Bus24>(&mut self, inst: &Instruction, mem: &mut B) -> (Operand, bool) {
+        let (x, y) = (self.reg_index_x, self.reg_index_y);
+        let indexed = |base: u32, index: u16| {
+            let addr = base.wrapping_add(index as u32) & 0xFFFFFF;
+            (Operand::ADDRESS(addr), (addr & 0xFFFF00) != (base & 0xFFFF00))
+        };
+        match inst.mode {
+            AddressingMode65816::IMPLIED => (Operand::NONE, false),
+            AddressingMode65816::ACCUMULATOR => (Operand::ACCUMULATOR, false),
+            AddressingMode65816::IMMEDIATE_M | AddressingMode65816::IMMEDIATE_X | AddressingMode65816::IMMEDIATE_8 => {
+                let value = if inst.data.len() == 2 { inst.word() } else { inst.data[0] as u16 };
+                (Operand::IMMEDIATE(value), false)
+            },
+            AddressingMode65816::DIRECT => (Operand::ADDRESS(self.direct_addr(inst.data[0] as u16)), false),
+            AddressingMode65816::DIRECT_X => (Operand::ADDRESS(self.direct_addr((inst.data[0] as u16).wrapping_add(x))), false),
+            AddressingMode65816::DIRECT_Y => (Operand::ADDRESS(self.direct_addr((inst.data[0] as u16).wrapping_add(y))), false),
+            AddressingMode65816::DIRECT_INDIRECT => {
+                let ptr = self.read_bank_word(mem, 0, self.direct_addr(inst.data[0] as u16) as u16);
+                (Operand::ADDRESS(self.data_addr(ptr)), false)
+            },
+            AddressingMode65816::DIRECT_INDIRECT_X => {
+                let ptr = self.read_bank_word(mem, 0, self.direct_addr((inst.data[0] as u16).wrapping_add(x)) as u16);
+                (Operand::ADDRESS(self.data_addr(ptr)), false)
+            },
+            AddressingMode65816::DIRECT_INDIRECT_Y => {
+                let ptr = self.read_bank_word(mem, 0, self.direct_addr(inst.data[0] as u16) as u16);
+                indexed(self.data_addr(ptr), y)
+            },
+            AddressingMode65816::DIRECT_INDIRECT_LONG => {
+                (Operand::ADDRESS(self.read_bank_long(mem, 0, self.direct_addr(inst.data[0] as u16) as u16)), false)
+            },
+            AddressingMode65816::DIRECT_INDIRECT_LONG_Y => {
+                let base = self.read_bank_long(mem, 0, self.direct_addr(inst.data[0] as u16) as u16);
+                (indexed(base, y).0, false)
+            },
+            AddressingMode65816::ABSOLUTE => (Operand::ADDRESS(self.data_addr(inst.word())), false),
+            AddressingMode65816::ABSOLUTE_X => indexed(self.data_addr(inst.word()), x),
+            AddressingMode65816::ABSOLUTE_Y => indexed(self.data_addr(inst.word()), y),
+            AddressingMode65816::ABSOLUTE_LONG => {
+                (Operand::ADDRESS((inst.word() as u32) | ((inst.data[2] as u32) << 16)), false)
+            },
+            AddressingMode65816::ABSOLUTE_LONG_X => {
+                (indexed((inst.word() as u32) | ((inst.data[2] as u32) << 16), x).0, false)
+            },
+            AddressingMode65816::STACK_RELATIVE => {
+                (Operand::ADDRESS(self.reg_sp.wrapping_add(inst.data[0] as u16) as u32), false)
+            },
+            AddressingMode65816::STACK_RELATIVE_INDIRECT_Y => {
+                let ptr = self.read_bank_word(mem, 0, self.reg_sp.wrapping_add(inst.data[0] as u16));
+                (indexed(self.data_addr(ptr), y).0, false)
+            },
+            //Jumps, branches and block moves use their operand bytes directly
+            AddressingMode65816::ABSOLUTE_INDIRECT | AddressingMode65816::ABSOLUTE_INDIRECT_X
+            | AddressingMode65816::ABSOLUTE_INDIRECT_LONG | AddressingMode65816::RELATIVE
+            | AddressingMode65816::RELATIVE_LONG | AddressingMode65816::BLOCK_MOVE => (Operand::NONE, false)
+        }
+    }
+
+    //Read an 8 or 16 bit operand
+    fn read_operand<B: Bus24>(&mut self, operand: Operand, mem: &mut B, byte: bool) -> u16 {
+        let (mask, _) = CPU65816::width(byte);
+        match operand {
+            Operand::ACCUMULATOR => self.reg_accum & mask,
+            Operand::IMMEDIATE(value) => value & mask,
+            Operand::ADDRESS(addr) => {
+                if byte { self.read_byte(mem, addr) as u16 } else { self.read_word(mem, addr) }
+            },
+            Operand::NONE => 0
+        }
+    }
+
+    fn write_operand<B: Bus24>(&mut self, operand: Operand, mem: &mut B, data: u16, byte: bool) {
+        match operand {
+            Operand::ACCUMULATOR => {
+                self.set_accum(data);
+            },
+            Operand::ADDRESS(addr) => {
+                self.write_byte(mem, addr, data as u8);
+                if !byte {
+                    self.write_byte(mem, addr.wrapping_add(1), (data >> 8) as u8);
+                }
+            },
+            _ => {
+                log!(Subsystem::CPU, Level::WARN, "Operand {:?} is not writable", operand);
+            }
+        }
+    }
+
+    fn read_modify_write<B: Bus24>(&mut self, operand: Operand, mem: &mut B, modify: fn(&mut CPU65816, u16, bool) -> u16) {
+        let byte = self.flag_m;
+        let value = self.read_operand(operand, mem, byte);
+        let res = modify(self, value, byte);
+        self.write_operand(operand, mem, res, byte);
+    }
+
+    fn binary_add(&mut self, value: u16) {
+        let byte = self.flag_m;
+        let (mask, sign) = CPU65816::width(byte);
+        let accum = self.accum();
+        let sum = accum as u32 + (value & mask) as u32 + self.reg_ps.carry as u32;
+        let res = (sum as u16) & mask;
+        self.reg_ps.carry = sum > mask as u32;
+        self.reg_ps.overflow = (!(accum ^ value) & (accum ^ res) & sign) != 0;
+        self.set_accum(res);
+        self.update_zero_negative_flags(res, byte);
+    }
+
+    //In decimal mode each nibble is a BCD digit, with the carry rippling from digit to digit. Unlike
+    //the NMOS 6502 every flag reflects the decimal result.
+    fn add_with_carry(&mut self, value: u16) {
+        if !self.reg_ps.decimal {
+            self.binary_add(value);
+            return;
+        }
+        let byte = self.flag_m;
+        let (_, sign) = CPU65816::width(byte);
+        let accum = self.accum();
+        let mut carry = self.reg_ps.carry as u16;
+        let mut res: u16 = 0;
+        for digit in 0..(if byte { 2 } else { 4 }) {
+            let shift = digit * 4;
+            let mut sum = ((accum >> shift) & 0xF) + ((value >> shift) & 0xF) + carry;
+            carry = (sum > 9) as u16;
+            if carry == 1 {
+                sum = (sum + 6) & 0xF;
+            }
+            res |= sum << shift;
+        }
+        self.reg_ps.carry = carry == 1;
+        self.reg_ps.overflow = (!(accum ^ value) & (accum ^ res) & sign) != 0;
+        self.set_accum(res);
+        self.update_zero_negative_flags(res, byte);
+    }
+
+    fn subtract_with_borrow(&mut self, value: u16) {
+        let byte = self.flag_m;
+        let (mask, sign) = CPU65816::width(byte);
+        if !self.reg_ps.decimal {
+            //Subtraction is addition of the one's complement, with carry acting as "not borrow"
+            self.binary_add(!value & mask);
+            return;
+        }
+        let accum = self.accum();
+        let mut borrow = !self.reg_ps.carry as i16;
+        let mut res: u16 = 0;
+        for digit in 0..(if byte { 2 } else { 4 }) {
+            let shift = digit * 4;
+            let mut diff = ((accum >> shift) & 0xF) as i16 - ((value >> shift) & 0xF) as i16 - borrow;
+            borrow = (diff < 0) as i16;
+            if diff < 0 {
+                diff += 10;
+            }
+            res |= ((diff as u16) & 0xF) << shift;
+        }
+        self.reg_ps.carry = borrow == 0;
+        self.reg_ps.overflow = ((accum ^ value) & (accum ^ res) & sign) != 0;
+        self.set_accum(res);
+        self.update_zero_negative_flags(res, byte);
+    }
+
+    fn compare(&mut self, reg: u16, value: u16, byte: bool) {
+        let (mask, _) = CPU65816::width(byte);
+        let (reg, value) = (reg & mask, value & mask);
+        self.update_zero_negative_flags(reg.wrapping_sub(value) & mask, byte);
+        self.reg_ps.carry = reg >= value;
+    }
+
+    fn shift_left(&mut self, value: u16, carry_in: bool, byte: bool) -> u16 {
+        let (mask, sign) = CPU65816::width(byte);
+        let res = ((value << 1) | carry_in as u16) & mask;
+        self.reg_ps.carry = (value & sign) != 0;
+        self.update_zero_negative_flags(res, byte);
+        res
+    }
+
+    fn shift_right(&mut self, value: u16, carry_in: bool, byte: bool) -> u16 {
+        let (_, sign) = CPU65816::width(byte);
+        let res = (value >> 1) | if carry_in { sign } else { 0 };
+        self.reg_ps.carry = (value & 0x1) != 0;
+        self.update_zero_negative_flags(res, byte);
+        res
+    }
+
+    //Returns the extra cycles used: one for a taken branch, and in emulation mode another if it
+    //lands on a different page
+    fn branch(&mut self, inst: &Instruction, condition: bool) -> u16 {
+        if condition {
+            let target = self.reg_pc.wrapping_add(inst.data[0] as i8 as u16);
+            let page_crossed = (target & 0xFF00) != (self.reg_pc & 0xFF00);
+            self.reg_pc = target;
+            1 + (page_crossed && self.emulation) as u16
+        }else{
+            0
+        }
+    }
+
+    //Execute a fetched instruction, returning the number of cycles it took
+    fn execute<B: Bus24>(&mut self, inst: &Instruction, mem: &mut B) -> u16 {
+        self.break_hit = false;
+
+        //Program counter points at the next instruction while this one executes
+        let inst_pc = self.reg_pc;
+        self.reg_pc = self.reg_pc.wrapping_add(inst.num_bytes);
+        let (operand, page_crossed) = self.resolve_operand(inst, mem);
+
+        //Each extra byte a 16 bit operand moves costs a cycle, twice over for read-modify-write
+        let mut num_cycles = inst.info.base_cycles;
+        let wide = (uses_accumulator_width(inst.inst) && !self.flag_m) || (uses_index_width(inst.inst) && !self.flag_x);
+        if wide {
+            match operand {
+                Operand::ADDRESS(_) => num_cycles += if is_read_modify_write(inst.inst) { 2 } else { 1 },
+                Operand::IMMEDIATE(_) => num_cycles += 1,
+                _ => {}
+            }
+        }
+        //A direct page register that is not page aligned costs a cycle to add in
+        if inst.mode.uses_direct_page() && (self.reg_dp & 0x00FF) != 0 {
+            num_cycles += 1;
+        }
+        //Indexed reads fix up the high byte when crossing a page, and always do so with 16 bit index registers
+        let indexed_read = matches!(inst.mode, AddressingMode65816::ABSOLUTE_X | AddressingMode65816::ABSOLUTE_Y
+            | AddressingMode65816::DIRECT_INDIRECT_Y) && !inst.inst.writes_operand();
+        if indexed_read && (page_crossed || !self.flag_x) {
+            num_cycles += 1;
+        }
+
+        match inst.inst {
+            InstructionTypes65816::SHARED(shared) => match shared {
+                InstructionTypes::BRK => {
+                    num_cycles = self.interrupt(mem, EMULATION_IRQ_BRK_VECTOR, NATIVE_BRK_VECTOR, true);
+                },
+                InstructionTypes::LDA => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    self.set_accum(value);
+                    self.update_zero_negative_flags(value, self.flag_m);
+                },
+                InstructionTypes::LDX => {
+                    self.reg_index_x = self.read_operand(operand, mem, self.flag_x);
+                    self.update_zero_negative_flags(self.reg_index_x, self.flag_x);
+                },
+                InstructionTypes::LDY => {
+                    self.reg_index_y = self.read_operand(operand, mem, self.flag_x);
+                    self.update_zero_negative_flags(self.reg_index_y, self.flag_x);
+                },
+                InstructionTypes::STA => {
+                    self.write_operand(operand, mem, self.reg_accum, self.flag_m);
+                },
+                InstructionTypes::STX => {
+                    self.write_operand(operand, mem, self.reg_index_x, self.flag_x);
+                },
+                InstructionTypes::STY => {
+                    self.write_operand(operand, mem, self.reg_index_y, self.flag_x);
+                },
+                InstructionTypes::STZ => {
+                    self.write_operand(operand, mem, 0, self.flag_m);
+                },
+                InstructionTypes::TAX => {
+                    //Transfers into an index register copy the whole of C when the index is 16 bits wide
+                    self.reg_index_x = self.index_value(self.reg_accum);
+                    self.update_zero_negative_flags(self.reg_index_x, self.flag_x);
+                },
+                InstructionTypes::TAY => {
+                    self.reg_index_y = self.index_value(self.reg_accum);
+                    self.update_zero_negative_flags(self.reg_index_y, self.flag_x);
+                },
+                InstructionTypes::TXA => {
+                    self.set_accum(self.reg_index_x);
+                    self.update_zero_negative_flags(self.accum(), self.flag_m);
+                },
+                InstructionTypes::TYA => {
+                    self.set_accum(self.reg_index_y);
+                    self.update_zero_negative_flags(self.accum(), self.flag_m);
+                },
+                InstructionTypes::TSX => {
+                    self.reg_index_x = self.index_value(self.reg_sp);
+                    self.update_zero_negative_flags(self.reg_index_x, self.flag_x);
+                },
+                InstructionTypes::TXS => {
+                    self.reg_sp = if self.emulation { 0x0100 | (self.reg_index_x & 0x00FF) } else { self.reg_index_x };
+                },
+                InstructionTypes::PHA => {
+                    self.push_value(mem, self.reg_accum, self.flag_m);
+                    num_cycles += !self.flag_m as u16;
+                },
+                InstructionTypes::PHX => {
+                    self.push_value(mem, self.reg_index_x, self.flag_x);
+                    num_cycles += !self.flag_x as u16;
+                },
+                InstructionTypes::PHY => {
+                    self.push_value(mem, self.reg_index_y, self.flag_x);
+                    num_cycles += !self.flag_x as u16;
+                },
+                InstructionTypes::PLA => {
+                    let value = self.pull_value(mem, self.flag_m);
+                    self.set_accum(value);
+                    self.update_zero_negative_flags(value, self.flag_m);
+                    num_cycles += !self.flag_m as u16;
+                },
+                InstructionTypes::PLX => {
+                    self.reg_index_x = self.pull_value(mem, self.flag_x);
+                    self.update_zero_negative_flags(self.reg_index_x, self.flag_x);
+                    num_cycles += !self.flag_x as u16;
+                },
+                InstructionTypes::PLY => {
+                    self.reg_index_y = self.pull_value(mem, self.flag_x);
+                    self.update_zero_negative_flags(self.reg_index_y, self.flag_x);
+                    num_cycles += !self.flag_x as u16;
+                },
+                InstructionTypes::PHP => {
+                    //In emulation mode PHP pushes the break bit set, just like the 6502
+                    let status = self.status_byte(true);
+                    self.push_byte(mem, status);
+                },
+                InstructionTypes::PLP => {
+                    let status = self.pull_byte(mem);
+                    self.set_status_byte(status);
+                },
+                InstructionTypes::AND => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    self.set_accum(self.reg_accum & value);
+                    self.update_zero_negative_flags(self.accum(), self.flag_m);
+                },
+                InstructionTypes::EOR => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    self.set_accum(self.reg_accum ^ value);
+                    self.update_zero_negative_flags(self.accum(), self.flag_m);
+                },
+                InstructionTypes::ORA => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    self.set_accum(self.reg_accum | value);
+                    self.update_zero_negative_flags(self.accum(), self.flag_m);
+                },
+                InstructionTypes::BIT => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    let (_, sign) = CPU65816::width(self.flag_m);
+                    self.reg_ps.zero = (self.accum() & value) == 0;
+                    //BIT #imm has no memory flags to copy, so it only affects Z
+                    if inst.mode != AddressingMode65816::IMMEDIATE_M {
+                        self.reg_ps.negative = (value & sign) != 0;
+                        self.reg_ps.overflow = (value & (sign >> 1)) != 0;
+                    }
+                },
+                InstructionTypes::ADC => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    self.add_with_carry(value);
+                },
+                InstructionTypes::SBC => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    self.subtract_with_borrow(value);
+                },
+                InstructionTypes::CMP => {
+                    let value = self.read_operand(operand, mem, self.flag_m);
+                    self.compare(self.reg_accum, value, self.flag_m);
+                },
+                InstructionTypes::CPX => {
+                    let value = self.read_operand(operand, mem, self.flag_x);
+                    self.compare(self.reg_index_x, value, self.flag_x);
+                },
+                InstructionTypes::CPY => {
+                    let value = self.read_operand(operand, mem, self.flag_x);
+                    self.compare(self.reg_index_y, value, self.flag_x);
+                },
+                InstructionTypes::INC => {
+                    self.read_modify_write(operand, mem, |cpu, value, byte| {
+                        let res = value.wrapping_add(1) & CPU65816::width(byte).0;
+                        cpu.update_zero_negative_flags(res, byte);
+                        res
+                    });
+                },
+                InstructionTypes::DEC => {
+                    self.read_modify_write(operand, mem, |cpu, value, byte| {
+                        let res = value.wrapping_sub(1) & CPU65816::width(byte).0;
+                        cpu.update_zero_negative_flags(res, byte);
+                        res
+                    });
+                },
+                InstructionTypes::ASL => {
+                    self.read_modify_write(operand, mem, |cpu, value, byte| cpu.shift_left(value, false, byte));
+                },
+                InstructionTypes::ROL => {
+                    self.read_modify_write(operand, mem, |cpu, value, byte| cpu.shift_left(value, cpu.reg_ps.carry, byte));
+                },
+                InstructionTypes::LSR => {
+                    self.read_modify_write(operand, mem, |cpu, value, byte| cpu.shift_right(value, false, byte));
+                },
+                InstructionTypes::ROR => {
+                    self.read_modify_write(operand, mem, |cpu, value, byte| cpu.shift_right(value, cpu.reg_ps.carry, byte));
+                },
+                InstructionTypes::TSB => {
+                    self.read_modify_write(operand, mem, |cpu, value, _| {
+                        cpu.reg_ps.zero = (cpu.accum() & value) == 0;
+                        value | cpu.accum()
+                    });
+                },
+                InstructionTypes::TRB => {
+                    self.read_modify_write(operand, mem, |cpu, value, _| {
+                        cpu.reg_ps.zero = (cpu.accum() & value) == 0;
+                        value & !cpu.accum()
+                    });
+                },
+                InstructionTypes::INX => {
+                    self.reg_index_x = self.index_value(self.reg_index_x.wrapping_add(1));
+                    self.update_zero_negative_flags(self.reg_index_x, self.flag_x);
+                },
+                InstructionTypes::INY => {
+                    self.reg_index_y = self.index_value(self.reg_index_y.wrapping_add(1));
+                    self.update_zero_negative_flags(self.reg_index_y, self.flag_x);
+                },
+                InstructionTypes::DEX => {
+                    self.reg_index_x = self.index_value(self.reg_index_x.wrapping_sub(1));
+                    self.update_zero_negative_flags(self.reg_index_x, self.flag_x);
+                },
+                InstructionTypes::DEY => {
+                    self.reg_index_y = self.index_value(self.reg_index_y.wrapping_sub(1));
+                    self.update_zero_negative_flags(self.reg_index_y, self.flag_x);
+                },
+                InstructionTypes::JMP => {
+                    self.reg_pc = match inst.mode {
+                        //JMP (abs) always takes its pointer from bank zero
+                        AddressingMode65816::ABSOLUTE_INDIRECT => self.read_bank_word(mem, 0, inst.word()),
+                        AddressingMode65816::ABSOLUTE_INDIRECT_X => self.read_bank_word(mem, self.reg_pbr, inst.word().wrapping_add(self.reg_index_x)),
+                        _ => inst.word()
+                    };
+                },
+                InstructionTypes::JSR => {
+                    //Return address pushed is the last byte of the JSR instruction
+                    self.push_word(mem, self.reg_pc.wrapping_sub(1));
+                    self.reg_pc = if inst.mode == AddressingMode65816::ABSOLUTE_INDIRECT_X {
+                        self.read_bank_word(mem, self.reg_pbr, inst.word().wrapping_add(self.reg_index_x))
+                    }else{
+                        inst.word()
+                    };
+                },
+                InstructionTypes::RTS => {
+                    self.reg_pc = self.pull_word(mem).wrapping_add(1);
+                },
+                InstructionTypes::RTI => {
+                    let status = self.pull_byte(mem);
+                    self.set_status_byte(status);
+                    self.reg_pc = self.pull_word(mem);
+                    if !self.emulation {
+                        self.reg_pbr = self.pull_byte(mem);
+                        num_cycles += 1;
+                    }
+                },
+                InstructionTypes::BCC => {
+                    num_cycles += self.branch(inst, !self.reg_ps.carry);
+                },
+                InstructionTypes::BCS => {
+                    num_cycles += self.branch(inst, self.reg_ps.carry);
+                },
+                InstructionTypes::BEQ => {
+                    num_cycles += self.branch(inst, self.reg_ps.zero);
+                },
+                InstructionTypes::BNE => {
+                    num_cycles += self.branch(inst, !self.reg_ps.zero);
+                },
+                InstructionTypes::BMI => {
+                    num_cycles += self.branch(inst, self.reg_ps.negative);
+                },
+                InstructionTypes::BPL => {
+                    num_cycles += self.branch(inst, !self.reg_ps.negative);
+                },
+                InstructionTypes::BVS => {
+                    num_cycles += self.branch(inst, self.reg_ps.overflow);
+                },
+                InstructionTypes::BVC => {
+                    num_cycles += self.branch(inst, !self.reg_ps.overflow);
+                },
+                InstructionTypes::BRA => {
+                    num_cycles += self.branch(inst, true);
+                },
+                InstructionTypes::CLC => {
+                    self.reg_ps.carry = false;
+                },
+                InstructionTypes::SEC => {
+                    self.reg_ps.carry = true;
+                },
+                InstructionTypes::CLI => {
+                    self.reg_ps.interrupt_disable = false;
+                },
+                InstructionTypes::SEI => {
+                    self.reg_ps.interrupt_disable = true;
+                },
+                InstructionTypes::CLD => {
+                    self.reg_ps.decimal = false;
+                },
+                InstructionTypes::SED => {
+                    self.reg_ps.decimal = true;
+                },
+                InstructionTypes::CLV => {
+                    self.reg_ps.overflow = false;
+                },
+                InstructionTypes::WAI => {
+                    self.waiting = true;
+                },
+                InstructionTypes::STP => {
+                    //Only a reset restarts the processor
+                    self.set_halt();
+                },
+                InstructionTypes::NOP => {},
+                InstructionTypes::ALR | InstructionTypes::ANC | InstructionTypes::ARR | InstructionTypes::DCP
+                | InstructionTypes::ISC | InstructionTypes::JAM | InstructionTypes::LAX | InstructionTypes::RLA
+                | InstructionTypes::RRA | InstructionTypes::SAX | InstructionTypes::SBX | InstructionTypes::SLO
+                | InstructionTypes::SRE | InstructionTypes::BBR(_) | InstructionTypes::BBS(_) | InstructionTypes::RMB(_)
+                | InstructionTypes::SMB(_) => {
+                    //The 65816 opcode table never decodes to these
+                    log!(Subsystem::CPU, Level::WARN, "{} is not supported by the 65816", shared.mnemonic());
+                }
+            },
+            InstructionTypes65816::COP => {
+                num_cycles = self.interrupt(mem, EMULATION_COP_VECTOR, NATIVE_COP_VECTOR, false);
+            },
+            InstructionTypes65816::TXY => {
+                self.reg_index_y = self.reg_index_x;
+                self.update_zero_negative_flags(self.reg_index_y, self.flag_x);
+            },
+            InstructionTypes65816::TYX => {
+                self.reg_index_x = self.reg_index_y;
+                self.update_zero_negative_flags(self.reg_index_x, self.flag_x);
+            },
+            InstructionTypes65816::TCS => {
+                self.reg_sp = if self.emulation { 0x0100 | (self.reg_accum & 0x00FF) } else { self.reg_accum };
+            },
+            InstructionTypes65816::TSC => {
+                self.reg_accum = self.reg_sp;
+                self.update_zero_negative_flags(self.reg_accum, false);
+            },
+            InstructionTypes65816::TCD => {
+                self.reg_dp = self.reg_accum;
+                self.update_zero_negative_flags(self.reg_dp, false);
+            },
+            InstructionTypes65816::TDC => {
+                self.reg_accum = self.reg_dp;
+                self.update_zero_negative_flags(self.reg_accum, false);
+            },
+            InstructionTypes65816::XBA => {
+                self.reg_accum = self.reg_accum.rotate_left(8);
+                self.update_zero_negative_flags(self.reg_accum & 0x00FF, true);
+            },
+            InstructionTypes65816::XCE => {
+                self.exchange_carry_emulation();
+            },
+            InstructionTypes65816::PHB => {
+                self.push_byte(mem, self.reg_dbr);
+            },
+            InstructionTypes65816::PHK => {
+                self.push_byte(mem, self.reg_pbr);
+            },
+            InstructionTypes65816::PHD => {
+                self.push_word(mem, self.reg_dp);
+            },
+            InstructionTypes65816::PLB => {
+                self.reg_dbr = self.pull_byte(mem);
+                self.update_zero_negative_flags(self.reg_dbr as u16, true);
+            },
+            InstructionTypes65816::PLD => {
+                self.reg_dp = self.pull_word(mem);
+                self.update_zero_negative_flags(self.reg_dp, false);
+            },
+            InstructionTypes65816::PEA => {
+                self.push_word(mem, inst.word());
+            },
+            InstructionTypes65816::PEI => {
+                let value = self.read_bank_word(mem, 0, self.direct_addr(inst.data[0] as u16) as u16);
+                self.push_word(mem, value);
+            },
+            InstructionTypes65816::PER => {
+                let value = self.reg_pc.wrapping_add(inst.word());
+                self.push_word(mem, value);
+            },
+            InstructionTypes65816::JML => {
+                let target = if inst.mode == AddressingMode65816::ABSOLUTE_INDIRECT_LONG {
+                    self.read_bank_long(mem, 0, inst.word())
+                }else{
+                    (inst.word() as u32) | ((inst.data[2] as u32) << 16)
+                };
+                self.reg_pbr = (target >> 16) as u8;
+                self.reg_pc = target as u16;
+            },
+            InstructionTypes65816::JSL => {
+                self.push_byte(mem, self.reg_pbr);
+                self.push_word(mem, self.reg_pc.wrapping_sub(1));
+                self.reg_pbr = inst.data[2];
+                self.reg_pc = inst.word();
+            },
+            InstructionTypes65816::RTL => {
+                self.reg_pc = self.pull_word(mem).wrapping_add(1);
+                self.reg_pbr = self.pull_byte(mem);
+            },
+            InstructionTypes65816::BRL => {
+                self.reg_pc = self.reg_pc.wrapping_add(inst.word());
+            },
+            InstructionTypes65816::REP => {
+                let status = self.status_byte(false) & !inst.data[0];
+                self.set_status_byte(status);
+            },
+            InstructionTypes65816::SEP => {
+                let status = self.status_byte(false) | inst.data[0];
+                self.set_status_byte(status);
+            },
+            InstructionTypes65816::MVN | InstructionTypes65816::MVP => {
+                //Move one byte per execution from bank src:X to bank dst:Y, then repeat the
+                //instruction until the count in C runs out
+                let (dst_bank, src_bank) = (inst.data[0], inst.data[1]);
+                let value = self.read_byte(mem, ((src_bank as u32) << 16) | self.reg_index_x as u32);
+                self.write_byte(mem, ((dst_bank as u32) << 16) | self.reg_index_y as u32, value);
+                self.reg_dbr = dst_bank;
+                if inst.inst == InstructionTypes65816::MVN {
+                    self.reg_index_x = self.index_value(self.reg_index_x.wrapping_add(1));
+                    self.reg_index_y = self.index_value(self.reg_index_y.wrapping_add(1));
+                }else{
+                    self.reg_index_x = self.index_value(self.reg_index_x.wrapping_sub(1));
+                    self.reg_index_y = self.index_value(self.reg_index_y.wrapping_sub(1));
+                }
+                self.reg_accum = self.reg_accum.wrapping_sub(1);
+                if self.reg_accum != 0xFFFF {
+                    self.reg_pc = inst_pc;
+                }
+            },
+            InstructionTypes65816::WDM => {}
+        }
+
+        num_cycles
+    }
+
+    //Run whole instructions until at least num_cycles clock cycles have elapsed, returning how many
+    //actually ran
+    pub fn run_for_cycles<B: Bus24>(&mut self, mem_ref: &mut B, num_cycles: u64) -> Result<u64, CpuError> {
+        let start_cycles = self.total_cycles;
+        while !self.do_halt && self.total_cycles - start_cycles < num_cycles {
+            self.step(mem_ref)?;
+        }
+        Ok(self.total_cycles - start_cycles)
+    }
+
+    //Execute one whole instruction (or interrupt sequence) at once. Like CPU::step, an instruction
+    //that faults still runs to completion before the fault is returned.
+    pub fn step<B: Bus24>(&mut self, mem_ref: &mut B) -> Result<StepOutcome, CpuError> {
+        //WAI leaves the CPU idle until an interrupt line is asserted. An IRQ wakes it even while
+        //interrupts are disabled, in which case execution simply carries on.
+        if self.waiting {
+            if self.nmi_pending || self.irq_line {
+                self.waiting = false;
+            }else{
+                self.total_cycles += 1;
+                return Ok(StepOutcome::IDLE);
+            }
+        }
+
+        //An interrupt sequence takes the place of the next instruction
+        if let Some((emulation_vector, native_vector)) = self.pending_interrupt() {
+            if emulation_vector == EMULATION_NMI_VECTOR {
+                self.nmi_pending = false;
+            }
+            let interrupted_addr = self.program_address();
+            let vector = if self.emulation { emulation_vector } else { native_vector };
+            self.bus_fault = None;
+            let num_cycles = self.interrupt(mem_ref, emulation_vector, native_vector, false);
+            self.total_cycles += num_cycles as u64;
+            log!(Subsystem::CPU, Level::DEBUG, "Interrupt: {} - Cycles {} - Total Cycles {}", if emulation_vector == EMULATION_NMI_VECTOR { "NMI" } else { "IRQ" }, num_cycles, self.total_cycles);
+            self.take_fault(interrupted_addr, None)?;
+            return Ok(StepOutcome::INTERRUPT { vector, cycles: num_cycles });
+        }
+
+        let inst_addr = self.program_address();
+        self.bus_fault = None;
+        let inst = self.fetch(mem_ref)?;
+        let num_cycles = self.execute(&inst, mem_ref);
+        self.total_cycles += num_cycles as u64;
+        log!(Subsystem::CPU, Level::TRACE, "Instruction: {:06X} {} - Cycles {} - Total Cycles {}", inst_addr, inst.info.disassemble(&inst.data, inst_addr), num_cycles, self.total_cycles);
+        self.take_fault(inst_addr, Some(inst.opcode))?;
+        Ok(StepOutcome::INSTRUCTION { pc: inst_addr, opcode: inst.opcode, cycles: num_cycles })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Error, ExtendedMemory};
+
+    const PROGRAM_START: u32 = 0x8000;
+
+    //A 16MB bus that remembers every write the CPU makes, with nothing attached to bank $FF
+    struct RecordingBus {
+        mem: ExtendedMemory,
+        writes: Vec<(u32, u8)>
+    }
+
+    const MISSING_BANK: u32 = 0xFF0000;
+
+    impl Bus24 for RecordingBus {
+        fn read(&mut self, addr: u32) -> Result<u8, Error> {
+            if addr >= MISSING_BANK {
+                return Err(Error::READ_OUT_OF_BOUNDS);
+            }
+            self.mem.read_byte(addr)
+        }
+
+        fn write(&mut self, addr: u32, data: u8) -> Result<u8, Error> {
+            if addr >= MISSING_BANK {
+                return Err(Error::WRITE_OUT_OF_BOUNDS);
+            }
+            self.writes.push((addr, data));
+            self.mem.write_byte(addr, data)
+        }
+
+        fn peek(&self, addr: u32) -> Result<u8, Error> {
+            self.mem.read_byte(addr)
+        }
+    }
+
+    fn load(prog: &[u8]) -> (CPU65816, RecordingBus) {
+        let mut bus = RecordingBus { mem: ExtendedMemory::new(), writes: Vec::new() };
+        for (i, byte) in prog.iter().enumerate() {
+            bus.mem.write_byte(PROGRAM_START + i as u32, *byte).unwrap();
+        }
+        bus.mem.write_byte(RESET_VECTOR as u32, PROGRAM_START as u8).unwrap();
+        bus.mem.write_byte(RESET_VECTOR as u32 + 1, (PROGRAM_START >> 8) as u8).unwrap();
+        let mut cpu = CPU65816::new();
+        cpu.reset(&mut bus);
+        (cpu, bus)
+    }
+
+    #[test]
+    fn opcode_table_layers() {
+        //Shared with the 6502, with immediates sized by the register they use
+        assert_eq!(OPCODE_TABLE_65816[0xA9].mode, AddressingMode65816::IMMEDIATE_M);
+        assert_eq!(OPCODE_TABLE_65816[0xA2].mode, AddressingMode65816::IMMEDIATE_X);
+        //Added by the 65C02
+        assert_eq!(OPCODE_TABLE_65816[0xB2].inst, InstructionTypes65816::SHARED(InstructionTypes::LDA));
+        assert_eq!(OPCODE_TABLE_65816[0xB2].mode, AddressingMode65816::DIRECT_INDIRECT);
+        //The 65816's own, in slots the 65C02 used for NOPs and bit instructions
+        assert_eq!(OPCODE_TABLE_65816[0x07].mode, AddressingMode65816::DIRECT_INDIRECT_LONG);
+        assert_eq!(OPCODE_TABLE_65816[0xFB].inst, InstructionTypes65816::XCE);
+        assert_eq!(OPCODE_TABLE_65816[0x6C].base_cycles, 5);
+    }
+
+    #[test]
+    fn disassembly_keeps_program_bank() {
+        assert_eq!(OPCODE_TABLE_65816[0xFB].disassemble(&[], 0x128000), "XCE");
+        assert_eq!(OPCODE_TABLE_65816[0xAF].disassemble(&[0x10, 0x00, 0x7E], 0x128000), "LDA $7E0010");
+        assert_eq!(OPCODE_TABLE_65816[0x54].disassemble(&[0x7E, 0x12], 0x128000), "MVN $12,$7E");
+        //Branch targets land in the bank of the branch, wrapping within it
+        assert_eq!(OPCODE_TABLE_65816[0xD0].disassemble(&[0xFE], 0x128000), "BNE $128000");
+        assert_eq!(OPCODE_TABLE_65816[0x80].disassemble(&[0x10], 0x12FFF0), "BRA $120002");
+        assert_eq!(OPCODE_TABLE_65816[0x82].disassemble(&[0x00, 0x10], 0x12F000), "BRL $120003");
+    }
+
+    #[test]
+    fn native_mode_on_bus() {
+        let (mut cpu, mut bus) = load(&[
+            0x18,                       //CLC
+            0xFB,                       //XCE
+            0xC2, 0x30,                 //REP #$30
+            0xA9, 0x34, 0x12,           //LDA #$1234
+            0x8F, 0x10, 0x00, 0x7E,     //STA $7E0010
+            0xA2, 0xCD, 0xAB,           //LDX #$ABCD
+            0xE2, 0x20,                 //SEP #$20
+            0xA9, 0x7E,                 //LDA #$7E
+            0x48,                       //PHA
+            0xAB,                       //PLB
+            0xC2, 0x20,                 //REP #$20
+            0xAD, 0x10, 0x00,           //LDA $0010
+            0xDB                        //STP
+        ]);
+        while !cpu.check_halt() {
+            cpu.step(&mut bus).unwrap();
+        }
+        assert!(!cpu.emulation);
+        assert_eq!(cpu.reg_dbr, 0x7E);
+        assert_eq!(cpu.reg_accum, 0x1234);
+        assert_eq!(cpu.reg_index_x, 0xABCD);
+        assert_eq!(&bus.writes[..2], &[(0x7E0010, 0x34), (0x7E0011, 0x12)]);
+    }
+
+    #[test]
+    fn faults_are_returned() {
+        let (mut cpu, mut bus) = load(&[
+            0x8F, 0x00, 0x00, 0xFF,     //STA $FF0000
+            0xAF, 0x00, 0x00, 0xFF,     //LDA $FF0000
+            0x5C, 0x00, 0x00, 0xFF      //JML $FF0000
+        ]);
+        let error = cpu.step(&mut bus).unwrap_err();
+        assert_eq!(error, CpuError { pc: 0x8000, opcode: Some(0x8F), kind: CpuErrorKind::WRITE_FAILED(0xFF0000, Error::WRITE_OUT_OF_BOUNDS) });
+        //The faulting instruction still completes
+        assert_eq!(cpu.program_address(), 0x8004);
+
+        let error = cpu.step(&mut bus).unwrap_err();
+        assert_eq!(error, CpuError { pc: 0x8004, opcode: Some(0xAF), kind: CpuErrorKind::READ_FAILED(0xFF0000, Error::READ_OUT_OF_BOUNDS) });
+
+        assert_eq!(cpu.step(&mut bus).unwrap(), StepOutcome::INSTRUCTION { pc: 0x8008, opcode: 0x5C, cycles: 4 });
+        let error = cpu.step(&mut bus).unwrap_err();
+        assert_eq!(error, CpuError { pc: 0xFF0000, opcode: None, kind: CpuErrorKind::FETCH_FAILED(Error::READ_OUT_OF_BOUNDS) });
+    }
+}
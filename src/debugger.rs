@@ -1,5 +1,7 @@
+use crate::bus::Bus;
 use crate::cpu::CPU;
-use crate::memory::Memory;
+use crate::log;
+use crate::logging::{Level, Subsystem};
 use pretty_hex::*;
 use std::io::{stdin, stdout, Write};
 
@@ -14,15 +16,17 @@ enum Action {
     HELP,
     QUIT,
     STEP,
+    TICK,
     CONTINUE,
     PRINT_REGS,
-    PRINT_MEM
+    PRINT_MEM,
+    PRINT_INST
 }
 
 impl Debugger {
     pub fn new(enabled: bool) -> Debugger{
         Debugger {
-            enabled: enabled,
+            enabled,
             in_continue: false
         }
     }
@@ -36,21 +40,25 @@ impl Debugger {
         println!("\t\tPrints out all available debugger commands and their usage");
         println!("\tSTEP");
         println!("\t\tIncrements, and then executes, the CPU program counter by one instruction");
+        println!("\tTICK");
+        println!("\t\tRuns the CPU for a single clock cycle, which may stop part way through an instruction");
         println!("\tQUIT");
         println!("\t\tSets the halt flag of the CPU, exiting on the next CPU cycle");
         println!("\tCONTINUE");
-        println!("\t\tDisables the debugger and continues executing the CPU");
+        println!("\t\tDisables the debugger and continues executing the CPU until a BRK, watchpoint or fault");
         println!("\tPRINT REGS");
         println!("\t\tPrints the CPU registers and their values");
         println!("\tPRINT MEM");
         println!("\t\tPrints specific memory regions in their hex format");
+        println!("\tPRINT INST");
+        println!("\t\tDisassembles the instruction at the current program counter");
     }
 
     fn get_next_user_action(&self) -> Action {
         let mut input_string = String::new();
         print!("DEBUGGER> ");
         let _ = stdout().flush();
-        if let Ok(_) = stdin().read_line(&mut input_string) {
+        if stdin().read_line(&mut input_string).is_ok() {
             match input_string.trim().to_uppercase().as_str() {
                 "HELP" => {
                     Action::HELP
@@ -58,6 +66,9 @@ impl Debugger {
                 "STEP" => {
                     Action::STEP
                 },
+                "TICK" => {
+                    Action::TICK
+                },
                 "QUIT" => {
                     Action::QUIT
                 },
@@ -70,6 +81,9 @@ impl Debugger {
                 "PRINT MEM" => {
                     Action::PRINT_MEM
                 },
+                "PRINT INST" => {
+                    Action::PRINT_INST
+                },
                 _ => {
                     Action::UNKNOWN
                 }
@@ -79,24 +93,36 @@ impl Debugger {
         }
     }
 
-    fn parse_and_execute_next_user_action(&mut self, cpu: &mut CPU, mem: &mut Memory) {
+    fn parse_and_execute_next_user_action<B: Bus>(&mut self, cpu: &mut CPU, mem: &mut B) {
             match self.get_next_user_action(){
                 Action::UNKNOWN => {
-                    println!("DEBUGGER> Action: Action::UNKNOWN")
+                    log!(Subsystem::DEBUGGER, Level::INFO, "Action: Action::UNKNOWN")
                 },
                 Action::HELP => {
                     self.print_help();
                 },
                 Action::QUIT => {
-                    println!("DEBUGGER> Action: Action::QUIT");
+                    log!(Subsystem::DEBUGGER, Level::INFO, "Action: Action::QUIT");
                     cpu.set_halt();
                 },
                 Action::STEP => {
-                    println!("DEBUGGER> Action: Action::STEP");
-                    cpu.step(mem);
+                    log!(Subsystem::DEBUGGER, Level::INFO, "Action: Action::STEP");
+                    if let Err(error) = cpu.step(mem) {
+                        log!(Subsystem::DEBUGGER, Level::ERROR, "CPU fault at {}", error);
+                    }
+                    self.check_watchpoints(mem);
+                },
+                Action::TICK => {
+                    log!(Subsystem::DEBUGGER, Level::INFO, "Action: Action::TICK");
+                    match cpu.tick(mem) {
+                        Ok(false) => log!(Subsystem::DEBUGGER, Level::INFO, "Instruction in progress - Total Cycles {}", cpu.cycles()),
+                        Ok(true) => {},
+                        Err(error) => log!(Subsystem::DEBUGGER, Level::ERROR, "CPU fault at {}", error)
+                    }
+                    self.check_watchpoints(mem);
                 },
                 Action::CONTINUE => {
-                    println!("DEBUGGER> Action: Action::CONTINUE");
+                    log!(Subsystem::DEBUGGER, Level::INFO, "Action: Action::CONTINUE");
                     self.in_continue = true;
                 },
                 Action::PRINT_REGS => {
@@ -106,7 +132,7 @@ impl Debugger {
                     );
                     println!(
                         "{0: <10X} | {1: <10X} | {2: <10X} | {3: <10X} | {4: <10X} | {5: <10X} | {6: <10X} | {7: <10X} | {8: <10X} | {9: <10X} | {10: <10X} | {11: <10X} | {12: <10X} | {13: <10X}",
-                        cpu.reg_pc, cpu.reg_sp, cpu.reg_accum, cpu.reg_index_x, cpu.reg_index_y, cpu.reg_ps_nf, cpu.reg_ps_of, cpu.reg_ps_un, cpu.reg_ps_bc, cpu.reg_ps_dm, cpu.reg_ps_id, cpu.reg_ps_zf, cpu.reg_ps_cf, cpu.get_status_reg_byte()
+                        cpu.reg_pc, cpu.reg_sp, cpu.reg_accum, cpu.reg_index_x, cpu.reg_index_y, cpu.reg_ps.negative as u8, cpu.reg_ps.overflow as u8, 1, 0, cpu.reg_ps.decimal as u8, cpu.reg_ps.interrupt_disable as u8, cpu.reg_ps.zero as u8, cpu.reg_ps.carry as u8, cpu.get_status_reg_byte()
                     );
                 },
                 Action::PRINT_MEM => {
@@ -114,43 +140,74 @@ impl Debugger {
                     let mut input_string_num_bytes = String::new();
                     print!("DEBUGGER> Enter memory offset: ");
                     let _ = stdout().flush();
-                    if let Ok(_) = stdin().read_line(&mut input_string_mem_offset) {
+                    if stdin().read_line(&mut input_string_mem_offset).is_ok() {
                         print!("DEBUGGER> Enter number of bytes: ");
                         let _ = stdout().flush();
-                        if let Ok(_) = stdin().read_line(&mut input_string_num_bytes) {
+                        if stdin().read_line(&mut input_string_num_bytes).is_ok() {
                             if let Ok(mem_offset) = input_string_mem_offset.trim().parse::<u16>() {
                                 if let Ok(num_bytes) = input_string_num_bytes.trim().parse::<usize>() {
                                     //Read num_bytes from mem_offset, and print a formatted hexdump
-                                    if let Ok(bytes_vec) = mem.read_n_bytes(mem_offset, num_bytes, false) {
+                                    if let Ok(bytes_vec) = mem.peek_n_bytes(mem_offset, num_bytes) {
                                         println!("{:?}", bytes_vec.hex_dump());
                                     }else{
-                                        println!("DEBUGGER> PRINT_MEM: Failed to read {:#04x} bytes from offset {:#04x}", num_bytes, mem_offset);
+                                        log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_MEM: Failed to read {:#04x} bytes from offset {:#04x}", num_bytes, mem_offset);
                                     }
                                 }else{
-                                    println!("DEBUGGER> PRINT_MEM: Unable to interpret number of bytes");
+                                    log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_MEM: Unable to interpret number of bytes");
                                 }
                             }else{
-                                println!("DEBUGGER> PRINT_MEM: Unable to interpret memory offset");
+                                log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_MEM: Unable to interpret memory offset");
+                            }
+                        }else{
+                            log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_MEM: Unable to read user input");
+                        }
+                    }else{
+                        log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_MEM: Unable to read user input");
+                    }
+                },
+                Action::PRINT_INST => {
+                    if let Ok(opcode) = mem.peek(cpu.reg_pc) {
+                        if let Some(info) = cpu.decode(opcode) {
+                            if let Ok(data) = mem.peek_n_bytes(cpu.reg_pc.wrapping_add(1), (info.num_bytes - 1) as usize) {
+                                println!("DEBUGGER> {:#06x}: {}", cpu.reg_pc, info.disassemble(&data, cpu.reg_pc));
+                            }else{
+                                log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_INST: Failed to read operand bytes at {:#06x}", cpu.reg_pc);
                             }
                         }else{
-                            println!("DEBUGGER> PRINT_MEM: Unable to read user input");
+                            log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_INST: Unknown opcode {:#04x} at {:#06x}", opcode, cpu.reg_pc);
                         }
                     }else{
-                        println!("DEBUGGER> PRINT_MEM: Unable to read user input");
+                        log!(Subsystem::DEBUGGER, Level::WARN, "PRINT_INST: Failed to read opcode at {:#06x}", cpu.reg_pc);
                     }
                 }
             }
     }
 
-    pub fn execute_next_user_action(&mut self, cpu: &mut CPU, mem: &mut Memory) {
+    //Report a watchpoint hit by the last step, returning whether there was one
+    fn check_watchpoints<B: Bus>(&self, mem: &mut B) -> bool {
+        if let Some(hit) = mem.take_watch_hit() {
+            log!(Subsystem::DEBUGGER, Level::INFO, "Hit Watchpoint! {:?} of {:#04x} at {:#06x} by instruction at {:#06x}", hit.kind, hit.value, hit.addr, hit.pc);
+            true
+        }else{
+            false
+        }
+    }
+
+    pub fn execute_next_user_action<B: Bus>(&mut self, cpu: &mut CPU, mem: &mut B) {
         if self.in_continue {
             //If we asked the debugger to continue, step the compiler like normally, until we hit a BRK
-            if cpu.reg_ps_bc == 1 {
-                println!("DEBUGGER> Hit Breakpoint!");
+            if cpu.check_break() {
+                log!(Subsystem::DEBUGGER, Level::INFO, "Hit Breakpoint!");
                 self.in_continue = false;
                 self.parse_and_execute_next_user_action(cpu, mem);
-            }else{
-                cpu.step(mem);
+            }else if let Err(error) = cpu.step(mem) {
+                //Hand control back to the user rather than running on past the fault, reporting any
+                //watchpoint the faulting instruction hit so it isn't left over for the next step
+                log!(Subsystem::DEBUGGER, Level::ERROR, "CPU fault at {}", error);
+                self.check_watchpoints(mem);
+                self.in_continue = false;
+            }else if self.check_watchpoints(mem) {
+                self.in_continue = false;
             }
         }else{
             //This is normal debugger operation, so always ask for input each time
@@ -158,3 +215,27 @@ impl Debugger {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Memory, ProtectionPolicy, WatchCondition, Watchpoint};
+    use crate::bus::WatchKind;
+
+    #[test]
+    fn fault_consumes_watch_hit() {
+        //JSR pushes its return address, then faults reading the high byte of a target never written
+        let mut mem = Memory::new();
+        mem.write_byte(0x0400, 0x20).unwrap();
+        mem.write_byte(0x0401, 0x00).unwrap();
+        mem.set_uninitialised_read_policy(ProtectionPolicy::ERROR);
+        mem.add_watchpoint(Watchpoint { start: 0x0100, end: 0x01FF, kind: WatchKind::WRITE, condition: WatchCondition::ANY });
+        let mut cpu = CPU::new();
+        cpu.reg_pc = 0x0400;
+        let mut debugger = Debugger::new(true);
+        debugger.in_continue = true;
+        debugger.execute_next_user_action(&mut cpu, &mut mem);
+        assert!(!debugger.in_continue);
+        assert_eq!(mem.take_watch_hit(), None);
+    }
+}
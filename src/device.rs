@@ -0,0 +1,20 @@
+//A memory-mapped peripheral such as a UART, timer or display, attached to a range of addresses with
+//Memory::map_device. Offsets are relative to the start of that range, so the same device can be
+//mapped anywhere.
+pub trait Device {
+    //A read by the CPU, which may have side effects (e.g. popping a receive buffer)
+    fn read(&mut self, offset: u16) -> u8;
+
+    fn write(&mut self, offset: u16, data: u8);
+
+    //Read without side effects, for the debugger and disassembler
+    fn peek(&self, offset: u16) -> u8;
+
+    //Called once per CPU clock cycle
+    fn tick(&mut self) {}
+
+    //Whether the device is holding the IRQ line low. Memory passes this on to the CPU through Bus::irq.
+    fn irq(&self) -> bool {
+        false
+    }
+}
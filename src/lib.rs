@@ -0,0 +1,15 @@
+//Register, opcode and error names follow the 6502 datasheet naming rather than Rust casing
+#![allow(non_camel_case_types, clippy::upper_case_acronyms)]
+
+pub mod addressing;
+pub mod bus;
+pub mod cpu;
+pub mod cpu65816;
+pub mod debugger;
+pub mod device;
+pub mod logging;
+pub mod mapper;
+pub mod memory;
+pub mod opcodes;
+pub mod status;
+pub mod trace;
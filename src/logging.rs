@@ -0,0 +1,220 @@
+use std::fmt::Arguments;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+//How important a message is. A subsystem set to a given level shows that level and everything
+//above it, so TRACE shows everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    ERROR = 1,
+    WARN,
+    INFO,
+    DEBUG,
+    TRACE       //Every instruction executed, far too much output to run at full speed
+}
+
+//The parts of the emulator that produce log output, each filtered separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    CPU,
+    MEMORY,
+    DEBUGGER
+}
+
+impl Subsystem {
+    //Every subsystem, in discriminant order. The level table and set_all_levels are sized from this.
+    pub const ALL: [Subsystem; 3] = [Subsystem::CPU, Subsystem::MEMORY, Subsystem::DEBUGGER];
+}
+
+const NUM_SUBSYSTEMS: usize = Subsystem::ALL.len();
+const LEVEL_OFF: u8 = 0;
+const DEFAULT_LEVEL: u8 = Level::INFO as u8;
+
+//Most logging calls are filtered out, so the levels are atomics that can be checked without locking
+static LEVELS: [AtomicU8; NUM_SUBSYSTEMS] = [const { AtomicU8::new(DEFAULT_LEVEL) }; NUM_SUBSYSTEMS];
+static SINK: Mutex<Option<Box<dyn LogSink>>> = Mutex::new(None);
+
+//Held by tests that change the levels or the sink, so they don't filter or swallow each other's output
+#[cfg(test)]
+pub(crate) static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+//Where log messages that pass the filter end up. Replace the default stdout sink with set_sink to
+//capture output in a test harness or an embedding application.
+pub trait LogSink: Send {
+    fn write(&mut self, subsystem: Subsystem, level: Level, message: &Arguments);
+}
+
+//Prints each message on its own line prefixed with the subsystem, e.g. "CPU> Instruction: ..."
+pub struct StdoutSink;
+
+impl LogSink for StdoutSink {
+    fn write(&mut self, subsystem: Subsystem, _level: Level, message: &Arguments) {
+        println!("{:?}> {}", subsystem, message);
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "ERROR" => Ok(Level::ERROR),
+            "WARN" => Ok(Level::WARN),
+            "INFO" => Ok(Level::INFO),
+            "DEBUG" => Ok(Level::DEBUG),
+            "TRACE" => Ok(Level::TRACE),
+            _ => Err(format!("Unknown log level {}", s))
+        }
+    }
+}
+
+impl FromStr for Subsystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_uppercase();
+        Subsystem::ALL.into_iter()
+            .find(|subsystem| format!("{:?}", subsystem) == name)
+            .ok_or_else(|| format!("Unknown log subsystem {}", s))
+    }
+}
+
+impl Level {
+    fn from_u8(value: u8) -> Option<Level> {
+        match value {
+            1 => Some(Level::ERROR),
+            2 => Some(Level::WARN),
+            3 => Some(Level::INFO),
+            4 => Some(Level::DEBUG),
+            5 => Some(Level::TRACE),
+            _ => None
+        }
+    }
+}
+
+//Show messages from the subsystem at this level and above, or none at all if the level is None
+pub fn set_level(subsystem: Subsystem, level: Option<Level>) {
+    LEVELS[subsystem as usize].store(level.map_or(LEVEL_OFF, |level| level as u8), Ordering::Relaxed);
+}
+
+pub fn level(subsystem: Subsystem) -> Option<Level> {
+    Level::from_u8(LEVELS[subsystem as usize].load(Ordering::Relaxed))
+}
+
+pub fn set_all_levels(level: Option<Level>) {
+    for subsystem in Subsystem::ALL {
+        set_level(subsystem, level);
+    }
+}
+
+//Turn off all logging, for running at full speed
+pub fn disable() {
+    set_all_levels(None);
+}
+
+pub fn set_sink(sink: Box<dyn LogSink>) {
+    if let Ok(mut current) = SINK.lock() {
+        *current = Some(sink);
+    }
+}
+
+pub fn enabled(subsystem: Subsystem, level: Level) -> bool {
+    (level as u8) <= LEVELS[subsystem as usize].load(Ordering::Relaxed)
+}
+
+//Send a message to the sink. Use the log! macro instead, which skips formatting filtered out messages.
+pub fn write(subsystem: Subsystem, level: Level, message: Arguments) {
+    if let Ok(mut sink) = SINK.lock() {
+        if let Some(sink) = sink.as_mut() {
+            sink.write(subsystem, level, &message);
+        }else{
+            StdoutSink.write(subsystem, level, &message);
+        }
+    }
+}
+
+//Log a formatted message, e.g. log!(Subsystem::CPU, Level::TRACE, "Instruction: {}", text). The
+//arguments are only evaluated if the subsystem is showing that level.
+#[macro_export]
+macro_rules! log {
+    ($subsystem:expr, $level:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($subsystem, $level) {
+            $crate::logging::write($subsystem, $level, format_args!($($arg)+));
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    struct RecordingSink(Arc<Mutex<Vec<(Subsystem, Level, String)>>>);
+
+    impl LogSink for RecordingSink {
+        fn write(&mut self, subsystem: Subsystem, level: Level, message: &Arguments) {
+            self.0.lock().unwrap().push((subsystem, level, message.to_string()));
+        }
+    }
+
+    //Run the test with its own sink, putting the default levels and stdout back afterwards
+    fn with_recording_sink(test: impl FnOnce(&Mutex<Vec<(Subsystem, Level, String)>>)) {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        set_sink(Box::new(RecordingSink(messages.clone())));
+        test(&messages);
+        set_sink(Box::new(StdoutSink));
+        set_all_levels(Some(Level::INFO));
+    }
+
+    #[test]
+    fn subsystem_table() {
+        for (index, subsystem) in Subsystem::ALL.into_iter().enumerate() {
+            assert_eq!(subsystem as usize, index);
+            assert_eq!(format!("{:?}", subsystem).to_lowercase().parse::<Subsystem>(), Ok(subsystem));
+        }
+        assert!("video".parse::<Subsystem>().is_err());
+        assert_eq!("Trace".parse::<Level>(), Ok(Level::TRACE));
+    }
+
+    #[test]
+    fn levels_filter_each_subsystem() {
+        with_recording_sink(|messages| {
+            set_level(Subsystem::CPU, Some(Level::WARN));
+            set_level(Subsystem::MEMORY, Some(Level::TRACE));
+            set_level(Subsystem::DEBUGGER, None);
+            assert_eq!(level(Subsystem::CPU), Some(Level::WARN));
+            assert!(enabled(Subsystem::CPU, Level::ERROR));
+            assert!(!enabled(Subsystem::CPU, Level::INFO));
+
+            crate::log!(Subsystem::CPU, Level::INFO, "filtered");
+            crate::log!(Subsystem::CPU, Level::WARN, "cpu {}", 1);
+            crate::log!(Subsystem::MEMORY, Level::TRACE, "memory {}", 2);
+            crate::log!(Subsystem::DEBUGGER, Level::ERROR, "filtered");
+            assert_eq!(*messages.lock().unwrap(), vec![
+                (Subsystem::CPU, Level::WARN, String::from("cpu 1")),
+                (Subsystem::MEMORY, Level::TRACE, String::from("memory 2"))
+            ]);
+        });
+    }
+
+    #[test]
+    fn all_levels_off() {
+        with_recording_sink(|messages| {
+            set_all_levels(None);
+            for subsystem in Subsystem::ALL {
+                assert_eq!(level(subsystem), None);
+                assert!(!enabled(subsystem, Level::ERROR));
+                crate::log!(subsystem, Level::ERROR, "filtered");
+            }
+            assert!(messages.lock().unwrap().is_empty());
+
+            set_all_levels(Some(Level::DEBUG));
+            for subsystem in Subsystem::ALL {
+                assert!(enabled(subsystem, Level::DEBUG));
+                assert!(!enabled(subsystem, Level::TRACE));
+            }
+        });
+    }
+}
@@ -1,6 +1,106 @@
-mod cpu;
-mod debugger;
-mod memory;
+use emulator::logging::{self, Level, Subsystem};
+use emulator::trace::TraceWriter;
+use emulator::bus::WatchKind;
+use emulator::memory::{WatchCondition, Watchpoint};
+use emulator::{cpu, cpu65816, debugger, log, memory};
+
+const PROGRAM_START: u16 = 0x0000;
+const RESET_VECTOR: u16 = 0xFFFC;
+const PROGRAM_FILE: &str = "programs/fast-multiply-by-ten.bin";
+const DEFAULT_65816_CYCLES: u64 = 1000;
+
+//Apply the logging options: "--log <subsystem|all>=<level|off>" (repeatable) and "--quiet"
+fn configure_logging(args: &[String]) {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--quiet" {
+            logging::disable();
+        }else if arg == "--log" {
+            if let Some((subsystem, level)) = args.next().and_then(|spec| spec.split_once('=')) {
+                let level = if level.eq_ignore_ascii_case("off") { Ok(None) } else { level.parse::<Level>().map(Some) };
+                match level {
+                    Ok(level) if subsystem.eq_ignore_ascii_case("all") => logging::set_all_levels(level),
+                    Ok(level) => {
+                        if let Ok(subsystem) = subsystem.parse::<Subsystem>() {
+                            logging::set_level(subsystem, level);
+                        }else{
+                            println!("Unknown log subsystem {}", subsystem);
+                        }
+                    },
+                    Err(error) => println!("{}", error)
+                }
+            }else{
+                println!("Expected --log <subsystem>=<level>");
+            }
+        }
+    }
+}
+
+//Apply the watchpoint options: "--watch <start>[-<end>]=<read|write|access>[:<value>]" (repeatable),
+//with addresses and the value in hex, e.g. "--watch 0200-02ff=write:ff"
+fn configure_watchpoints(args: &[String], mem: &mut memory::Memory) {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--watch" {
+            let watchpoint = args.next().and_then(|spec| spec.split_once('=')).and_then(|(range, access)| {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let (kind, value) = access.split_once(':').map_or((access, None), |(kind, value)| (kind, Some(value)));
+                let kind = match kind.to_lowercase().as_str() {
+                    "read" => WatchKind::READ,
+                    "write" => WatchKind::WRITE,
+                    "access" => WatchKind::ACCESS,
+                    _ => return None
+                };
+                let condition = match value {
+                    Some(value) => WatchCondition::EQUAL(u8::from_str_radix(value, 16).ok()?),
+                    None => WatchCondition::ANY
+                };
+                let (start, end) = (u16::from_str_radix(start, 16).ok()?, u16::from_str_radix(end, 16).ok()?);
+                if end < start {
+                    return None;
+                }
+                Some(Watchpoint { start, end, kind, condition })
+            });
+            if let Some(watchpoint) = watchpoint {
+                mem.add_watchpoint(watchpoint);
+            }else{
+                println!("Expected --watch <start>[-<end>]=<read|write|access>[:<value>]");
+            }
+        }
+    }
+}
+
+//Run the program on the 65816 core, selected with "--cpu 65816". The debugger only knows the 6502,
+//so this runs until the program stops with STP, hits a BRK, or has used up "--cycles <count>".
+fn run_65816(args: &[String]) {
+    let max_cycles = match args.iter().position(|arg| arg == "--cycles").map(|index| args.get(index + 1).and_then(|count| count.parse::<u64>().ok())) {
+        Some(Some(count)) => count,
+        Some(None) => {
+            println!("Expected --cycles <count>");
+            return;
+        },
+        None => DEFAULT_65816_CYCLES
+    };
+
+    let mut mem = memory::ExtendedMemory::new();
+    let mut cpu = cpu65816::CPU65816::new();
+
+    //Load program into bank zero, and point the reset vector at it
+    let loaded = mem.load_program_from_file(PROGRAM_START as u32, PROGRAM_FILE)
+        .and_then(|_| mem.write_byte(RESET_VECTOR as u32, PROGRAM_START as u8))
+        .and_then(|_| mem.write_byte(RESET_VECTOR as u32 + 1, (PROGRAM_START >> 8) as u8));
+    if loaded.is_ok() {
+        cpu.reset(&mut mem);
+        while !cpu.check_halt() && !cpu.check_break() && cpu.cycles() < max_cycles {
+            if let Err(error) = cpu.step(&mut mem) {
+                log!(Subsystem::CPU, Level::ERROR, "Fault at {}", error);
+                break;
+            }
+        }
+        log!(Subsystem::CPU, Level::INFO, "Stopped at {:#08x} after {} cycles: A {:#06x} X {:#06x} Y {:#06x} SP {:#06x} P {:#04x}",
+            cpu.program_address(), cpu.cycles(), cpu.reg_accum, cpu.reg_index_x, cpu.reg_index_y, cpu.reg_sp, cpu.get_status_reg_byte());
+    }
+}
 
 fn main() {
     
@@ -9,25 +109,54 @@ fn main() {
     println!("6502 Emulator");
     println!("======================================");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    configure_logging(&args);
+
+    if let Some(index) = args.iter().position(|arg| arg == "--cpu") {
+        match args.get(index + 1).map(String::as_str) {
+            Some("6502") => {},
+            Some("65816") => return run_65816(&args),
+            _ => {
+                println!("Expected --cpu <6502|65816>");
+                return;
+            }
+        }
+    }
 
     //Create the memory
     let mut mem = memory::Memory::new();
+    configure_watchpoints(&args, &mut mem);
 
     //Create the cpu
     let mut cpu = cpu::CPU::new();
 
+    //Write a nestest.log style trace of every instruction if asked to with "--trace <file>"
+    if let Some(filename) = args.iter().position(|arg| arg == "--trace").and_then(|index| args.get(index + 1)) {
+        match TraceWriter::create(filename) {
+            Ok(writer) => cpu.set_trace_writer(Some(writer)),
+            Err(error) => log!(Subsystem::CPU, Level::ERROR, "Unable to create trace file {}: {}", filename, error)
+        }
+    }
+
     //Create the debugger
     let mut debugger = debugger::Debugger::new(true);
 
-    //Load program
-    if let Ok(_) = mem.load_program_from_file(0, "programs/fast-multiply-by-ten.bin", true) {
+    //Load program, and point the reset vector at it
+    let loaded = mem.load_program_from_file(PROGRAM_START, PROGRAM_FILE)
+        .and_then(|_| mem.write_byte(RESET_VECTOR, PROGRAM_START as u8))
+        .and_then(|_| mem.write_byte(RESET_VECTOR + 1, (PROGRAM_START >> 8) as u8));
+    if loaded.is_ok() {
+        //Start executing from the reset vector
+        cpu.reset(&mut mem);
+
         //Continue to execute instructions untilwe need to halt
         while !cpu.check_halt() {
             //Check to see if the debugger is enabled/disabled
             if debugger.is_enabled() {
                 debugger.execute_next_user_action(&mut cpu, &mut mem);
-            } else {
-                cpu.step(&mut mem);
+            } else if let Err(error) = cpu.step(&mut mem) {
+                log!(Subsystem::CPU, Level::ERROR, "Fault at {}", error);
+                break;
             }
         }
     }
@@ -0,0 +1,460 @@
+//Bank switching for systems with more than 64K of ROM or RAM. A mapper installed with
+//Memory::set_mapper sees every access before devices, ROM regions and RAM, and decides whether to
+//answer it from one of its banks, send it straight to RAM, or leave it alone.
+
+const BANK_SIZE_16K: usize = 0x4000;
+
+//Where a read ends up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MappedRead {
+    UNMAPPED,       //Not banked: handled by devices, ROM and RAM as usual
+    RAM,            //Banked to the RAM underneath, bypassing any device mapped there
+    VALUE(u8)       //Answered by the mapper from one of its banks or registers
+}
+
+//Where a write ends up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MappedWrite {
+    UNMAPPED,       //Not banked: handled by devices, ROM and RAM as usual
+    RAM,            //Goes to the RAM underneath, e.g. a write to a ROM that is banked in
+    HANDLED         //Consumed by the mapper, e.g. a bank select register or banked RAM
+}
+
+pub trait Mapper {
+    //Read without side effects
+    fn peek(&self, addr: u16) -> MappedRead;
+
+    //A read by the CPU. Override this for mappers whose registers change state when read.
+    fn read(&mut self, addr: u16) -> MappedRead {
+        self.peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) -> MappedWrite;
+
+    //Called once per CPU clock cycle, after that cycle's access, for mappers that care about timing
+    fn tick(&mut self) {}
+}
+
+//Pad or truncate an image to a whole number of banks, and at least one
+fn whole_banks(mut data: Vec<u8>, bank_size: usize) -> Vec<u8> {
+    let num_banks = data.len().div_ceil(bank_size).max(1);
+    data.resize(num_banks * bank_size, 0);
+    data
+}
+
+//A 16K window showing one of many 16K banks, chosen by writing the bank number to a latch
+//elsewhere in the address space. Banks can be ROM, or RAM if writable.
+pub struct BankSwitch16K {
+    window_start: u16,
+    register: u16,
+    banks: Vec<u8>,
+    writable: bool,
+    bank: usize
+}
+
+impl BankSwitch16K {
+    pub fn new(window_start: u16, register: u16, banks: Vec<u8>, writable: bool) -> BankSwitch16K {
+        BankSwitch16K { window_start, register, banks: whole_banks(banks, BANK_SIZE_16K), writable, bank: 0 }
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    fn offset(&self, addr: u16) -> Option<usize> {
+        let offset = addr.wrapping_sub(self.window_start) as usize;
+        if addr >= self.window_start && offset < BANK_SIZE_16K {
+            Some(self.bank * BANK_SIZE_16K + offset)
+        } else {
+            None
+        }
+    }
+}
+
+impl Mapper for BankSwitch16K {
+    fn peek(&self, addr: u16) -> MappedRead {
+        self.offset(addr).map_or(MappedRead::UNMAPPED, |offset| MappedRead::VALUE(self.banks[offset]))
+    }
+
+    fn write(&mut self, addr: u16, data: u8) -> MappedWrite {
+        if addr == self.register {
+            self.bank = data as usize % (self.banks.len() / BANK_SIZE_16K);
+            MappedWrite::HANDLED
+        } else if let Some(offset) = self.offset(addr) {
+            if self.writable {
+                self.banks[offset] = data;
+            }
+            MappedWrite::HANDLED
+        } else {
+            MappedWrite::UNMAPPED
+        }
+    }
+}
+
+//NES mapper 2. $8000-$BFFF shows a switchable 16K PRG ROM bank selected by writing anywhere in
+//$8000-$FFFF, while $C000-$FFFF is fixed to the last bank.
+pub struct UxROM {
+    prg_rom: Vec<u8>,
+    bank: usize
+}
+
+impl UxROM {
+    pub fn new(prg_rom: Vec<u8>) -> UxROM {
+        UxROM { prg_rom: whole_banks(prg_rom, BANK_SIZE_16K), bank: 0 }
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    fn num_banks(&self) -> usize {
+        self.prg_rom.len() / BANK_SIZE_16K
+    }
+}
+
+impl Mapper for UxROM {
+    fn peek(&self, addr: u16) -> MappedRead {
+        let offset = addr as usize & (BANK_SIZE_16K - 1);
+        match addr {
+            0x8000..=0xBFFF => MappedRead::VALUE(self.prg_rom[self.bank * BANK_SIZE_16K + offset]),
+            0xC000..=0xFFFF => MappedRead::VALUE(self.prg_rom[(self.num_banks() - 1) * BANK_SIZE_16K + offset]),
+            _ => MappedRead::UNMAPPED
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) -> MappedWrite {
+        if addr >= 0x8000 {
+            self.bank = data as usize % self.num_banks();
+            MappedWrite::HANDLED
+        } else {
+            MappedWrite::UNMAPPED
+        }
+    }
+}
+
+//NES mapper 1 PRG banking. Registers are loaded a bit at a time through a serial port at
+//$8000-$FFFF: five writes of bit 0 fill the register chosen by the address of the last write,
+//and any write with bit 7 set resets the port. A write on the cycle straight after another is
+//ignored, so only the first of a read-modify-write instruction's two writes counts; cycles are
+//counted by Mapper::tick. $6000-$7FFF is PRG RAM, taken from the RAM below.
+pub struct MMC1 {
+    prg_rom: Vec<u8>,
+    shift: u8,
+    shift_count: u8,
+    wrote_this_cycle: bool,
+    wrote_last_cycle: bool,
+    control: u8,        //Bits 2-3 select the PRG banking mode
+    chr_bank_0: u8,     //CHR banks are kept for completeness, the PPU side is not emulated
+    chr_bank_1: u8,
+    prg_bank: u8
+}
+
+impl MMC1 {
+    pub fn new(prg_rom: Vec<u8>) -> MMC1 {
+        //Power on in mode 3 so the reset vector in the last bank is visible
+        MMC1 {
+            prg_rom: whole_banks(prg_rom, BANK_SIZE_16K),
+            shift: 0,
+            shift_count: 0,
+            wrote_this_cycle: false,
+            wrote_last_cycle: false,
+            control: 0x0C,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0
+        }
+    }
+
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    pub fn chr_banks(&self) -> (u8, u8) {
+        (self.chr_bank_0, self.chr_bank_1)
+    }
+
+    fn num_banks(&self) -> usize {
+        self.prg_rom.len() / BANK_SIZE_16K
+    }
+
+    //16K banks shown at $8000 and $C000 in the current mode
+    fn banks(&self) -> (usize, usize) {
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let last = self.num_banks() - 1;
+        match (self.control >> 2) & 0x03 {
+            0 | 1 => (bank & !1, bank | 1),     //One 32K bank, ignoring the low bit
+            2 => (0, bank),                     //First bank fixed at $8000
+            _ => (bank, last)                   //Last bank fixed at $C000
+        }
+    }
+}
+
+impl Mapper for MMC1 {
+    fn peek(&self, addr: u16) -> MappedRead {
+        let offset = addr as usize & (BANK_SIZE_16K - 1);
+        let (low, high) = self.banks();
+        match addr {
+            0x6000..=0x7FFF => MappedRead::RAM,
+            0x8000..=0xBFFF => MappedRead::VALUE(self.prg_rom[(low % self.num_banks()) * BANK_SIZE_16K + offset]),
+            0xC000..=0xFFFF => MappedRead::VALUE(self.prg_rom[(high % self.num_banks()) * BANK_SIZE_16K + offset]),
+            _ => MappedRead::UNMAPPED
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) -> MappedWrite {
+        match addr {
+            0x6000..=0x7FFF => MappedWrite::RAM,
+            0x8000..=0xFFFF => {
+                let consecutive = self.wrote_last_cycle;
+                self.wrote_this_cycle = true;
+                if consecutive {
+                    //Dropped, e.g. the second write of INC or ASL
+                } else if (data & 0x80) != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0C;
+                } else {
+                    self.shift |= (data & 0x01) << self.shift_count;
+                    self.shift_count += 1;
+                    if self.shift_count == 5 {
+                        match (addr >> 13) & 0x03 {
+                            0 => self.control = self.shift,
+                            1 => self.chr_bank_0 = self.shift,
+                            2 => self.chr_bank_1 = self.shift,
+                            _ => self.prg_bank = self.shift
+                        }
+                        self.shift = 0;
+                        self.shift_count = 0;
+                    }
+                }
+                MappedWrite::HANDLED
+            },
+            _ => MappedWrite::UNMAPPED
+        }
+    }
+
+    fn tick(&mut self) {
+        self.wrote_last_cycle = std::mem::take(&mut self.wrote_this_cycle);
+    }
+}
+
+//The Commodore 64's 6510 processor port. $00 is the data direction register and $01 the port,
+//whose LORAM, HIRAM and CHAREN bits choose whether BASIC ($A000), the KERNAL ($E000) and the
+//character ROM or I/O ($D000) are visible over the RAM. Writes to a visible ROM go to the RAM
+//below it, and when I/O is visible accesses fall through to the devices mapped at $D000. Like the
+//real machine, writes to $00 and $01 also reach the RAM underneath, though reads see the port.
+pub struct C64ProcessorPort {
+    basic: Vec<u8>,
+    kernal: Vec<u8>,
+    charset: Vec<u8>,
+    direction: u8,
+    data: u8
+}
+
+const C64_LORAM: u8 = 0x01;
+const C64_HIRAM: u8 = 0x02;
+const C64_CHAREN: u8 = 0x04;
+
+impl C64ProcessorPort {
+    pub fn new(basic: Vec<u8>, kernal: Vec<u8>, charset: Vec<u8>) -> C64ProcessorPort {
+        let rom = |mut image: Vec<u8>, size: usize| { image.resize(size, 0); image };
+        C64ProcessorPort { basic: rom(basic, 0x2000), kernal: rom(kernal, 0x2000), charset: rom(charset, 0x1000), direction: 0, data: 0 }
+    }
+
+    //Pins set as inputs are pulled high, so at power on everything is banked in
+    pub fn port(&self) -> u8 {
+        (self.data & self.direction) | !self.direction
+    }
+}
+
+impl Mapper for C64ProcessorPort {
+    fn peek(&self, addr: u16) -> MappedRead {
+        let port = self.port();
+        let (loram, hiram) = ((port & C64_LORAM) != 0, (port & C64_HIRAM) != 0);
+        match addr {
+            0x0000 => MappedRead::VALUE(self.direction),
+            0x0001 => MappedRead::VALUE(port),
+            0xA000..=0xBFFF if loram && hiram => MappedRead::VALUE(self.basic[(addr - 0xA000) as usize]),
+            0xD000..=0xDFFF if !loram && !hiram => MappedRead::RAM,
+            0xD000..=0xDFFF if (port & C64_CHAREN) == 0 => MappedRead::VALUE(self.charset[(addr - 0xD000) as usize]),
+            0xD000..=0xDFFF => MappedRead::UNMAPPED,
+            0xE000..=0xFFFF if hiram => MappedRead::VALUE(self.kernal[(addr - 0xE000) as usize]),
+            _ => MappedRead::RAM
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) -> MappedWrite {
+        match addr {
+            0x0000 => {
+                self.direction = data;
+                MappedWrite::RAM
+            },
+            0x0001 => {
+                self.data = data;
+                MappedWrite::RAM
+            },
+            //I/O is only visible when the character ROM is banked out and some ROM is banked in
+            0xD000..=0xDFFF if (self.port() & (C64_LORAM | C64_HIRAM)) != 0 && (self.port() & C64_CHAREN) != 0 => MappedWrite::UNMAPPED,
+            _ => MappedWrite::RAM
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+    use crate::memory::Memory;
+
+    //PRG ROM of num_banks 16K banks, each filled with its own bank number
+    fn numbered_banks(num_banks: usize) -> Vec<u8> {
+        (0..num_banks).flat_map(|bank| vec![bank as u8; BANK_SIZE_16K]).collect()
+    }
+
+    //Load a register through the MMC1's serial port, a bit per write, with a CPU cycle between each
+    fn mmc1_load(mmc1: &mut MMC1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            assert_eq!(mmc1.write(addr, (value >> bit) & 0x01), MappedWrite::HANDLED);
+            mmc1.tick();
+            mmc1.tick();
+        }
+    }
+
+    #[test]
+    fn bank_switch_16k() {
+        let mut mapper = BankSwitch16K::new(0x8000, 0x0200, numbered_banks(4), true);
+        assert_eq!(mapper.peek(0x8000), MappedRead::VALUE(0));
+        assert_eq!(mapper.peek(0xC000), MappedRead::UNMAPPED);
+        assert_eq!(mapper.write(0x0200, 6), MappedWrite::HANDLED);
+        assert_eq!(mapper.bank(), 2);
+        assert_eq!(mapper.write(0xBFFF, 0xAA), MappedWrite::HANDLED);
+        assert_eq!(mapper.peek(0xBFFF), MappedRead::VALUE(0xAA));
+        assert_eq!(mapper.write(0x0200, 1), MappedWrite::HANDLED);
+        assert_eq!(mapper.peek(0xBFFF), MappedRead::VALUE(1));
+    }
+
+    #[test]
+    fn uxrom_banks() {
+        let mut mapper = UxROM::new(numbered_banks(4));
+        assert_eq!(mapper.peek(0x8000), MappedRead::VALUE(0));
+        assert_eq!(mapper.peek(0xC000), MappedRead::VALUE(3));
+        assert_eq!(mapper.write(0xFFF0, 2), MappedWrite::HANDLED);
+        assert_eq!(mapper.peek(0xBFFF), MappedRead::VALUE(2));
+        assert_eq!(mapper.peek(0xFFFF), MappedRead::VALUE(3));
+        assert_eq!(mapper.peek(0x6000), MappedRead::UNMAPPED);
+    }
+
+    #[test]
+    fn mmc1_shift_register() {
+        let mut mmc1 = MMC1::new(numbered_banks(8));
+        //Powers on with the last bank fixed at $C000
+        assert_eq!(mmc1.peek(0x8000), MappedRead::VALUE(0));
+        assert_eq!(mmc1.peek(0xC000), MappedRead::VALUE(7));
+
+        mmc1_load(&mut mmc1, 0xE000, 5);
+        assert_eq!(mmc1.peek(0x8000), MappedRead::VALUE(5));
+        assert_eq!(mmc1.peek(0xC000), MappedRead::VALUE(7));
+
+        //Mode 2 fixes the first bank at $8000 and switches $C000
+        mmc1_load(&mut mmc1, 0x8000, 0x08);
+        assert_eq!(mmc1.control(), 0x08);
+        assert_eq!(mmc1.peek(0x8000), MappedRead::VALUE(0));
+        assert_eq!(mmc1.peek(0xC000), MappedRead::VALUE(5));
+
+        //Mode 0 switches 32K at a time, ignoring the low bit of the bank
+        mmc1_load(&mut mmc1, 0x8000, 0x00);
+        assert_eq!(mmc1.peek(0x8000), MappedRead::VALUE(4));
+        assert_eq!(mmc1.peek(0xC000), MappedRead::VALUE(5));
+
+        mmc1_load(&mut mmc1, 0xA000, 0x11);
+        mmc1_load(&mut mmc1, 0xC000, 0x12);
+        assert_eq!(mmc1.chr_banks(), (0x11, 0x12));
+
+        //A write with bit 7 set part way through discards the bits so far and goes back to mode 3
+        mmc1.write(0xE000, 1);
+        mmc1.tick();
+        mmc1.tick();
+        mmc1.write(0xE000, 0x80);
+        mmc1.tick();
+        mmc1.tick();
+        assert_eq!(mmc1.control(), 0x0C);
+        mmc1_load(&mut mmc1, 0xE000, 2);
+        assert_eq!(mmc1.peek(0x8000), MappedRead::VALUE(2));
+        assert_eq!(mmc1.peek(0xC000), MappedRead::VALUE(7));
+    }
+
+    #[test]
+    fn mmc1_ignores_consecutive_writes() {
+        let mut mmc1 = MMC1::new(numbered_banks(8));
+        mmc1.write(0xE000, 1);
+        mmc1.tick();
+        //Written on the very next cycle, so dropped
+        mmc1.write(0xE000, 0);
+        mmc1.tick();
+        mmc1.tick();
+        for _ in 0..4 {
+            mmc1.write(0xE000, 0);
+            mmc1.tick();
+            mmc1.tick();
+        }
+        assert_eq!(mmc1.peek(0x8000), MappedRead::VALUE(1));
+
+        //INC $FFFF writes back the $FF it read, which resets the port to mode 3, then on the next
+        //cycle $00, which is dropped. Four more zeros are then not enough to load a register.
+        let mut prg_rom = numbered_banks(8);
+        for bank in 0..8 {
+            prg_rom[bank * BANK_SIZE_16K + BANK_SIZE_16K - 1] = 0xFF;
+        }
+        let mut mmc1 = MMC1::new(prg_rom);
+        mmc1_load(&mut mmc1, 0x8000, 0x08);
+        mmc1_load(&mut mmc1, 0xE000, 5);
+        let mut mem = Memory::new();
+        mem.set_mapper(Some(Box::new(mmc1)));
+        let prog = [0xEE, 0xFF, 0xFF, 0xA9, 0x00, 0x8D, 0x00, 0xE0, 0x8D, 0x00, 0xE0, 0x8D, 0x00, 0xE0, 0x8D, 0x00, 0xE0];
+        for (offset, byte) in prog.iter().enumerate() {
+            mem.write_byte(0x0400 + offset as u16, *byte).unwrap();
+        }
+        let mut cpu = CPU::new();
+        cpu.reg_pc = 0x0400;
+        for _ in 0..6 {
+            cpu.step(&mut mem).unwrap();
+        }
+        assert_eq!(mem.peek(0x8000).unwrap(), 5);
+        assert_eq!(mem.peek(0xC000).unwrap(), 7);
+    }
+
+    #[test]
+    fn c64_processor_port() {
+        let mut port = C64ProcessorPort::new(vec![0xBA; 0x2000], vec![0xEE; 0x2000], vec![0xC0; 0x1000]);
+        //Every line is an input at power on, so they are all pulled high and everything is banked in
+        assert_eq!(port.peek(0xA000), MappedRead::VALUE(0xBA));
+        assert_eq!(port.peek(0xD000), MappedRead::UNMAPPED);
+        assert_eq!(port.peek(0xE000), MappedRead::VALUE(0xEE));
+        assert_eq!(port.write(0xA000, 0x55), MappedWrite::RAM);
+
+        //The port registers are also written through to RAM
+        assert_eq!(port.write(0x0000, 0x07), MappedWrite::RAM);
+        assert_eq!(port.write(0x0001, C64_HIRAM | C64_CHAREN), MappedWrite::RAM);
+        assert_eq!(port.peek(0x0000), MappedRead::VALUE(0x07));
+        assert_eq!(port.peek(0x0001), MappedRead::VALUE(0xFE));
+        assert_eq!(port.peek(0xA000), MappedRead::RAM);
+        assert_eq!(port.peek(0xE000), MappedRead::VALUE(0xEE));
+
+        //Character ROM replaces I/O when CHAREN is low
+        port.write(0x0001, C64_HIRAM);
+        assert_eq!(port.peek(0xD000), MappedRead::VALUE(0xC0));
+        assert_eq!(port.write(0xD000, 0x55), MappedWrite::RAM);
+
+        //With LORAM and HIRAM both low everything is RAM
+        port.write(0x0001, 0x00);
+        assert_eq!(port.peek(0xD000), MappedRead::RAM);
+        assert_eq!(port.peek(0xE000), MappedRead::RAM);
+
+        let mut mem = Memory::new();
+        mem.set_mapper(Some(Box::new(port)));
+        mem.write_byte(0x0001, 0x37).unwrap();
+        mem.set_mapper(None);
+        assert_eq!(mem.peek(0x0001).unwrap(), 0x37);
+    }
+}
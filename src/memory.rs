@@ -1,99 +1,485 @@
-use core::num;
+use crate::bus::{Bus, Bus24, WatchHit, WatchKind};
+use crate::device::Device;
+use crate::log;
+use crate::logging::{Level, Subsystem};
+use crate::mapper::{MappedRead, MappedWrite, Mapper};
 use std::fs::File;
 use std::io::Read;
 
 const MAX_MEMORY_SIZE_BYTES: usize = 65536; //Max size is the fact the 6502 has an 8 bit accumulator
-const STACK_START: u16 = 0x0100;
-const STACK_END: u16 = 0x01FF;
+const MAX_EXTENDED_MEMORY_SIZE_BYTES: usize = 0x1000000; //The 65816 has a 24 bit address bus
 
 pub struct Memory {
     mem: [u8; MAX_MEMORY_SIZE_BYTES],
+    devices: Vec<DeviceRegion>,         //Address ranges handled by devices instead of RAM
+    roms: Vec<RomRegion>,               //Read-only address ranges
+    mapper: Option<Box<dyn Mapper>>,    //Bank switching, consulted before everything else
+    protected: Vec<ProtectedRegion>,    //Address ranges with restricted CPU access
+    on_violation: ProtectionPolicy,
+    on_execute_written: ProtectionPolicy,   //What to do when the CPU executes bytes it wrote itself
+    writers: Vec<Option<u16>>,          //PC of the instruction that last wrote each byte, while tracked
+    current_pc: u16,                    //Instruction making the current access, from Bus::begin_instruction
+    on_uninitialised_read: ProtectionPolicy,
+    initialised: Vec<bool>,             //Whether each byte has been written or loaded since power on
+    watchpoints: Vec<Watchpoint>,
+    watch_hit: Option<WatchHit>,        //First watchpoint hit since the last Bus::take_watch_hit
 }
 
-#[derive(Debug)]
+//What the value read or written must be for a watchpoint to fire
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchCondition {
+    ANY,
+    EQUAL(u8),
+    NOT_EQUAL(u8),
+    MASKED(u8, u8)  //Mask then expected value, e.g. MASKED(0x80, 0x80) fires when bit 7 is set
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,   //Inclusive
+    pub kind: WatchKind,
+    pub condition: WatchCondition
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: u16, value: u8, kind: WatchKind) -> bool {
+        addr >= self.start && addr <= self.end
+            && (self.kind == WatchKind::ACCESS || self.kind == kind)
+            && match self.condition {
+                WatchCondition::ANY => true,
+                WatchCondition::EQUAL(expected) => value == expected,
+                WatchCondition::NOT_EQUAL(expected) => value != expected,
+                WatchCondition::MASKED(mask, expected) => (value & mask) == expected
+            }
+    }
+}
+
+//What RAM holds at power on. Real RAM comes up in no particular state, so filling it with something
+//other than zeros shows up programs that rely on memory they never cleared.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RamFill {
+    ZERO,
+    PATTERN(Vec<u8>),                   //Repeated from $0000, e.g. vec![0x00, 0xFF]
+    RANDOM(u64)                         //Seed, so runs can be repeated
+}
+
+//What happens when something writes to ROM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RomWritePolicy {
+    IGNORE,         //Drop the write silently, like real hardware
+    LOG,            //Drop the write and log a warning
+    ERROR           //Refuse the write with Error::WRITE_TO_ROM, which makes the CPU step fail
+}
+
+struct RomRegion {
+    start: u16,
+    end: u16,                           //Inclusive
+    on_write: RomWritePolicy
+}
+
+//What happens when the CPU accesses a protected range in a way its permissions don't allow. Real
+//hardware has no protection, so it is OFF by default and the other policies are debugging aids.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProtectionPolicy {
+    OFF,            //Allow everything without checking
+    LOG,            //Allow the access and log a warning
+    ERROR           //Refuse the access with a *_PROTECTED error, which makes the CPU step fail
+}
+
+//What the CPU may do in a protected range. Addresses outside every protected range allow everything.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool       //Opcode and operand fetches
+}
+
+impl Permissions {
+    pub const ALL: Permissions = Permissions { read: true, write: true, execute: true };
+    pub const DATA: Permissions = Permissions { read: true, write: true, execute: false };
+    pub const CODE: Permissions = Permissions { read: true, write: false, execute: true };
+    pub const READ_ONLY: Permissions = Permissions { read: true, write: false, execute: false };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    READ,
+    WRITE,
+    EXECUTE
+}
+
+struct ProtectedRegion {
+    start: u16,
+    end: u16,                           //Inclusive
+    permissions: Permissions
+}
+
+struct DeviceRegion {
+    start: u16,
+    end: u16,                           //Inclusive
+    device: Box<dyn Device>
+}
+
+//Flat 16MB memory for the 65816, addressed by bank (top 8 bits) and offset within the bank
+pub struct ExtendedMemory {
+    mem: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     READ_OUT_OF_BOUNDS,
     WRITE_OUT_OF_BOUNDS,
     PROGRAM_SIZE_TOO_LARGE,
     FILE_NOT_FOUND,
-    PUSH_ON_STACK_OUT_OF_SPACE,
-    PUSH_ON_STACK_INPUT_EMPTY,
-    POP_OFF_STACK_EMPTY,
-    POP_OFF_STACK_REQUESTED_ZERO_BYTES,
-    POP_OFF_STACK_REQUESTED_TOO_MANY_BYTES
+    REGION_OVERLAP,
+    WRITE_TO_ROM,
+    READ_PROTECTED,
+    WRITE_PROTECTED,
+    EXECUTE_PROTECTED,
+    EXECUTE_WRITTEN(u16),               //Holds the PC of the instruction that wrote the byte
+    READ_UNINITIALISED
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
 }
 
 impl Memory {
     pub fn new() -> Memory {
         Memory {
             mem: [0; MAX_MEMORY_SIZE_BYTES],
+            devices: Vec::new(),
+            roms: Vec::new(),
+            mapper: None,
+            protected: Vec::new(),
+            on_violation: ProtectionPolicy::OFF,
+            on_execute_written: ProtectionPolicy::OFF,
+            writers: Vec::new(),
+            current_pc: 0,
+            on_uninitialised_read: ProtectionPolicy::OFF,
+            initialised: vec![false; MAX_MEMORY_SIZE_BYTES],
+            watchpoints: Vec::new(),
+            watch_hit: None,
         }
     }
 
-    pub fn read_byte(&self, addr: u16, prohibit_stack: bool) -> Result<u8, Error> {
-        let index = addr as usize;
-        if index > MAX_MEMORY_SIZE_BYTES
-            || (prohibit_stack && (index >= (STACK_START as usize) && index <= (STACK_END as usize)))
-        {
-            Err(Error::READ_OUT_OF_BOUNDS)
+    //Overwrite RAM with a power on state. ROM regions are left alone, and the filled bytes count as
+    //neither initialised nor written by the CPU.
+    pub fn fill_ram(&mut self, fill: RamFill) {
+        //xorshift64, which is plenty for junk in RAM and avoids pulling in a crate for it
+        let mut state = match fill { RamFill::RANDOM(seed) => seed.max(1), _ => 0 };
+        for addr in 0..MAX_MEMORY_SIZE_BYTES {
+            let byte = match &fill {
+                RamFill::ZERO => 0,
+                RamFill::PATTERN(pattern) => pattern.get(addr % pattern.len().max(1)).copied().unwrap_or(0),
+                RamFill::RANDOM(_) => {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                }
+            };
+            if self.rom_at(addr as u16).is_none() {
+                self.mem[addr] = byte;
+                self.initialised[addr] = false;
+                self.forget_writers(addr, 1);
+            }
+        }
+    }
+
+    //Copy the bytes in at start_addr and make them read-only. ROM regions may not overlap.
+    pub fn load_rom_bytes(&mut self, start_addr: u16, bytes: &[u8], on_write: RomWritePolicy) -> Result<(), Error> {
+        let start = start_addr as usize;
+        if bytes.is_empty() || start + bytes.len() > MAX_MEMORY_SIZE_BYTES {
+            return Err(Error::PROGRAM_SIZE_TOO_LARGE);
+        }
+        let end = (start + bytes.len() - 1) as u16;
+        if self.roms.iter().any(|region| start_addr <= region.end && region.start <= end) {
+            return Err(Error::REGION_OVERLAP);
+        }
+        self.mem[start..=(end as usize)].copy_from_slice(bytes);
+        self.forget_writers(start, bytes.len());
+        self.mark_initialised(start, bytes.len());
+        self.roms.push(RomRegion { start: start_addr, end, on_write });
+        Ok(())
+    }
+
+    //Load a firmware image (e.g. at $E000) as ROM
+    pub fn load_rom_from_file(&mut self, start_addr: u16, filename: &str, on_write: RomWritePolicy) -> Result<(), Error> {
+        if let Ok(buffer) = std::fs::read(filename) {
+            self.load_rom_bytes(start_addr, &buffer, on_write)
         } else {
-            Ok(self.mem[index])
+            Err(Error::FILE_NOT_FOUND)
         }
     }
 
-    pub fn read_n_bytes(&self, addr: u16, size: usize, prohibit_stack: bool) -> Result<Vec<u8>, Error> {
-        let mut output: Vec<u8> = Vec::new();
-        for i in 0..size {
-            if let Ok(byte) = self.read_byte(addr + (i as u16), prohibit_stack) {
-                output.push(byte);
-            } else {
-                return Err(Error::READ_OUT_OF_BOUNDS);
+    fn rom_at(&self, addr: u16) -> Option<&RomRegion> {
+        self.roms.iter().find(|region| addr >= region.start && addr <= region.end)
+    }
+
+    //Hand reads and writes of start..=end to the device instead of RAM. Regions may not overlap.
+    pub fn map_device(&mut self, start: u16, end: u16, device: Box<dyn Device>) -> Result<(), Error> {
+        if end < start || self.devices.iter().any(|region| start <= region.end && region.start <= end) {
+            Err(Error::REGION_OVERLAP)
+        } else {
+            self.devices.push(DeviceRegion { start, end, device });
+            Ok(())
+        }
+    }
+
+    //Remove the device mapped at start, returning it, so the range goes back to being RAM
+    pub fn unmap_device(&mut self, start: u16) -> Option<Box<dyn Device>> {
+        let index = self.devices.iter().position(|region| region.start == start)?;
+        Some(self.devices.remove(index).device)
+    }
+
+    //Restrict what the CPU may do in start..=end. Only checked once a policy other than OFF is set
+    //with set_protection_policy. Protected ranges may not overlap.
+    pub fn protect(&mut self, start: u16, end: u16, permissions: Permissions) -> Result<(), Error> {
+        if end < start || self.protected.iter().any(|region| start <= region.end && region.start <= end) {
+            Err(Error::REGION_OVERLAP)
+        } else {
+            self.protected.push(ProtectedRegion { start, end, permissions });
+            Ok(())
+        }
+    }
+
+    //Remove the protected range starting at start, returning whether there was one
+    pub fn unprotect(&mut self, start: u16) -> bool {
+        let count = self.protected.len();
+        self.protected.retain(|region| region.start != start);
+        self.protected.len() != count
+    }
+
+    pub fn set_protection_policy(&mut self, on_violation: ProtectionPolicy) {
+        self.on_violation = on_violation;
+    }
+
+    fn check_access(&self, addr: u16, access: Access) -> Result<(), Error> {
+        if self.on_violation == ProtectionPolicy::OFF {
+            return Ok(());
+        }
+        let allowed = match self.protected.iter().find(|region| addr >= region.start && addr <= region.end) {
+            Some(region) => match access {
+                Access::READ => region.permissions.read,
+                Access::WRITE => region.permissions.write,
+                Access::EXECUTE => region.permissions.execute
+            },
+            None => true
+        };
+        if allowed {
+            Ok(())
+        } else if self.on_violation == ProtectionPolicy::LOG {
+            log!(Subsystem::MEMORY, Level::WARN, "Protection violation: {:?} of {:#06x} by instruction at {:#06x}", access, addr, self.current_pc);
+            Ok(())
+        } else {
+            Err(match access {
+                Access::READ => Error::READ_PROTECTED,
+                Access::WRITE => Error::WRITE_PROTECTED,
+                Access::EXECUTE => Error::EXECUTE_PROTECTED
+            })
+        }
+    }
+
+    //Track which bytes the CPU writes, and check instruction fetches against them to catch self-modifying
+    //code or code overwritten by accident. Bytes count as written until a program or ROM is loaded
+    //over them. OFF stops tracking and forgets every write.
+    pub fn set_self_modifying_code_policy(&mut self, on_execute_written: ProtectionPolicy) {
+        self.on_execute_written = on_execute_written;
+        if on_execute_written == ProtectionPolicy::OFF {
+            self.writers = Vec::new();
+        } else if self.writers.is_empty() {
+            self.writers = vec![None; MAX_MEMORY_SIZE_BYTES];
+        }
+    }
+
+    //PC of the instruction that last wrote addr since it was loaded, if writes are being tracked
+    pub fn writer_of(&self, addr: u16) -> Option<u16> {
+        self.writers.get(addr as usize).copied().flatten()
+    }
+
+    fn forget_writers(&mut self, start: usize, len: usize) {
+        if let Some(writers) = self.writers.get_mut(start..(start + len).min(MAX_MEMORY_SIZE_BYTES)) {
+            writers.fill(None);
+        }
+    }
+
+    fn check_fetch_written(&self, addr: u16) -> Result<(), Error> {
+        match self.writer_of(addr) {
+            Some(writer) if self.on_execute_written == ProtectionPolicy::ERROR => Err(Error::EXECUTE_WRITTEN(writer)),
+            Some(writer) => {
+                log!(Subsystem::MEMORY, Level::WARN, "Self-modifying code: fetch of {:#06x} by instruction at {:#06x}, written by instruction at {:#06x}", addr, self.current_pc, writer);
+                Ok(())
+            },
+            None => Ok(())
+        }
+    }
+
+    //Check CPU reads of RAM against the bytes that have been written or loaded, to catch programs
+    //relying on memory they never set. With ERROR the read fails the CPU step, which hands control back
+    //to the debugger. Writes and loads are always tracked, so the policy can be changed at any point.
+    pub fn set_uninitialised_read_policy(&mut self, on_uninitialised_read: ProtectionPolicy) {
+        self.on_uninitialised_read = on_uninitialised_read;
+    }
+
+    pub fn is_initialised(&self, addr: u16) -> bool {
+        self.initialised[addr as usize]
+    }
+
+    fn mark_initialised(&mut self, start: usize, len: usize) {
+        if let Some(initialised) = self.initialised.get_mut(start..(start + len).min(MAX_MEMORY_SIZE_BYTES)) {
+            initialised.fill(true);
+        }
+    }
+
+    //Only reads that end up in RAM are checked, devices and mapper banks have their own contents
+    fn check_initialised(&self, addr: u16) -> Result<(), Error> {
+        if self.on_uninitialised_read == ProtectionPolicy::OFF || self.is_initialised(addr) {
+            return Ok(());
+        }
+        let reaches_ram = match self.mapper.as_ref().map_or(MappedRead::UNMAPPED, |mapper| mapper.peek(addr)) {
+            MappedRead::RAM => true,
+            MappedRead::VALUE(_) => false,
+            MappedRead::UNMAPPED => self.device_at(addr).is_none()
+        };
+        if !reaches_ram {
+            Ok(())
+        } else if self.on_uninitialised_read == ProtectionPolicy::ERROR {
+            Err(Error::READ_UNINITIALISED)
+        } else {
+            log!(Subsystem::MEMORY, Level::WARN, "Read of uninitialised {:#06x} by instruction at {:#06x}", addr, self.current_pc);
+            Ok(())
+        }
+    }
+
+    //Watch CPU accesses to a range. Reads and fetches count as READ, dummy reads don't count at all.
+    //The hit is picked up with Bus::take_watch_hit, which the debugger does after every step to hand
+    //control back to the user. Watchpoints may overlap.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    //Remove every watchpoint starting at start, returning whether there were any
+    pub fn remove_watchpoint(&mut self, start: u16) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.start != start);
+        self.watchpoints.len() != count
+    }
+
+    fn check_watchpoints(&mut self, addr: u16, value: u8, kind: WatchKind) {
+        if self.watch_hit.is_none() && self.watchpoints.iter().any(|watchpoint| watchpoint.matches(addr, value, kind)) {
+            self.watch_hit = Some(WatchHit { pc: self.current_pc, addr, value, kind });
+        }
+    }
+
+    //Install a mapper to bank switch parts of the address space, or remove it with None
+    pub fn set_mapper(&mut self, mapper: Option<Box<dyn Mapper>>) {
+        self.mapper = mapper;
+    }
+
+    fn device_at(&self, addr: u16) -> Option<usize> {
+        self.devices.iter().position(|region| addr >= region.start && addr <= region.end)
+    }
+
+    //CPU reads go to Mapper::read and Device::read, which may have side effects
+    fn read_device_or_byte(&mut self, addr: u16) -> Result<u8, Error> {
+        match self.mapper.as_mut().map_or(MappedRead::UNMAPPED, |mapper| mapper.read(addr)) {
+            MappedRead::VALUE(value) => Ok(value),
+            MappedRead::RAM => Ok(self.mem[addr as usize]),
+            MappedRead::UNMAPPED => {
+                if let Some(index) = self.device_at(addr) {
+                    let region = &mut self.devices[index];
+                    Ok(region.device.read(addr - region.start))
+                } else {
+                    Ok(self.read_byte(addr))
+                }
             }
         }
-        Ok(output)
     }
 
-    pub fn write_byte(&mut self, addr: u16, data: u8, prohibit_stack: bool) -> Result<u8, Error> {
+    //Every address is backed by RAM, ROM, the mapper or a device, so unlike a CPU read this can't fail
+    pub fn read_byte(&self, addr: u16) -> u8 {
         let index = addr as usize;
-        if index > MAX_MEMORY_SIZE_BYTES
-            || (prohibit_stack && (index >= (STACK_START as usize) && index <= (STACK_END as usize)))
-        {
-            Err(Error::WRITE_OUT_OF_BOUNDS)
+        if let Some(mapped) = self.mapper.as_ref().map(|mapper| mapper.peek(addr)).filter(|mapped| *mapped != MappedRead::UNMAPPED) {
+            match mapped {
+                MappedRead::VALUE(value) => value,
+                _ => self.mem[index]
+            }
+        } else if let Some(device) = self.device_at(addr) {
+            //This can't have side effects, so devices are peeked
+            let region = &self.devices[device];
+            region.device.peek(addr - region.start)
+        } else {
+            self.mem[index]
+        }
+    }
+
+    pub fn read_n_bytes(&self, addr: u16, size: usize) -> Vec<u8> {
+        (0..size).map(|i| self.read_byte(addr.wrapping_add(i as u16))).collect()
+    }
+
+    pub fn write_byte(&mut self, addr: u16, data: u8) -> Result<u8, Error> {
+        self.route_write(addr, data).map(|(value, _)| value)
+    }
+
+    //Send a write wherever addr is mapped, returning the resulting value and whether it was stored
+    //in RAM rather than taken by the mapper or a device, or dropped by a ROM
+    fn route_write(&mut self, addr: u16, data: u8) -> Result<(u8, bool), Error> {
+        let index = addr as usize;
+        if let Some(mapped) = self.mapper.as_mut().map(|mapper| mapper.write(addr, data)).filter(|mapped| *mapped != MappedWrite::UNMAPPED) {
+            if mapped == MappedWrite::RAM {
+                self.mem[index] = data;
+                self.mark_initialised(index, 1);
+            }
+            Ok((data, mapped == MappedWrite::RAM))
+        } else if let Some(device) = self.device_at(addr) {
+            let region = &mut self.devices[device];
+            region.device.write(addr - region.start, data);
+            Ok((data, false))
+        } else if let Some(rom) = self.rom_at(addr) {
+            match rom.on_write {
+                RomWritePolicy::IGNORE => Ok((self.mem[index], false)),
+                RomWritePolicy::LOG => {
+                    log!(Subsystem::MEMORY, Level::WARN, "Ignored write of {:#04x} to ROM at {:#06x}", data, addr);
+                    Ok((self.mem[index], false))
+                },
+                RomWritePolicy::ERROR => Err(Error::WRITE_TO_ROM)
+            }
         } else {
             self.mem[index] = data;
-            Ok(self.mem[index])
+            self.mark_initialised(index, 1);
+            Ok((self.mem[index], true))
         }
     }
 
-    fn load_program_bytes(&mut self, start_addr: u16, bytes: &Vec<u8>, prohibit_stack: bool) -> Result<(), Error> {
+    fn load_program_bytes(&mut self, start_addr: u16, bytes: &Vec<u8>) -> Result<(), Error> {
         let start_index: usize = start_addr as usize;
-        if start_index > MAX_MEMORY_SIZE_BYTES
-            || (prohibit_stack 
-                && ((start_index >= (STACK_START as usize) && start_index <= (STACK_END as usize))
-                || (start_index + bytes.len() + 1 >= (STACK_START as usize) && start_index + bytes.len() + 1 <= (STACK_END as usize))))
-        {
-            Err(Error::WRITE_OUT_OF_BOUNDS)
+        let end_index = start_index + bytes.len();
+        //Leave room for the BRK after the program
+        if end_index >= MAX_MEMORY_SIZE_BYTES {
+            Err(Error::PROGRAM_SIZE_TOO_LARGE)
         } else {
-            if (start_index + bytes.len() + 1 > (MAX_MEMORY_SIZE_BYTES as usize)) {
-                Err(Error::PROGRAM_SIZE_TOO_LARGE)
-            } else {
-                //Put program into memory
-                self.mem[start_index..(start_index + bytes.len())]
-                    .copy_from_slice(bytes.as_slice());
-                //Put a BRK instruction at the end of the program
-                self.mem[(start_addr as usize) + bytes.len() + 1] = 0x0;
-                Ok(())
-            }
+            //Put program into memory
+            self.mem[start_index..end_index]
+                .copy_from_slice(bytes.as_slice());
+            //Put a BRK instruction straight after the end of the program
+            self.mem[end_index] = 0x0;
+            self.forget_writers(start_index, bytes.len() + 1);
+            self.mark_initialised(start_index, bytes.len() + 1);
+            Ok(())
         }
     }
 
-    pub fn load_program_from_file(&mut self, start_addr: u16, filename: &str, prohibit_stack: bool) -> Result<(), Error> {
-        if let Ok(mut f) = File::open(&filename) {
-            if let Ok(metadata) = std::fs::metadata(&filename) {
+    pub fn load_program_from_file(&mut self, start_addr: u16, filename: &str) -> Result<(), Error> {
+        if let Ok(mut f) = File::open(filename) {
+            if let Ok(metadata) = std::fs::metadata(filename) {
                 let mut buffer: Vec<u8> = vec![0; metadata.len() as usize];
-                if let Ok(_) = f.read(&mut buffer) {
+                if f.read_exact(&mut buffer).is_ok() {
                     //Load the main program
-                    self.load_program_bytes(start_addr, &buffer, prohibit_stack)
+                    self.load_program_bytes(start_addr, &buffer)
                 } else {
                     Err(Error::PROGRAM_SIZE_TOO_LARGE)
                 }
@@ -104,37 +490,338 @@ impl Memory {
             Err(Error::FILE_NOT_FOUND)
         }
     }
+}
+
+//RAM reads and peeks are the same, while mapped devices see their side-effecting reads only from
+//the CPU. CPU accesses are checked against the protected ranges, peeks are not.
+impl Bus for Memory {
+    fn read(&mut self, addr: u16) -> Result<u8, Error> {
+        self.check_access(addr, Access::READ)?;
+        self.check_initialised(addr)?;
+        let value = self.read_device_or_byte(addr)?;
+        self.check_watchpoints(addr, value, WatchKind::READ);
+        Ok(value)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) -> Result<u8, Error> {
+        self.check_access(addr, Access::WRITE)?;
+        let (value, stored) = self.route_write(addr, data)?;
+        //Only bytes that really changed in RAM can be executed as self-modified code
+        if stored {
+            if let Some(writer) = self.writers.get_mut(addr as usize) {
+                *writer = Some(self.current_pc);
+            }
+        }
+        self.check_watchpoints(addr, data, WatchKind::WRITE);
+        Ok(value)
+    }
+
+    fn peek(&self, addr: u16) -> Result<u8, Error> {
+        Ok(self.read_byte(addr))
+    }
+
+    fn fetch(&mut self, addr: u16) -> Result<u8, Error> {
+        self.check_access(addr, Access::EXECUTE)?;
+        self.check_fetch_written(addr)?;
+        self.check_initialised(addr)?;
+        let value = self.read_device_or_byte(addr)?;
+        self.check_watchpoints(addr, value, WatchKind::READ);
+        Ok(value)
+    }
+
+    //Thrown away reads still go to devices, but aren't reported as reading uninitialised RAM or as
+    //hitting a read watchpoint, since the program never sees the value
+    fn dummy_read(&mut self, addr: u16) -> Result<u8, Error> {
+        self.check_access(addr, Access::READ)?;
+        self.read_device_or_byte(addr)
+    }
+
+    fn begin_instruction(&mut self, pc: u16) {
+        self.current_pc = pc;
+    }
+
+    fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn tick(&mut self) {
+        if let Some(mapper) = self.mapper.as_mut() {
+            mapper.tick();
+        }
+        for region in self.devices.iter_mut() {
+            region.device.tick();
+        }
+    }
+
+    //IRQ is wired-OR, so any mapped device can hold it low
+    fn irq(&self) -> bool {
+        self.devices.iter().any(|region| region.device.irq())
+    }
+}
+
+impl Bus24 for ExtendedMemory {
+    fn read(&mut self, addr: u32) -> Result<u8, Error> {
+        self.read_byte(addr)
+    }
+
+    fn write(&mut self, addr: u32, data: u8) -> Result<u8, Error> {
+        self.write_byte(addr, data)
+    }
+
+    fn peek(&self, addr: u32) -> Result<u8, Error> {
+        self.read_byte(addr)
+    }
+}
+
+impl Default for ExtendedMemory {
+    fn default() -> Self {
+        ExtendedMemory::new()
+    }
+}
+
+impl ExtendedMemory {
+    pub fn new() -> ExtendedMemory {
+        ExtendedMemory {
+            mem: vec![0; MAX_EXTENDED_MEMORY_SIZE_BYTES],
+        }
+    }
 
-    pub fn push_onto_stack(&mut self, stack_pointer: u8, bytes: &Vec<u8>) -> Result<(), Error> {
-        let start_index = (STACK_END as usize) - ((STACK_END as usize) - (STACK_START as usize + stack_pointer as usize));
-        if bytes.is_empty() {
-            Err(Error::PUSH_ON_STACK_INPUT_EMPTY)
-        }else if start_index - bytes.len() < STACK_START as usize {
-            Err(Error::PUSH_ON_STACK_OUT_OF_SPACE)
+    pub fn read_byte(&self, addr: u32) -> Result<u8, Error> {
+        if let Some(byte) = self.mem.get(addr as usize) {
+            Ok(*byte)
         } else {
-            let mut cur_index = start_index;
-            for b in bytes {
-                self.mem[cur_index] = b.clone();
-                cur_index -= 1;
-            }   
-            Ok(())
+            Err(Error::READ_OUT_OF_BOUNDS)
         }
     }
 
-    pub fn pop_off_stack(&mut self, stack_pointer: u8, num_bytes: u8) -> Result<Vec<u8>, Error> {
-        let start_index = (STACK_END as usize) - ((STACK_END as usize) - (STACK_START as usize + stack_pointer as usize));
-        if num_bytes == 0 {
-            Err(Error::POP_OFF_STACK_REQUESTED_ZERO_BYTES)
-        } else if start_index + (num_bytes as usize) > STACK_END as usize {
-            Err(Error::POP_OFF_STACK_REQUESTED_TOO_MANY_BYTES)
-        } else if start_index == (STACK_END as usize) {
-            Err(Error::POP_OFF_STACK_EMPTY)
+    pub fn write_byte(&mut self, addr: u32, data: u8) -> Result<u8, Error> {
+        if let Some(byte) = self.mem.get_mut(addr as usize) {
+            *byte = data;
+            Ok(data)
         } else {
-            let mut return_vec: Vec<u8> = Vec::new();
-            for cur_index in start_index..(start_index + (num_bytes as usize)) {
-                return_vec.push(self.mem[cur_index].clone());
+            Err(Error::WRITE_OUT_OF_BOUNDS)
+        }
+    }
+
+    pub fn load_program_from_file(&mut self, start_addr: u32, filename: &str) -> Result<(), Error> {
+        if let Ok(buffer) = std::fs::read(filename) {
+            let start_index = start_addr as usize;
+            if start_index + buffer.len() > MAX_EXTENDED_MEMORY_SIZE_BYTES {
+                Err(Error::PROGRAM_SIZE_TOO_LARGE)
+            } else {
+                self.mem[start_index..(start_index + buffer.len())].copy_from_slice(buffer.as_slice());
+                Ok(())
             }
-            Ok(return_vec)
+        } else {
+            Err(Error::FILE_NOT_FOUND)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{self, LogSink};
+    use crate::mapper::BankSwitch16K;
+    use std::fmt::Arguments;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn program_is_followed_by_brk() {
+        let mut mem = Memory::new();
+        mem.set_uninitialised_read_policy(ProtectionPolicy::ERROR);
+        mem.write_byte(0x0203, 0x55).unwrap();
+        mem.load_program_bytes(0x0200, &vec![0xEA, 0xEA]).unwrap();
+        assert_eq!(mem.read_n_bytes(0x0200, 4), vec![0xEA, 0xEA, 0x00, 0x55]);
+        assert!(mem.is_initialised(0x0202));
+        assert!(!mem.is_initialised(0x0204));
+    }
+
+    #[test]
+    fn program_at_top_of_memory() {
+        let mut mem = Memory::new();
+        mem.set_uninitialised_read_policy(ProtectionPolicy::ERROR);
+        //Just room for the BRK in the last byte
+        mem.load_program_bytes(0xFFFC, &vec![0xEA, 0xEA, 0xEA]).unwrap();
+        assert_eq!(mem.read_byte(0xFFFF), 0x00);
+        assert!(mem.is_initialised(0xFFFF));
+        assert_eq!(mem.load_program_bytes(0xFFFD, &vec![0xEA, 0xEA, 0xEA]), Err(Error::PROGRAM_SIZE_TOO_LARGE));
+    }
+
+    //Swallows writes, like a register with nothing behind it
+    struct Sink;
+
+    impl Device for Sink {
+        fn read(&mut self, _offset: u16) -> u8 {
+            0
+        }
+
+        fn write(&mut self, _offset: u16, _data: u8) {}
+
+        fn peek(&self, _offset: u16) -> u8 {
+            0
+        }
+    }
+
+    #[test]
+    fn writers_only_recorded_for_ram() {
+        let mut mem = Memory::new();
+        mem.set_self_modifying_code_policy(ProtectionPolicy::ERROR);
+        mem.load_rom_bytes(0xF000, &[0xEA; 0x10], RomWritePolicy::IGNORE).unwrap();
+        mem.map_device(0xD000, 0xD00F, Box::new(Sink)).unwrap();
+        mem.set_mapper(Some(Box::new(BankSwitch16K::new(0x8000, 0x0300, vec![0xEA; 0x4000], false))));
+        mem.begin_instruction(0x0400);
+        for addr in [0x0200, 0xF000, 0xD000, 0x8000, 0x0300] {
+            mem.write(addr, 0x60).unwrap();
+        }
+        assert_eq!(mem.writer_of(0x0200), Some(0x0400));
+        for addr in [0xF000, 0xD000, 0x8000, 0x0300] {
+            assert_eq!(mem.writer_of(addr), None);
+        }
+        //The ROM still holds what it was loaded with, so running it is fine
+        assert_eq!(mem.fetch(0xF000), Ok(0xEA));
+        assert_eq!(mem.fetch(0x0200), Err(Error::EXECUTE_WRITTEN(0x0400)));
+    }
+
+    #[test]
+    fn policy_keeps_initialised_state() {
+        let mut mem = Memory::new();
+        mem.load_program_bytes(0x0200, &vec![0xEA]).unwrap();
+        mem.write_byte(0x0300, 0x55).unwrap();
+        //Turned on after loading, then switched around, without losing track
+        mem.set_uninitialised_read_policy(ProtectionPolicy::LOG);
+        mem.set_uninitialised_read_policy(ProtectionPolicy::OFF);
+        mem.set_uninitialised_read_policy(ProtectionPolicy::ERROR);
+        assert_eq!(mem.read(0x0200), Ok(0xEA));
+        assert_eq!(mem.read(0x0300), Ok(0x55));
+        assert_eq!(mem.read(0x0400), Err(Error::READ_UNINITIALISED));
+    }
+
+    #[test]
+    fn fill_ram_resets_tracking() {
+        let mut mem = Memory::new();
+        mem.set_uninitialised_read_policy(ProtectionPolicy::ERROR);
+        mem.set_self_modifying_code_policy(ProtectionPolicy::ERROR);
+        mem.load_rom_bytes(0xF000, &[0xEA; 0x10], RomWritePolicy::IGNORE).unwrap();
+        mem.begin_instruction(0x0400);
+        mem.write(0x0200, 0x60).unwrap();
+        mem.fill_ram(RamFill::ZERO);
+        assert!(!mem.is_initialised(0x0200));
+        assert_eq!(mem.writer_of(0x0200), None);
+        assert_eq!(mem.read(0x0200), Err(Error::READ_UNINITIALISED));
+        //ROM keeps its contents, so it still counts as initialised
+        assert!(mem.is_initialised(0xF000));
+        assert_eq!(mem.read(0xF000), Ok(0xEA));
+    }
+
+    //Keeps what was logged while it is the sink
+    struct RecordingSink(Arc<Mutex<Vec<String>>>);
+
+    impl LogSink for RecordingSink {
+        fn write(&mut self, _subsystem: Subsystem, _level: Level, message: &Arguments) {
+            self.0.lock().unwrap().push(message.to_string());
+        }
+    }
+
+    #[test]
+    fn rom_write_policies() {
+        let _guard = logging::TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        logging::set_sink(Box::new(RecordingSink(messages.clone())));
+        logging::set_level(Subsystem::MEMORY, Some(Level::WARN));
+
+        let mut mem = Memory::new();
+        mem.load_rom_bytes(0xE000, &[0xEA; 0x10], RomWritePolicy::IGNORE).unwrap();
+        mem.load_rom_bytes(0xF000, &[0xEA; 0x10], RomWritePolicy::LOG).unwrap();
+        mem.load_rom_bytes(0xF800, &[0xEA; 0x10], RomWritePolicy::ERROR).unwrap();
+        //Dropped writes return what the ROM still holds
+        assert_eq!(mem.write(0xE000, 0x60), Ok(0xEA));
+        assert_eq!(mem.write(0xF000, 0x60), Ok(0xEA));
+        assert_eq!(mem.write(0xF800, 0x60), Err(Error::WRITE_TO_ROM));
+        assert_eq!(mem.write_byte(0xF801, 0x60), Err(Error::WRITE_TO_ROM));
+        for addr in [0xE000, 0xF000, 0xF800, 0xF801] {
+            assert_eq!(mem.read_byte(addr), 0xEA);
         }
+
+        logging::set_sink(Box::new(logging::StdoutSink));
+        logging::set_level(Subsystem::MEMORY, Some(Level::INFO));
+        //Other tests may log to MEMORY at the same time, so only look at the ROM messages
+        let rom_messages: Vec<String> = messages.lock().unwrap().iter().filter(|message| message.contains("ROM")).cloned().collect();
+        assert_eq!(rom_messages, vec![String::from("Ignored write of 0x60 to ROM at 0xf000")]);
+    }
+
+    fn watch(start: u16, end: u16, kind: WatchKind, condition: WatchCondition) -> Watchpoint {
+        Watchpoint { start, end, kind, condition }
+    }
+
+    #[test]
+    fn watchpoint_kinds_and_ranges() {
+        let mut mem = Memory::new();
+        mem.add_watchpoint(watch(0x0200, 0x020F, WatchKind::READ, WatchCondition::ANY));
+        mem.add_watchpoint(watch(0x0300, 0x0300, WatchKind::WRITE, WatchCondition::EQUAL(0x42)));
+        mem.add_watchpoint(watch(0x0400, 0x0401, WatchKind::ACCESS, WatchCondition::MASKED(0x80, 0x80)));
+        mem.begin_instruction(0x8000);
+
+        //Writes don't fire read watchpoints, nor reads write ones
+        mem.write(0x0200, 0x01).unwrap();
+        mem.read(0x0300).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+        //Either end of the range, but not past it
+        mem.read(0x01FF).unwrap();
+        mem.read(0x0210).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+        mem.read(0x020F).unwrap();
+        assert_eq!(mem.take_watch_hit(), Some(WatchHit { pc: 0x8000, addr: 0x020F, value: 0x00, kind: WatchKind::READ }));
+        mem.fetch(0x0200).unwrap();
+        assert_eq!(mem.take_watch_hit(), Some(WatchHit { pc: 0x8000, addr: 0x0200, value: 0x01, kind: WatchKind::READ }));
+        //Taking the hit clears it
+        assert_eq!(mem.take_watch_hit(), None);
+
+        mem.write(0x0300, 0x41).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+        mem.write(0x0300, 0x42).unwrap();
+        assert_eq!(mem.take_watch_hit(), Some(WatchHit { pc: 0x8000, addr: 0x0300, value: 0x42, kind: WatchKind::WRITE }));
+
+        //ACCESS fires on both, reporting which it was
+        mem.write(0x0401, 0x80).unwrap();
+        assert_eq!(mem.take_watch_hit(), Some(WatchHit { pc: 0x8000, addr: 0x0401, value: 0x80, kind: WatchKind::WRITE }));
+        mem.read(0x0401).unwrap();
+        assert_eq!(mem.take_watch_hit(), Some(WatchHit { pc: 0x8000, addr: 0x0401, value: 0x80, kind: WatchKind::READ }));
+        mem.write(0x0400, 0x7F).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+
+        //Only the first hit is kept until it is taken
+        mem.begin_instruction(0x8003);
+        mem.read(0x0201).unwrap();
+        mem.read(0x0202).unwrap();
+        assert_eq!(mem.take_watch_hit().map(|hit| (hit.pc, hit.addr)), Some((0x8003, 0x0201)));
+    }
+
+    #[test]
+    fn dummy_reads_and_peeks_skip_watchpoints() {
+        let mut mem = Memory::new();
+        mem.add_watchpoint(watch(0x0200, 0x0200, WatchKind::ACCESS, WatchCondition::ANY));
+        mem.dummy_read(0x0200).unwrap();
+        mem.peek(0x0200).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+        mem.read(0x0200).unwrap();
+        assert!(mem.take_watch_hit().is_some());
+    }
+
+    #[test]
+    fn remove_watchpoint_by_start() {
+        let mut mem = Memory::new();
+        mem.add_watchpoint(watch(0x0200, 0x0200, WatchKind::READ, WatchCondition::ANY));
+        mem.add_watchpoint(watch(0x0200, 0x02FF, WatchKind::WRITE, WatchCondition::ANY));
+        mem.add_watchpoint(watch(0x0300, 0x0300, WatchKind::READ, WatchCondition::ANY));
+        //Removes both that start at $0200
+        assert!(mem.remove_watchpoint(0x0200));
+        assert!(!mem.remove_watchpoint(0x0200));
+        assert!(!mem.remove_watchpoint(0x0250));
+        mem.read(0x0200).unwrap();
+        mem.write(0x0210, 0x01).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+        mem.read(0x0300).unwrap();
+        assert_eq!(mem.take_watch_hit().map(|hit| hit.addr), Some(0x0300));
     }
 }
@@ -0,0 +1,1004 @@
+use crate::addressing::AddressingMode;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstructionTypes {
+    ADC,
+    AND,
+    ASL,
+    BCC,
+    BCS,
+    BEQ,
+    BIT,
+    BMI,
+    BNE,
+    BPL,
+    BRK,
+    BVC,
+    BVS,
+    CLC,
+    CLD,
+    CLI,
+    CLV,
+    CMP,
+    CPX,
+    CPY,
+    DEC,
+    DEX,
+    DEY,
+    EOR,
+    INC,
+    INX,
+    INY,
+    JMP,
+    JSR,
+    LDA,
+    LDX,
+    LDY,
+    LSR,
+    NOP,
+    ORA,
+    PHA,
+    PHP,
+    PLA,
+    PLP,
+    ROL,
+    ROR,
+    RTI,
+    RTS,
+    SBC,
+    SEC,
+    SED,
+    SEI,
+    STA,
+    STX,
+    STY,
+    TAX,
+    TAY,
+    TSX,
+    TXA,
+    TXS,
+    TYA,
+    //Undocumented NMOS instructions, only decoded when the CPU is configured to allow them
+    ALR,
+    ANC,
+    ARR,
+    DCP,
+    ISC,
+    JAM,
+    LAX,
+    RLA,
+    RRA,
+    SAX,
+    SBX,
+    SLO,
+    SRE,
+    //65C02 additions. The bit instructions carry the bit number they test or change.
+    BBR(u8),
+    BBS(u8),
+    BRA,
+    PHX,
+    PHY,
+    PLX,
+    PLY,
+    RMB(u8),
+    SMB(u8),
+    STP,
+    STZ,
+    TRB,
+    TSB,
+    WAI
+}
+
+impl InstructionTypes {
+    //Stores and read-modify-write instructions, which always spend the indexed addressing fix-up cycle
+    pub fn writes_operand(&self) -> bool {
+        matches!(self, InstructionTypes::STA | InstructionTypes::STX | InstructionTypes::STY
+            | InstructionTypes::ASL | InstructionTypes::LSR | InstructionTypes::ROL | InstructionTypes::ROR
+            | InstructionTypes::INC | InstructionTypes::DEC
+            | InstructionTypes::SAX | InstructionTypes::SLO | InstructionTypes::RLA | InstructionTypes::SRE
+            | InstructionTypes::RRA | InstructionTypes::DCP | InstructionTypes::ISC
+            | InstructionTypes::STZ | InstructionTypes::TRB | InstructionTypes::TSB
+            | InstructionTypes::RMB(_) | InstructionTypes::SMB(_))
+    }
+
+    //Assembler mnemonic, with the bit number appended for the 65C02 bit instructions (e.g. RMB3)
+    pub fn mnemonic(&self) -> String {
+        match self {
+            InstructionTypes::BBR(bit) => format!("BBR{}", bit),
+            InstructionTypes::BBS(bit) => format!("BBS{}", bit),
+            InstructionTypes::RMB(bit) => format!("RMB{}", bit),
+            InstructionTypes::SMB(bit) => format!("SMB{}", bit),
+            _ => format!("{:?}", self)
+        }
+    }
+}
+
+//Everything the emulator knows about an opcode, shared by the CPU, debugger and any other tooling
+#[derive(Debug, Clone, Copy)]
+pub struct OpcodeInfo {
+    pub inst: InstructionTypes,
+    pub mode: AddressingMode,
+    pub num_bytes: u16,
+    pub base_cycles: u16,
+    pub page_cross_penalty: bool   //Takes an extra cycle when indexing or branching crosses a page
+}
+
+const fn op(inst: InstructionTypes, mode: AddressingMode, base_cycles: u16, page_cross_penalty: bool) -> Option<OpcodeInfo> {
+    Some(OpcodeInfo { inst, mode, num_bytes: mode.num_operand_bytes() + 1, base_cycles, page_cross_penalty })
+}
+
+//Indexed by opcode, None for opcodes that are not part of the documented instruction set
+pub static OPCODE_TABLE: [Option<OpcodeInfo>; 256] = [
+    /* 0x00 */ op(InstructionTypes::BRK, AddressingMode::IMPLIED, 7, false),
+    /* 0x01 */ op(InstructionTypes::ORA, AddressingMode::INDIRECT_X, 6, false),
+    /* 0x02 */ None,
+    /* 0x03 */ None,
+    /* 0x04 */ None,
+    /* 0x05 */ op(InstructionTypes::ORA, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x06 */ op(InstructionTypes::ASL, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x07 */ None,
+    /* 0x08 */ op(InstructionTypes::PHP, AddressingMode::IMPLIED, 3, false),
+    /* 0x09 */ op(InstructionTypes::ORA, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x0A */ op(InstructionTypes::ASL, AddressingMode::ACCUMULATOR, 2, false),
+    /* 0x0B */ None,
+    /* 0x0C */ None,
+    /* 0x0D */ op(InstructionTypes::ORA, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x0E */ op(InstructionTypes::ASL, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x0F */ None,
+    /* 0x10 */ op(InstructionTypes::BPL, AddressingMode::RELATIVE, 2, true),
+    /* 0x11 */ op(InstructionTypes::ORA, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0x12 */ None,
+    /* 0x13 */ None,
+    /* 0x14 */ None,
+    /* 0x15 */ op(InstructionTypes::ORA, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x16 */ op(InstructionTypes::ASL, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x17 */ None,
+    /* 0x18 */ op(InstructionTypes::CLC, AddressingMode::IMPLIED, 2, false),
+    /* 0x19 */ op(InstructionTypes::ORA, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0x1A */ None,
+    /* 0x1B */ None,
+    /* 0x1C */ None,
+    /* 0x1D */ op(InstructionTypes::ORA, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x1E */ op(InstructionTypes::ASL, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x1F */ None,
+    /* 0x20 */ op(InstructionTypes::JSR, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x21 */ op(InstructionTypes::AND, AddressingMode::INDIRECT_X, 6, false),
+    /* 0x22 */ None,
+    /* 0x23 */ None,
+    /* 0x24 */ op(InstructionTypes::BIT, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x25 */ op(InstructionTypes::AND, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x26 */ op(InstructionTypes::ROL, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x27 */ None,
+    /* 0x28 */ op(InstructionTypes::PLP, AddressingMode::IMPLIED, 4, false),
+    /* 0x29 */ op(InstructionTypes::AND, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x2A */ op(InstructionTypes::ROL, AddressingMode::ACCUMULATOR, 2, false),
+    /* 0x2B */ None,
+    /* 0x2C */ op(InstructionTypes::BIT, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x2D */ op(InstructionTypes::AND, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x2E */ op(InstructionTypes::ROL, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x2F */ None,
+    /* 0x30 */ op(InstructionTypes::BMI, AddressingMode::RELATIVE, 2, true),
+    /* 0x31 */ op(InstructionTypes::AND, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0x32 */ None,
+    /* 0x33 */ None,
+    /* 0x34 */ None,
+    /* 0x35 */ op(InstructionTypes::AND, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x36 */ op(InstructionTypes::ROL, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x37 */ None,
+    /* 0x38 */ op(InstructionTypes::SEC, AddressingMode::IMPLIED, 2, false),
+    /* 0x39 */ op(InstructionTypes::AND, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0x3A */ None,
+    /* 0x3B */ None,
+    /* 0x3C */ None,
+    /* 0x3D */ op(InstructionTypes::AND, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x3E */ op(InstructionTypes::ROL, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x3F */ None,
+    /* 0x40 */ op(InstructionTypes::RTI, AddressingMode::IMPLIED, 6, false),
+    /* 0x41 */ op(InstructionTypes::EOR, AddressingMode::INDIRECT_X, 6, false),
+    /* 0x42 */ None,
+    /* 0x43 */ None,
+    /* 0x44 */ None,
+    /* 0x45 */ op(InstructionTypes::EOR, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x46 */ op(InstructionTypes::LSR, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x47 */ None,
+    /* 0x48 */ op(InstructionTypes::PHA, AddressingMode::IMPLIED, 3, false),
+    /* 0x49 */ op(InstructionTypes::EOR, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x4A */ op(InstructionTypes::LSR, AddressingMode::ACCUMULATOR, 2, false),
+    /* 0x4B */ None,
+    /* 0x4C */ op(InstructionTypes::JMP, AddressingMode::ABSOLUTE, 3, false),
+    /* 0x4D */ op(InstructionTypes::EOR, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x4E */ op(InstructionTypes::LSR, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x4F */ None,
+    /* 0x50 */ op(InstructionTypes::BVC, AddressingMode::RELATIVE, 2, true),
+    /* 0x51 */ op(InstructionTypes::EOR, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0x52 */ None,
+    /* 0x53 */ None,
+    /* 0x54 */ None,
+    /* 0x55 */ op(InstructionTypes::EOR, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x56 */ op(InstructionTypes::LSR, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x57 */ None,
+    /* 0x58 */ op(InstructionTypes::CLI, AddressingMode::IMPLIED, 2, false),
+    /* 0x59 */ op(InstructionTypes::EOR, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0x5A */ None,
+    /* 0x5B */ None,
+    /* 0x5C */ None,
+    /* 0x5D */ op(InstructionTypes::EOR, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x5E */ op(InstructionTypes::LSR, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x5F */ None,
+    /* 0x60 */ op(InstructionTypes::RTS, AddressingMode::IMPLIED, 6, false),
+    /* 0x61 */ op(InstructionTypes::ADC, AddressingMode::INDIRECT_X, 6, false),
+    /* 0x62 */ None,
+    /* 0x63 */ None,
+    /* 0x64 */ None,
+    /* 0x65 */ op(InstructionTypes::ADC, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x66 */ op(InstructionTypes::ROR, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x67 */ None,
+    /* 0x68 */ op(InstructionTypes::PLA, AddressingMode::IMPLIED, 4, false),
+    /* 0x69 */ op(InstructionTypes::ADC, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x6A */ op(InstructionTypes::ROR, AddressingMode::ACCUMULATOR, 2, false),
+    /* 0x6B */ None,
+    /* 0x6C */ op(InstructionTypes::JMP, AddressingMode::INDIRECT, 5, false),
+    /* 0x6D */ op(InstructionTypes::ADC, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x6E */ op(InstructionTypes::ROR, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x6F */ None,
+    /* 0x70 */ op(InstructionTypes::BVS, AddressingMode::RELATIVE, 2, true),
+    /* 0x71 */ op(InstructionTypes::ADC, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0x72 */ None,
+    /* 0x73 */ None,
+    /* 0x74 */ None,
+    /* 0x75 */ op(InstructionTypes::ADC, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x76 */ op(InstructionTypes::ROR, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x77 */ None,
+    /* 0x78 */ op(InstructionTypes::SEI, AddressingMode::IMPLIED, 2, false),
+    /* 0x79 */ op(InstructionTypes::ADC, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0x7A */ None,
+    /* 0x7B */ None,
+    /* 0x7C */ None,
+    /* 0x7D */ op(InstructionTypes::ADC, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x7E */ op(InstructionTypes::ROR, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x7F */ None,
+    /* 0x80 */ None,
+    /* 0x81 */ op(InstructionTypes::STA, AddressingMode::INDIRECT_X, 6, false),
+    /* 0x82 */ None,
+    /* 0x83 */ None,
+    /* 0x84 */ op(InstructionTypes::STY, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x85 */ op(InstructionTypes::STA, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x86 */ op(InstructionTypes::STX, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x87 */ None,
+    /* 0x88 */ op(InstructionTypes::DEY, AddressingMode::IMPLIED, 2, false),
+    /* 0x89 */ None,
+    /* 0x8A */ op(InstructionTypes::TXA, AddressingMode::IMPLIED, 2, false),
+    /* 0x8B */ None,
+    /* 0x8C */ op(InstructionTypes::STY, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x8D */ op(InstructionTypes::STA, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x8E */ op(InstructionTypes::STX, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x8F */ None,
+    /* 0x90 */ op(InstructionTypes::BCC, AddressingMode::RELATIVE, 2, true),
+    /* 0x91 */ op(InstructionTypes::STA, AddressingMode::INDIRECT_Y, 6, false),
+    /* 0x92 */ None,
+    /* 0x93 */ None,
+    /* 0x94 */ op(InstructionTypes::STY, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x95 */ op(InstructionTypes::STA, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x96 */ op(InstructionTypes::STX, AddressingMode::ZERO_PAGE_Y, 4, false),
+    /* 0x97 */ None,
+    /* 0x98 */ op(InstructionTypes::TYA, AddressingMode::IMPLIED, 2, false),
+    /* 0x99 */ op(InstructionTypes::STA, AddressingMode::ABSOLUTE_Y, 5, false),
+    /* 0x9A */ op(InstructionTypes::TXS, AddressingMode::IMPLIED, 2, false),
+    /* 0x9B */ None,
+    /* 0x9C */ None,
+    /* 0x9D */ op(InstructionTypes::STA, AddressingMode::ABSOLUTE_X, 5, false),
+    /* 0x9E */ None,
+    /* 0x9F */ None,
+    /* 0xA0 */ op(InstructionTypes::LDY, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xA1 */ op(InstructionTypes::LDA, AddressingMode::INDIRECT_X, 6, false),
+    /* 0xA2 */ op(InstructionTypes::LDX, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xA3 */ None,
+    /* 0xA4 */ op(InstructionTypes::LDY, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xA5 */ op(InstructionTypes::LDA, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xA6 */ op(InstructionTypes::LDX, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xA7 */ None,
+    /* 0xA8 */ op(InstructionTypes::TAY, AddressingMode::IMPLIED, 2, false),
+    /* 0xA9 */ op(InstructionTypes::LDA, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xAA */ op(InstructionTypes::TAX, AddressingMode::IMPLIED, 2, false),
+    /* 0xAB */ None,
+    /* 0xAC */ op(InstructionTypes::LDY, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xAD */ op(InstructionTypes::LDA, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xAE */ op(InstructionTypes::LDX, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xAF */ None,
+    /* 0xB0 */ op(InstructionTypes::BCS, AddressingMode::RELATIVE, 2, true),
+    /* 0xB1 */ op(InstructionTypes::LDA, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0xB2 */ None,
+    /* 0xB3 */ None,
+    /* 0xB4 */ op(InstructionTypes::LDY, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xB5 */ op(InstructionTypes::LDA, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xB6 */ op(InstructionTypes::LDX, AddressingMode::ZERO_PAGE_Y, 4, false),
+    /* 0xB7 */ None,
+    /* 0xB8 */ op(InstructionTypes::CLV, AddressingMode::IMPLIED, 2, false),
+    /* 0xB9 */ op(InstructionTypes::LDA, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0xBA */ op(InstructionTypes::TSX, AddressingMode::IMPLIED, 2, false),
+    /* 0xBB */ None,
+    /* 0xBC */ op(InstructionTypes::LDY, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0xBD */ op(InstructionTypes::LDA, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0xBE */ op(InstructionTypes::LDX, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0xBF */ None,
+    /* 0xC0 */ op(InstructionTypes::CPY, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xC1 */ op(InstructionTypes::CMP, AddressingMode::INDIRECT_X, 6, false),
+    /* 0xC2 */ None,
+    /* 0xC3 */ None,
+    /* 0xC4 */ op(InstructionTypes::CPY, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xC5 */ op(InstructionTypes::CMP, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xC6 */ op(InstructionTypes::DEC, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xC7 */ None,
+    /* 0xC8 */ op(InstructionTypes::INY, AddressingMode::IMPLIED, 2, false),
+    /* 0xC9 */ op(InstructionTypes::CMP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xCA */ op(InstructionTypes::DEX, AddressingMode::IMPLIED, 2, false),
+    /* 0xCB */ None,
+    /* 0xCC */ op(InstructionTypes::CPY, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xCD */ op(InstructionTypes::CMP, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xCE */ op(InstructionTypes::DEC, AddressingMode::ABSOLUTE, 6, false),
+    /* 0xCF */ None,
+    /* 0xD0 */ op(InstructionTypes::BNE, AddressingMode::RELATIVE, 2, true),
+    /* 0xD1 */ op(InstructionTypes::CMP, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0xD2 */ None,
+    /* 0xD3 */ None,
+    /* 0xD4 */ None,
+    /* 0xD5 */ op(InstructionTypes::CMP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xD6 */ op(InstructionTypes::DEC, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0xD7 */ None,
+    /* 0xD8 */ op(InstructionTypes::CLD, AddressingMode::IMPLIED, 2, false),
+    /* 0xD9 */ op(InstructionTypes::CMP, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0xDA */ None,
+    /* 0xDB */ None,
+    /* 0xDC */ None,
+    /* 0xDD */ op(InstructionTypes::CMP, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0xDE */ op(InstructionTypes::DEC, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0xDF */ None,
+    /* 0xE0 */ op(InstructionTypes::CPX, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xE1 */ op(InstructionTypes::SBC, AddressingMode::INDIRECT_X, 6, false),
+    /* 0xE2 */ None,
+    /* 0xE3 */ None,
+    /* 0xE4 */ op(InstructionTypes::CPX, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xE5 */ op(InstructionTypes::SBC, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xE6 */ op(InstructionTypes::INC, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xE7 */ None,
+    /* 0xE8 */ op(InstructionTypes::INX, AddressingMode::IMPLIED, 2, false),
+    /* 0xE9 */ op(InstructionTypes::SBC, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xEA */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 2, false),
+    /* 0xEB */ None,
+    /* 0xEC */ op(InstructionTypes::CPX, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xED */ op(InstructionTypes::SBC, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xEE */ op(InstructionTypes::INC, AddressingMode::ABSOLUTE, 6, false),
+    /* 0xEF */ None,
+    /* 0xF0 */ op(InstructionTypes::BEQ, AddressingMode::RELATIVE, 2, true),
+    /* 0xF1 */ op(InstructionTypes::SBC, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0xF2 */ None,
+    /* 0xF3 */ None,
+    /* 0xF4 */ None,
+    /* 0xF5 */ op(InstructionTypes::SBC, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xF6 */ op(InstructionTypes::INC, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0xF7 */ None,
+    /* 0xF8 */ op(InstructionTypes::SED, AddressingMode::IMPLIED, 2, false),
+    /* 0xF9 */ op(InstructionTypes::SBC, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0xFA */ None,
+    /* 0xFB */ None,
+    /* 0xFC */ None,
+    /* 0xFD */ op(InstructionTypes::SBC, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0xFE */ op(InstructionTypes::INC, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0xFF */ None,
+];
+
+//Indexed by opcode, the undocumented NMOS opcodes that behave consistently across chips. The
+//unstable ones (ANE, LXA, SHA, SHX, SHY, TAS and LAS) depend on analogue effects and are left out.
+pub static UNDOCUMENTED_OPCODE_TABLE: [Option<OpcodeInfo>; 256] = [
+    /* 0x00 */ None,
+    /* 0x01 */ None,
+    /* 0x02 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x03 */ op(InstructionTypes::SLO, AddressingMode::INDIRECT_X, 8, false),
+    /* 0x04 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x05 */ None,
+    /* 0x06 */ None,
+    /* 0x07 */ op(InstructionTypes::SLO, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x08 */ None,
+    /* 0x09 */ None,
+    /* 0x0A */ None,
+    /* 0x0B */ op(InstructionTypes::ANC, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x0C */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x0D */ None,
+    /* 0x0E */ None,
+    /* 0x0F */ op(InstructionTypes::SLO, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x10 */ None,
+    /* 0x11 */ None,
+    /* 0x12 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x13 */ op(InstructionTypes::SLO, AddressingMode::INDIRECT_Y, 8, false),
+    /* 0x14 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x15 */ None,
+    /* 0x16 */ None,
+    /* 0x17 */ op(InstructionTypes::SLO, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x18 */ None,
+    /* 0x19 */ None,
+    /* 0x1A */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 2, false),
+    /* 0x1B */ op(InstructionTypes::SLO, AddressingMode::ABSOLUTE_Y, 7, false),
+    /* 0x1C */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x1D */ None,
+    /* 0x1E */ None,
+    /* 0x1F */ op(InstructionTypes::SLO, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x20 */ None,
+    /* 0x21 */ None,
+    /* 0x22 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x23 */ op(InstructionTypes::RLA, AddressingMode::INDIRECT_X, 8, false),
+    /* 0x24 */ None,
+    /* 0x25 */ None,
+    /* 0x26 */ None,
+    /* 0x27 */ op(InstructionTypes::RLA, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x28 */ None,
+    /* 0x29 */ None,
+    /* 0x2A */ None,
+    /* 0x2B */ op(InstructionTypes::ANC, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x2C */ None,
+    /* 0x2D */ None,
+    /* 0x2E */ None,
+    /* 0x2F */ op(InstructionTypes::RLA, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x30 */ None,
+    /* 0x31 */ None,
+    /* 0x32 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x33 */ op(InstructionTypes::RLA, AddressingMode::INDIRECT_Y, 8, false),
+    /* 0x34 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x35 */ None,
+    /* 0x36 */ None,
+    /* 0x37 */ op(InstructionTypes::RLA, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x38 */ None,
+    /* 0x39 */ None,
+    /* 0x3A */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 2, false),
+    /* 0x3B */ op(InstructionTypes::RLA, AddressingMode::ABSOLUTE_Y, 7, false),
+    /* 0x3C */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x3D */ None,
+    /* 0x3E */ None,
+    /* 0x3F */ op(InstructionTypes::RLA, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x40 */ None,
+    /* 0x41 */ None,
+    /* 0x42 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x43 */ op(InstructionTypes::SRE, AddressingMode::INDIRECT_X, 8, false),
+    /* 0x44 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x45 */ None,
+    /* 0x46 */ None,
+    /* 0x47 */ op(InstructionTypes::SRE, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x48 */ None,
+    /* 0x49 */ None,
+    /* 0x4A */ None,
+    /* 0x4B */ op(InstructionTypes::ALR, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x4C */ None,
+    /* 0x4D */ None,
+    /* 0x4E */ None,
+    /* 0x4F */ op(InstructionTypes::SRE, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x50 */ None,
+    /* 0x51 */ None,
+    /* 0x52 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x53 */ op(InstructionTypes::SRE, AddressingMode::INDIRECT_Y, 8, false),
+    /* 0x54 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x55 */ None,
+    /* 0x56 */ None,
+    /* 0x57 */ op(InstructionTypes::SRE, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x58 */ None,
+    /* 0x59 */ None,
+    /* 0x5A */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 2, false),
+    /* 0x5B */ op(InstructionTypes::SRE, AddressingMode::ABSOLUTE_Y, 7, false),
+    /* 0x5C */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x5D */ None,
+    /* 0x5E */ None,
+    /* 0x5F */ op(InstructionTypes::SRE, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x60 */ None,
+    /* 0x61 */ None,
+    /* 0x62 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x63 */ op(InstructionTypes::RRA, AddressingMode::INDIRECT_X, 8, false),
+    /* 0x64 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x65 */ None,
+    /* 0x66 */ None,
+    /* 0x67 */ op(InstructionTypes::RRA, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x68 */ None,
+    /* 0x69 */ None,
+    /* 0x6A */ None,
+    /* 0x6B */ op(InstructionTypes::ARR, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x6C */ None,
+    /* 0x6D */ None,
+    /* 0x6E */ None,
+    /* 0x6F */ op(InstructionTypes::RRA, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x70 */ None,
+    /* 0x71 */ None,
+    /* 0x72 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x73 */ op(InstructionTypes::RRA, AddressingMode::INDIRECT_Y, 8, false),
+    /* 0x74 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x75 */ None,
+    /* 0x76 */ None,
+    /* 0x77 */ op(InstructionTypes::RRA, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0x78 */ None,
+    /* 0x79 */ None,
+    /* 0x7A */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 2, false),
+    /* 0x7B */ op(InstructionTypes::RRA, AddressingMode::ABSOLUTE_Y, 7, false),
+    /* 0x7C */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x7D */ None,
+    /* 0x7E */ None,
+    /* 0x7F */ op(InstructionTypes::RRA, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0x80 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x81 */ None,
+    /* 0x82 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x83 */ op(InstructionTypes::SAX, AddressingMode::INDIRECT_X, 6, false),
+    /* 0x84 */ None,
+    /* 0x85 */ None,
+    /* 0x86 */ None,
+    /* 0x87 */ op(InstructionTypes::SAX, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x88 */ None,
+    /* 0x89 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x8A */ None,
+    /* 0x8B */ None,
+    /* 0x8C */ None,
+    /* 0x8D */ None,
+    /* 0x8E */ None,
+    /* 0x8F */ op(InstructionTypes::SAX, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x90 */ None,
+    /* 0x91 */ None,
+    /* 0x92 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0x93 */ None,
+    /* 0x94 */ None,
+    /* 0x95 */ None,
+    /* 0x96 */ None,
+    /* 0x97 */ op(InstructionTypes::SAX, AddressingMode::ZERO_PAGE_Y, 4, false),
+    /* 0x98 */ None,
+    /* 0x99 */ None,
+    /* 0x9A */ None,
+    /* 0x9B */ None,
+    /* 0x9C */ None,
+    /* 0x9D */ None,
+    /* 0x9E */ None,
+    /* 0x9F */ None,
+    /* 0xA0 */ None,
+    /* 0xA1 */ None,
+    /* 0xA2 */ None,
+    /* 0xA3 */ op(InstructionTypes::LAX, AddressingMode::INDIRECT_X, 6, false),
+    /* 0xA4 */ None,
+    /* 0xA5 */ None,
+    /* 0xA6 */ None,
+    /* 0xA7 */ op(InstructionTypes::LAX, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0xA8 */ None,
+    /* 0xA9 */ None,
+    /* 0xAA */ None,
+    /* 0xAB */ None,
+    /* 0xAC */ None,
+    /* 0xAD */ None,
+    /* 0xAE */ None,
+    /* 0xAF */ op(InstructionTypes::LAX, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xB0 */ None,
+    /* 0xB1 */ None,
+    /* 0xB2 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0xB3 */ op(InstructionTypes::LAX, AddressingMode::INDIRECT_Y, 5, true),
+    /* 0xB4 */ None,
+    /* 0xB5 */ None,
+    /* 0xB6 */ None,
+    /* 0xB7 */ op(InstructionTypes::LAX, AddressingMode::ZERO_PAGE_Y, 4, false),
+    /* 0xB8 */ None,
+    /* 0xB9 */ None,
+    /* 0xBA */ None,
+    /* 0xBB */ None,
+    /* 0xBC */ None,
+    /* 0xBD */ None,
+    /* 0xBE */ None,
+    /* 0xBF */ op(InstructionTypes::LAX, AddressingMode::ABSOLUTE_Y, 4, true),
+    /* 0xC0 */ None,
+    /* 0xC1 */ None,
+    /* 0xC2 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xC3 */ op(InstructionTypes::DCP, AddressingMode::INDIRECT_X, 8, false),
+    /* 0xC4 */ None,
+    /* 0xC5 */ None,
+    /* 0xC6 */ None,
+    /* 0xC7 */ op(InstructionTypes::DCP, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xC8 */ None,
+    /* 0xC9 */ None,
+    /* 0xCA */ None,
+    /* 0xCB */ op(InstructionTypes::SBX, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xCC */ None,
+    /* 0xCD */ None,
+    /* 0xCE */ None,
+    /* 0xCF */ op(InstructionTypes::DCP, AddressingMode::ABSOLUTE, 6, false),
+    /* 0xD0 */ None,
+    /* 0xD1 */ None,
+    /* 0xD2 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0xD3 */ op(InstructionTypes::DCP, AddressingMode::INDIRECT_Y, 8, false),
+    /* 0xD4 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xD5 */ None,
+    /* 0xD6 */ None,
+    /* 0xD7 */ op(InstructionTypes::DCP, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0xD8 */ None,
+    /* 0xD9 */ None,
+    /* 0xDA */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 2, false),
+    /* 0xDB */ op(InstructionTypes::DCP, AddressingMode::ABSOLUTE_Y, 7, false),
+    /* 0xDC */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0xDD */ None,
+    /* 0xDE */ None,
+    /* 0xDF */ op(InstructionTypes::DCP, AddressingMode::ABSOLUTE_X, 7, false),
+    /* 0xE0 */ None,
+    /* 0xE1 */ None,
+    /* 0xE2 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xE3 */ op(InstructionTypes::ISC, AddressingMode::INDIRECT_X, 8, false),
+    /* 0xE4 */ None,
+    /* 0xE5 */ None,
+    /* 0xE6 */ None,
+    /* 0xE7 */ op(InstructionTypes::ISC, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xE8 */ None,
+    /* 0xE9 */ None,
+    /* 0xEA */ None,
+    /* 0xEB */ op(InstructionTypes::SBC, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xEC */ None,
+    /* 0xED */ None,
+    /* 0xEE */ None,
+    /* 0xEF */ op(InstructionTypes::ISC, AddressingMode::ABSOLUTE, 6, false),
+    /* 0xF0 */ None,
+    /* 0xF1 */ None,
+    /* 0xF2 */ op(InstructionTypes::JAM, AddressingMode::IMPLIED, 2, false),
+    /* 0xF3 */ op(InstructionTypes::ISC, AddressingMode::INDIRECT_Y, 8, false),
+    /* 0xF4 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xF5 */ None,
+    /* 0xF6 */ None,
+    /* 0xF7 */ op(InstructionTypes::ISC, AddressingMode::ZERO_PAGE_X, 6, false),
+    /* 0xF8 */ None,
+    /* 0xF9 */ None,
+    /* 0xFA */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 2, false),
+    /* 0xFB */ op(InstructionTypes::ISC, AddressingMode::ABSOLUTE_Y, 7, false),
+    /* 0xFC */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0xFD */ None,
+    /* 0xFE */ None,
+    /* 0xFF */ op(InstructionTypes::ISC, AddressingMode::ABSOLUTE_X, 7, false),
+];
+
+//Indexed by opcode, the WDC 65C02's new instructions plus the documented opcodes whose timing or
+//behaviour it changed. Every opcode the 65C02 does not define is a NOP of a fixed size and length.
+pub static WDC_65C02_OPCODE_TABLE: [Option<OpcodeInfo>; 256] = [
+    /* 0x00 */ None,
+    /* 0x01 */ None,
+    /* 0x02 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x03 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x04 */ op(InstructionTypes::TSB, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x05 */ None,
+    /* 0x06 */ None,
+    /* 0x07 */ op(InstructionTypes::RMB(0), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x08 */ None,
+    /* 0x09 */ None,
+    /* 0x0A */ None,
+    /* 0x0B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x0C */ op(InstructionTypes::TSB, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x0D */ None,
+    /* 0x0E */ None,
+    /* 0x0F */ op(InstructionTypes::BBR(0), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x10 */ None,
+    /* 0x11 */ None,
+    /* 0x12 */ op(InstructionTypes::ORA, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0x13 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x14 */ op(InstructionTypes::TRB, AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x15 */ None,
+    /* 0x16 */ None,
+    /* 0x17 */ op(InstructionTypes::RMB(1), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x18 */ None,
+    /* 0x19 */ None,
+    /* 0x1A */ op(InstructionTypes::INC, AddressingMode::ACCUMULATOR, 2, false),
+    /* 0x1B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x1C */ op(InstructionTypes::TRB, AddressingMode::ABSOLUTE, 6, false),
+    /* 0x1D */ None,
+    /* 0x1E */ op(InstructionTypes::ASL, AddressingMode::ABSOLUTE_X, 6, true),
+    /* 0x1F */ op(InstructionTypes::BBR(1), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x20 */ None,
+    /* 0x21 */ None,
+    /* 0x22 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x23 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x24 */ None,
+    /* 0x25 */ None,
+    /* 0x26 */ None,
+    /* 0x27 */ op(InstructionTypes::RMB(2), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x28 */ None,
+    /* 0x29 */ None,
+    /* 0x2A */ None,
+    /* 0x2B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x2C */ None,
+    /* 0x2D */ None,
+    /* 0x2E */ None,
+    /* 0x2F */ op(InstructionTypes::BBR(2), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x30 */ None,
+    /* 0x31 */ None,
+    /* 0x32 */ op(InstructionTypes::AND, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0x33 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x34 */ op(InstructionTypes::BIT, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x35 */ None,
+    /* 0x36 */ None,
+    /* 0x37 */ op(InstructionTypes::RMB(3), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x38 */ None,
+    /* 0x39 */ None,
+    /* 0x3A */ op(InstructionTypes::DEC, AddressingMode::ACCUMULATOR, 2, false),
+    /* 0x3B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x3C */ op(InstructionTypes::BIT, AddressingMode::ABSOLUTE_X, 4, true),
+    /* 0x3D */ None,
+    /* 0x3E */ op(InstructionTypes::ROL, AddressingMode::ABSOLUTE_X, 6, true),
+    /* 0x3F */ op(InstructionTypes::BBR(3), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x40 */ None,
+    /* 0x41 */ None,
+    /* 0x42 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x43 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x44 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x45 */ None,
+    /* 0x46 */ None,
+    /* 0x47 */ op(InstructionTypes::RMB(4), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x48 */ None,
+    /* 0x49 */ None,
+    /* 0x4A */ None,
+    /* 0x4B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x4C */ None,
+    /* 0x4D */ None,
+    /* 0x4E */ None,
+    /* 0x4F */ op(InstructionTypes::BBR(4), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x50 */ None,
+    /* 0x51 */ None,
+    /* 0x52 */ op(InstructionTypes::EOR, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0x53 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x54 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x55 */ None,
+    /* 0x56 */ None,
+    /* 0x57 */ op(InstructionTypes::RMB(5), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x58 */ None,
+    /* 0x59 */ None,
+    /* 0x5A */ op(InstructionTypes::PHY, AddressingMode::IMPLIED, 3, false),
+    /* 0x5B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x5C */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE, 8, false),
+    /* 0x5D */ None,
+    /* 0x5E */ op(InstructionTypes::LSR, AddressingMode::ABSOLUTE_X, 6, true),
+    /* 0x5F */ op(InstructionTypes::BBR(5), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x60 */ None,
+    /* 0x61 */ None,
+    /* 0x62 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x63 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x64 */ op(InstructionTypes::STZ, AddressingMode::ZERO_PAGE, 3, false),
+    /* 0x65 */ None,
+    /* 0x66 */ None,
+    /* 0x67 */ op(InstructionTypes::RMB(6), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x68 */ None,
+    /* 0x69 */ None,
+    /* 0x6A */ None,
+    /* 0x6B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x6C */ op(InstructionTypes::JMP, AddressingMode::INDIRECT, 6, false),
+    /* 0x6D */ None,
+    /* 0x6E */ None,
+    /* 0x6F */ op(InstructionTypes::BBR(6), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x70 */ None,
+    /* 0x71 */ None,
+    /* 0x72 */ op(InstructionTypes::ADC, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0x73 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x74 */ op(InstructionTypes::STZ, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0x75 */ None,
+    /* 0x76 */ None,
+    /* 0x77 */ op(InstructionTypes::RMB(7), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x78 */ None,
+    /* 0x79 */ None,
+    /* 0x7A */ op(InstructionTypes::PLY, AddressingMode::IMPLIED, 4, false),
+    /* 0x7B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x7C */ op(InstructionTypes::JMP, AddressingMode::ABSOLUTE_INDIRECT_X, 6, false),
+    /* 0x7D */ None,
+    /* 0x7E */ op(InstructionTypes::ROR, AddressingMode::ABSOLUTE_X, 6, true),
+    /* 0x7F */ op(InstructionTypes::BBR(7), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x80 */ op(InstructionTypes::BRA, AddressingMode::RELATIVE, 2, true),
+    /* 0x81 */ None,
+    /* 0x82 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x83 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x84 */ None,
+    /* 0x85 */ None,
+    /* 0x86 */ None,
+    /* 0x87 */ op(InstructionTypes::SMB(0), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x88 */ None,
+    /* 0x89 */ op(InstructionTypes::BIT, AddressingMode::IMMEDIATE, 2, false),
+    /* 0x8A */ None,
+    /* 0x8B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x8C */ None,
+    /* 0x8D */ None,
+    /* 0x8E */ None,
+    /* 0x8F */ op(InstructionTypes::BBS(0), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0x90 */ None,
+    /* 0x91 */ None,
+    /* 0x92 */ op(InstructionTypes::STA, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0x93 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x94 */ None,
+    /* 0x95 */ None,
+    /* 0x96 */ None,
+    /* 0x97 */ op(InstructionTypes::SMB(1), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0x98 */ None,
+    /* 0x99 */ None,
+    /* 0x9A */ None,
+    /* 0x9B */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0x9C */ op(InstructionTypes::STZ, AddressingMode::ABSOLUTE, 4, false),
+    /* 0x9D */ None,
+    /* 0x9E */ op(InstructionTypes::STZ, AddressingMode::ABSOLUTE_X, 5, false),
+    /* 0x9F */ op(InstructionTypes::BBS(1), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0xA0 */ None,
+    /* 0xA1 */ None,
+    /* 0xA2 */ None,
+    /* 0xA3 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xA4 */ None,
+    /* 0xA5 */ None,
+    /* 0xA6 */ None,
+    /* 0xA7 */ op(InstructionTypes::SMB(2), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xA8 */ None,
+    /* 0xA9 */ None,
+    /* 0xAA */ None,
+    /* 0xAB */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xAC */ None,
+    /* 0xAD */ None,
+    /* 0xAE */ None,
+    /* 0xAF */ op(InstructionTypes::BBS(2), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0xB0 */ None,
+    /* 0xB1 */ None,
+    /* 0xB2 */ op(InstructionTypes::LDA, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0xB3 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xB4 */ None,
+    /* 0xB5 */ None,
+    /* 0xB6 */ None,
+    /* 0xB7 */ op(InstructionTypes::SMB(3), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xB8 */ None,
+    /* 0xB9 */ None,
+    /* 0xBA */ None,
+    /* 0xBB */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xBC */ None,
+    /* 0xBD */ None,
+    /* 0xBE */ None,
+    /* 0xBF */ op(InstructionTypes::BBS(3), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0xC0 */ None,
+    /* 0xC1 */ None,
+    /* 0xC2 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xC3 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xC4 */ None,
+    /* 0xC5 */ None,
+    /* 0xC6 */ None,
+    /* 0xC7 */ op(InstructionTypes::SMB(4), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xC8 */ None,
+    /* 0xC9 */ None,
+    /* 0xCA */ None,
+    /* 0xCB */ op(InstructionTypes::WAI, AddressingMode::IMPLIED, 3, false),
+    /* 0xCC */ None,
+    /* 0xCD */ None,
+    /* 0xCE */ None,
+    /* 0xCF */ op(InstructionTypes::BBS(4), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0xD0 */ None,
+    /* 0xD1 */ None,
+    /* 0xD2 */ op(InstructionTypes::CMP, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0xD3 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xD4 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xD5 */ None,
+    /* 0xD6 */ None,
+    /* 0xD7 */ op(InstructionTypes::SMB(5), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xD8 */ None,
+    /* 0xD9 */ None,
+    /* 0xDA */ op(InstructionTypes::PHX, AddressingMode::IMPLIED, 3, false),
+    /* 0xDB */ op(InstructionTypes::STP, AddressingMode::IMPLIED, 3, false),
+    /* 0xDC */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xDD */ None,
+    /* 0xDE */ None,
+    /* 0xDF */ op(InstructionTypes::BBS(5), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0xE0 */ None,
+    /* 0xE1 */ None,
+    /* 0xE2 */ op(InstructionTypes::NOP, AddressingMode::IMMEDIATE, 2, false),
+    /* 0xE3 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xE4 */ None,
+    /* 0xE5 */ None,
+    /* 0xE6 */ None,
+    /* 0xE7 */ op(InstructionTypes::SMB(6), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xE8 */ None,
+    /* 0xE9 */ None,
+    /* 0xEA */ None,
+    /* 0xEB */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xEC */ None,
+    /* 0xED */ None,
+    /* 0xEE */ None,
+    /* 0xEF */ op(InstructionTypes::BBS(6), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+    /* 0xF0 */ None,
+    /* 0xF1 */ None,
+    /* 0xF2 */ op(InstructionTypes::SBC, AddressingMode::ZERO_PAGE_INDIRECT, 5, false),
+    /* 0xF3 */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xF4 */ op(InstructionTypes::NOP, AddressingMode::ZERO_PAGE_X, 4, false),
+    /* 0xF5 */ None,
+    /* 0xF6 */ None,
+    /* 0xF7 */ op(InstructionTypes::SMB(7), AddressingMode::ZERO_PAGE, 5, false),
+    /* 0xF8 */ None,
+    /* 0xF9 */ None,
+    /* 0xFA */ op(InstructionTypes::PLX, AddressingMode::IMPLIED, 4, false),
+    /* 0xFB */ op(InstructionTypes::NOP, AddressingMode::IMPLIED, 1, false),
+    /* 0xFC */ op(InstructionTypes::NOP, AddressingMode::ABSOLUTE, 4, false),
+    /* 0xFD */ None,
+    /* 0xFE */ None,
+    /* 0xFF */ op(InstructionTypes::BBS(7), AddressingMode::ZERO_PAGE_RELATIVE, 5, true),
+];
+
+pub fn lookup(opcode: u8) -> Option<&'static OpcodeInfo> {
+    OPCODE_TABLE[opcode as usize].as_ref()
+}
+
+pub fn lookup_undocumented(opcode: u8) -> Option<&'static OpcodeInfo> {
+    UNDOCUMENTED_OPCODE_TABLE[opcode as usize].as_ref()
+}
+
+//Look up an opcode as the 65C02 decodes it, falling back to the documented NMOS instruction set
+pub fn lookup_65c02(opcode: u8) -> Option<&'static OpcodeInfo> {
+    WDC_65C02_OPCODE_TABLE[opcode as usize].as_ref().or_else(|| lookup(opcode))
+}
+
+impl OpcodeInfo {
+    //Render the instruction in standard assembler syntax. pc is the address of the opcode itself.
+    pub fn disassemble(&self, data: &[u8], pc: u16) -> String {
+        let byte = || data[0];
+        let word = || (data[0] as u16) | ((data[1] as u16) << 8);
+        let operand = match self.mode {
+            AddressingMode::IMPLIED => String::new(),
+            AddressingMode::ACCUMULATOR => String::from(" A"),
+            AddressingMode::IMMEDIATE => format!(" #${:02X}", byte()),
+            AddressingMode::ZERO_PAGE => format!(" ${:02X}", byte()),
+            AddressingMode::ZERO_PAGE_X => format!(" ${:02X},X", byte()),
+            AddressingMode::ZERO_PAGE_Y => format!(" ${:02X},Y", byte()),
+            AddressingMode::ABSOLUTE => format!(" ${:04X}", word()),
+            AddressingMode::ABSOLUTE_X => format!(" ${:04X},X", word()),
+            AddressingMode::ABSOLUTE_Y => format!(" ${:04X},Y", word()),
+            AddressingMode::INDIRECT => format!(" (${:04X})", word()),
+            AddressingMode::INDIRECT_X => format!(" (${:02X},X)", byte()),
+            AddressingMode::INDIRECT_Y => format!(" (${:02X}),Y", byte()),
+            AddressingMode::RELATIVE => {
+                let target = pc.wrapping_add(self.num_bytes).wrapping_add(byte() as i8 as u16);
+                format!(" ${:04X}", target)
+            },
+            AddressingMode::ZERO_PAGE_INDIRECT => format!(" (${:02X})", byte()),
+            AddressingMode::ABSOLUTE_INDIRECT_X => format!(" (${:04X},X)", word()),
+            AddressingMode::ZERO_PAGE_RELATIVE => {
+                let target = pc.wrapping_add(self.num_bytes).wrapping_add(data[1] as i8 as u16);
+                format!(" ${:02X},${:04X}", byte(), target)
+            }
+        };
+        format!("{}{}", self.inst.mnemonic(), operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Mnemonic, bytes, base cycles and page cross penalty of a documented opcode
+    fn entry(opcode: u8) -> (String, u16, u16, bool) {
+        let info = lookup(opcode).unwrap();
+        (info.inst.mnemonic(), info.num_bytes, info.base_cycles, info.page_cross_penalty)
+    }
+
+    #[test]
+    fn documented_entries() {
+        assert_eq!(entry(0x00), (String::from("BRK"), 1, 7, false));
+        assert_eq!(entry(0x20), (String::from("JSR"), 3, 6, false));
+        assert_eq!(entry(0x4C), (String::from("JMP"), 3, 3, false));
+        assert_eq!(entry(0x6C), (String::from("JMP"), 3, 5, false));
+        assert_eq!(entry(0xA9), (String::from("LDA"), 2, 2, false));
+        assert_eq!(entry(0xB1), (String::from("LDA"), 2, 5, true));
+        assert_eq!(entry(0xBD), (String::from("LDA"), 3, 4, true));
+        assert_eq!(entry(0x9D), (String::from("STA"), 3, 5, false));
+        assert_eq!(entry(0x91), (String::from("STA"), 2, 6, false));
+        assert_eq!(entry(0xFE), (String::from("INC"), 3, 7, false));
+        assert_eq!(entry(0x0A), (String::from("ASL"), 1, 2, false));
+        assert_eq!(entry(0xD0), (String::from("BNE"), 2, 2, true));
+        assert_eq!(entry(0x40), (String::from("RTI"), 1, 6, false));
+        assert_eq!(entry(0xEA), (String::from("NOP"), 1, 2, false));
+    }
+
+    #[test]
+    fn unused_slots() {
+        assert_eq!(OPCODE_TABLE.iter().filter(|info| info.is_some()).count(), 151);
+        for opcode in [0x02, 0x03, 0x04, 0x0B, 0x1A, 0x80, 0x9C, 0xA7, 0xEB, 0xFF] {
+            assert!(lookup(opcode).is_none(), "opcode {:#04x}", opcode);
+        }
+        //The undocumented table only fills in the gaps
+        for opcode in 0..=255u8 {
+            assert!(lookup(opcode).is_none() || lookup_undocumented(opcode).is_none(), "opcode {:#04x}", opcode);
+        }
+    }
+
+    #[test]
+    fn disassembly() {
+        assert_eq!(lookup(0xBD).unwrap().disassemble(&[0x34, 0x12], 0x0400), "LDA $1234,X");
+        assert_eq!(lookup(0xB1).unwrap().disassemble(&[0x20], 0x0400), "LDA ($20),Y");
+        assert_eq!(lookup(0xD0).unwrap().disassemble(&[0xFE], 0x0400), "BNE $0400");
+    }
+}
@@ -0,0 +1,112 @@
+//Bit positions of each flag in the processor status byte, as pushed to and pulled from the stack
+pub const FLAG_CARRY: u8 = 0x01;
+pub const FLAG_ZERO: u8 = 0x02;
+pub const FLAG_INTERRUPT_DISABLE: u8 = 0x04;
+pub const FLAG_DECIMAL: u8 = 0x08;
+pub const FLAG_BREAK: u8 = 0x10;
+pub const FLAG_UNUSED: u8 = 0x20;
+pub const FLAG_OVERFLOW: u8 = 0x40;
+pub const FLAG_NEGATIVE: u8 = 0x80;
+
+//The processor status register. Only six flags physically exist: the break and unused bits are
+//produced on the fly when the status is pushed, so they are not stored here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusFlags {
+    pub carry: bool,                //C
+    pub zero: bool,                 //Z
+    pub interrupt_disable: bool,    //I
+    pub decimal: bool,              //D
+    pub overflow: bool,             //V
+    pub negative: bool              //N
+}
+
+impl Default for StatusFlags {
+    fn default() -> Self {
+        StatusFlags::new()
+    }
+}
+
+impl StatusFlags {
+    pub fn new() -> StatusFlags {
+        StatusFlags {
+            carry: false,
+            zero: true,
+            interrupt_disable: true,
+            decimal: false,
+            overflow: false,
+            negative: false
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> StatusFlags {
+        let mut flags = StatusFlags::new();
+        flags.set_from_byte(byte);
+        flags
+    }
+
+    //Load the flags from a byte pulled off the stack by PLP or RTI. The break and unused bits have
+    //nowhere to go, so they are ignored.
+    pub fn set_from_byte(&mut self, byte: u8) {
+        self.carry = (byte & FLAG_CARRY) != 0;
+        self.zero = (byte & FLAG_ZERO) != 0;
+        self.interrupt_disable = (byte & FLAG_INTERRUPT_DISABLE) != 0;
+        self.decimal = (byte & FLAG_DECIMAL) != 0;
+        self.overflow = (byte & FLAG_OVERFLOW) != 0;
+        self.negative = (byte & FLAG_NEGATIVE) != 0;
+    }
+
+    //Pack the flags into a byte. The unused bit always reads back as 1, and the break bit is only
+    //set when the byte is pushed by BRK or PHP rather than by a hardware interrupt.
+    pub fn to_byte(&self, break_flag: bool) -> u8 {
+        let mut byte = FLAG_UNUSED;
+        if self.carry { byte |= FLAG_CARRY; }
+        if self.zero { byte |= FLAG_ZERO; }
+        if self.interrupt_disable { byte |= FLAG_INTERRUPT_DISABLE; }
+        if self.decimal { byte |= FLAG_DECIMAL; }
+        if break_flag { byte |= FLAG_BREAK; }
+        if self.overflow { byte |= FLAG_OVERFLOW; }
+        if self.negative { byte |= FLAG_NEGATIVE; }
+        byte
+    }
+
+    //Instructions only touch the flags the datasheet lists for them, so these updaters each cover
+    //one group and are combined as needed (e.g. LDA uses N/Z only, CMP adds carry, ADC all three)
+
+    //Z is set when the value is zero and N mirrors its top bit
+    pub fn update_zero_negative(&mut self, value: u8) {
+        self.zero = value == 0;
+        self.negative = (value & 0x80) != 0;
+    }
+
+    //C is set when the unwrapped result carried out of bit 7
+    pub fn update_carry(&mut self, wide_result: u16) {
+        self.carry = (wide_result & 0xFF00) != 0;
+    }
+
+    //V is set when both inputs of an addition share a sign that differs from the result's sign
+    pub fn update_overflow(&mut self, lhs: u8, rhs: u8, result: u8) {
+        self.overflow = (!(lhs ^ rhs) & (lhs ^ result) & 0x80) != 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn break_and_unused_bits() {
+        let flags = StatusFlags::from_byte(FLAG_CARRY | FLAG_NEGATIVE);
+        //Bit 5 is always pushed set, and B only when BRK or PHP pushes
+        assert_eq!(flags.to_byte(true), FLAG_CARRY | FLAG_NEGATIVE | FLAG_UNUSED | FLAG_BREAK);
+        assert_eq!(flags.to_byte(false), FLAG_CARRY | FLAG_NEGATIVE | FLAG_UNUSED);
+    }
+
+    #[test]
+    fn pulled_byte_ignores_break_and_unused() {
+        let mut flags = StatusFlags::new();
+        flags.set_from_byte(0xFF);
+        assert_eq!(flags.to_byte(false), !FLAG_BREAK);
+        flags.set_from_byte(FLAG_BREAK | FLAG_UNUSED);
+        assert_eq!(flags, StatusFlags { carry: false, zero: false, interrupt_disable: false, decimal: false, overflow: false, negative: false });
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+//One executed instruction along with the register state before it ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceLine {
+    pub pc: u16,
+    pub bytes: Vec<u8>,             //Opcode followed by its operand bytes
+    pub disassembly: String,
+    pub undocumented: bool,         //Marked with a '*' before the mnemonic, as nestest.log does
+    pub accum: u8,
+    pub index_x: u8,
+    pub index_y: u8,
+    pub status: u8,
+    pub sp: u8,
+    pub cycles: u64                 //Total cycles executed before the instruction
+}
+
+//Matches the columns of nestest.log, minus the PPU position, e.g.
+//C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(
+            f,
+            "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc, bytes.join(" "), if self.undocumented { '*' } else { ' ' }, self.disassembly,
+            self.accum, self.index_x, self.index_y, self.status, self.sp, self.cycles
+        )
+    }
+}
+
+//Writes a trace line for every instruction the CPU executes, for diffing against reference emulators
+pub struct TraceWriter {
+    out: Box<dyn Write>
+}
+
+impl TraceWriter {
+    pub fn new(out: Box<dyn Write>) -> TraceWriter {
+        TraceWriter { out }
+    }
+
+    pub fn create(filename: &str) -> io::Result<TraceWriter> {
+        Ok(TraceWriter::new(Box::new(BufWriter::new(File::create(filename)?))))
+    }
+
+    pub fn write_line(&mut self, line: &TraceLine) -> io::Result<()> {
+        writeln!(self.out, "{}", line)
+    }
+}
+
+impl Drop for TraceWriter {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(pc: u16, bytes: &[u8], disassembly: &str, undocumented: bool) -> TraceLine {
+        TraceLine {
+            pc,
+            bytes: bytes.to_vec(),
+            disassembly: String::from(disassembly),
+            undocumented,
+            accum: 0xAA,
+            index_x: 0x01,
+            index_y: 0x0F,
+            status: 0x24,
+            sp: 0xFD,
+            cycles: 7
+        }
+    }
+
+    #[test]
+    fn nestest_columns() {
+        let text = line(0xC000, &[0x4C, 0xF5, 0xC5], "JMP $C5F5", false).to_string();
+        assert_eq!(text, "C000  4C F5 C5  JMP $C5F5                       A:AA X:01 Y:0F P:24 SP:FD CYC:7");
+        assert_eq!(text.find("JMP"), Some(16));
+        assert_eq!(text.find("A:"), Some(48));
+
+        //Short instructions pad the bytes column, and undocumented ones take the column before the mnemonic
+        let text = line(0xC6BD, &[0x04, 0xA9], "NOP $A9", true).to_string();
+        assert_eq!(text, "C6BD  04 A9    *NOP $A9                         A:AA X:01 Y:0F P:24 SP:FD CYC:7");
+        assert_eq!(text.find('*'), Some(15));
+        assert_eq!(text.find("A:"), Some(48));
+
+        let text = line(0x0001, &[0xEA], "NOP", false).to_string();
+        assert_eq!(&text[..20], "0001  EA        NOP ");
+        assert_eq!(text.find("A:"), Some(48));
+    }
+}